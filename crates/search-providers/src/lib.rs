@@ -1,2 +1,5 @@
 mod rutracker;
 pub use rutracker::*;
+
+mod torznab;
+pub use torznab::*;
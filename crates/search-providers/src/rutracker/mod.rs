@@ -1,6 +1,9 @@
 mod parser;
 pub use parser::*;
 
+mod rate_limiter;
+pub use rate_limiter::RuTrackerRateLimitOptions;
+
 mod rutracker;
 pub use rutracker::*;
 
@@ -1,47 +1,15 @@
 use crate::{DownloadId, TopicId};
 use scraper::error::SelectorErrorKind;
-use scraper::{Html, Selector};
-
-const AUDIO_FORMAT_PRIORITY: [&str; 4] = ["FLAC", "MP3", "ALAC", "AAC"];
-const AUDIO_BITRATE_PRIORITY: [&str; 3] = ["lossless", "320 kbps", "256 kbps"];
+use scraper::{ElementRef, Html, Node, Selector};
 
 const CAPTCHA_IS_REQUIRED_TEXT: &str = "введите код подтверждения";
 const INCORRECT_PASSWORD_TEXT: &str = "неверный пароль";
 const SUCCESSFUL_LOGIN_TEXT: &str = "log-out-icon";
-
-fn get_search_result_priority(result: &TopicData) -> usize {
-    let format_priority = AUDIO_FORMAT_PRIORITY
-        .iter()
-        .enumerate()
-        .find_map(|(i, format)| {
-            if result.title.contains(format) {
-                Some(i)
-            } else {
-                None
-            }
-        })
-        .unwrap_or(10);
-    let bitrate_priority = AUDIO_BITRATE_PRIORITY
-        .iter()
-        .enumerate()
-        .find_map(|(i, bitrate)| {
-            if result.title.contains(bitrate) {
-                Some(i)
-            } else {
-                None
-            }
-        })
-        .unwrap_or(10);
-    let seeds_priority = match result.seeds_number {
-        x if x == 0 => 10,
-        x if x < 10 => 3,
-        x if x < 20 => 2,
-        x if x < 30 => 1,
-        _ => 0,
-    };
-
-    format_priority * 5 + bitrate_priority * 10 + seeds_priority
-}
+const LOSSLESS_FORUM_TEXT: &str = "loss";
+const RELEASE_YEAR_FIELDS: [&str; 2] = ["год издания", "год выпуска"];
+const AUDIO_CODEC_FIELDS: [&str; 2] = ["аудиокодек", "audio codec"];
+const TRACKLIST_FIELDS: [&str; 2] = ["треклист", "tracklist"];
+const DISC_PREFIXES: [&str; 3] = ["cd", "disc", "диск"];
 
 #[derive(Debug, thiserror::Error)]
 pub enum ParseError {
@@ -49,12 +17,78 @@ pub enum ParseError {
     SelectorError(#[from] SelectorErrorKind<'static>),
 }
 
+/// Moderation status of the topic, shown by the icon next to its title.
+#[derive(Debug, PartialEq, Clone)]
+pub enum TopicStatus {
+    Approved,
+    NotApproved,
+    Checking,
+    NeedsEdit,
+    Doubtful,
+    Duplicate,
+    Consumed,
+    Closed,
+    Temporary,
+    Unknown,
+}
+
+impl TopicStatus {
+    fn from_icon_class(class: &str) -> Self {
+        match class {
+            "tor-approved" => TopicStatus::Approved,
+            "tor-not-approved" => TopicStatus::NotApproved,
+            "tor-checking" => TopicStatus::Checking,
+            "tor-need-edit" => TopicStatus::NeedsEdit,
+            "tor-doubtful" => TopicStatus::Doubtful,
+            "tor-dup" => TopicStatus::Duplicate,
+            "tor-consumed" => TopicStatus::Consumed,
+            "tor-closed" | "tor-closed-cp" => TopicStatus::Closed,
+            "tor-temp" => TopicStatus::Temporary,
+            _ => TopicStatus::Unknown,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct TopicData {
     pub title: String,
     pub topic_id: TopicId,
     pub download_id: DownloadId,
     pub seeds_number: u64,
+    pub leeches_number: u64,
+    /// How many times the torrent has been downloaded.
+    pub downloads_number: u64,
+    /// Size of the torrent in bytes.
+    pub size: u64,
+    /// Unknown if the forum link is of an unexpected format.
+    pub forum_id: Option<u64>,
+    pub forum_name: String,
+    pub author: String,
+    /// Unix timestamp of the moment the torrent was registered.
+    pub added_at: u64,
+    pub status: TopicStatus,
+}
+
+fn get_text(element: ElementRef) -> String {
+    element.text().collect::<String>().trim().to_string()
+}
+
+/// Parses the `f` parameter of the forum link, e.g. `tracker.php?f=1818`.
+fn parse_forum_id(href: &str) -> Option<u64> {
+    let (_, query) = href.split_once('?')?;
+
+    query
+        .split('&')
+        .find_map(|param| param.strip_prefix("f=")?.parse().ok())
+}
+
+fn parse_number(value: &str) -> Option<u64> {
+    value
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect::<String>()
+        .parse()
+        .ok()
 }
 
 pub(crate) fn parse_search_results(raw_html: &str) -> Result<Vec<TopicData>, ParseError> {
@@ -66,23 +100,16 @@ pub(crate) fn parse_search_results(raw_html: &str) -> Result<Vec<TopicData>, Par
     let href_selector = Selector::parse(r#"a[href]"#)?;
     let td_selector = Selector::parse(r#"td"#)?;
     let seeds_selector = Selector::parse(r#"b.seedmed"#)?;
+    let status_selector = Selector::parse(r#"span.tor-icon"#)?;
 
-    let mut results: Vec<_> = table_entries
+    let results: Vec<_> = table_entries
         .skip(1)
         .filter(|el| el.children().filter(|el| el.value().is_element()).count() == 10)
         .filter_map(|el| {
             let columns = el.select(&td_selector).collect::<Vec<_>>();
             let link = columns[3].select(&href_selector).next()?;
-            let category_str = columns[2]
-                .select(&href_selector)
-                .next()?
-                .inner_html()
-                .to_lowercase();
-
-            if !category_str.contains("loss") {
-                return None;
-            }
-
+            let forum_link = columns[2].select(&href_selector).next()?;
+            let forum_name = get_text(forum_link);
             let title = link.inner_html().to_string();
             let topic_id = link
                 .value()
@@ -109,23 +136,252 @@ pub(crate) fn parse_search_results(raw_html: &str) -> Result<Vec<TopicData>, Par
                 .parse::<u64>()
                 .ok()?
                 .into();
+            let forum_id = forum_link.value().attr("href").and_then(parse_forum_id);
+            let status = columns[1]
+                .select(&status_selector)
+                .next()
+                .and_then(|icon| {
+                    icon.value()
+                        .classes()
+                        .find(|class| class.starts_with("tor-") && *class != "tor-icon")
+                })
+                .map(TopicStatus::from_icon_class)
+                .unwrap_or(TopicStatus::Unknown);
 
             Some(TopicData {
                 title,
                 topic_id,
                 download_id,
                 seeds_number,
+                leeches_number: parse_number(&get_text(columns[7])).unwrap_or_default(),
+                downloads_number: parse_number(&get_text(columns[8])).unwrap_or_default(),
+                // Exact values are kept in the sort keys of the columns.
+                size: columns[5]
+                    .value()
+                    .attr("data-ts_text")
+                    .and_then(parse_number)
+                    .unwrap_or_default(),
+                forum_id,
+                forum_name,
+                author: get_text(columns[4]),
+                added_at: columns[9]
+                    .value()
+                    .attr("data-ts_text")
+                    .and_then(parse_number)
+                    .unwrap_or_default(),
+                status,
             })
         })
-        .filter(|r| !r.title.contains("image+.cue"))
         .collect();
 
-    // Sort search results by the search result priority
-    results.sort_by(|a, b| get_search_result_priority(a).cmp(&get_search_result_priority(b)));
-
     Ok(results)
 }
 
+/// Release description from the first post of the topic page.
+#[derive(Debug, PartialEq)]
+pub struct TopicDetails {
+    pub title: String,
+    pub year: Option<u32>,
+    /// Audio codec, e.g. "FLAC" or "MP3".
+    pub format: Option<String>,
+    /// Track titles without the track numbers and durations.
+    pub tracklist: Vec<String>,
+}
+
+/// Splits the post into lines the way it's rendered.
+fn get_lines(post: ElementRef) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+
+    for node in post.descendants() {
+        match node.value() {
+            Node::Text(text) => line.push_str(text),
+            Node::Element(element) if matches!(element.name(), "br" | "div" | "li") => {
+                lines.push(line.trim().to_string());
+                line.clear();
+            }
+            _ => (),
+        }
+    }
+
+    lines.push(line.trim().to_string());
+
+    lines
+}
+
+fn is_field(line: &str, names: &[&str]) -> bool {
+    let line = line.to_lowercase();
+
+    names.iter().any(|name| line.starts_with(name))
+}
+
+fn get_field_value<'a>(lines: &'a [String], names: &[&str]) -> Option<&'a str> {
+    lines
+        .iter()
+        .find(|line| is_field(line, names))
+        .and_then(|line| line.split_once(':'))
+        .map(|(_, value)| value.trim())
+        .filter(|value| !value.is_empty())
+}
+
+/// Strips the number and duration from lines like "01. Title (04:32)".
+fn parse_track(line: &str) -> Option<String> {
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+
+    if digits == 0 || digits > 3 {
+        return None;
+    }
+
+    let title = line[digits..].trim_start_matches(['.', ')', ' ', '-']);
+    let title = match title.rsplit_once('(') {
+        Some((rest, duration))
+            if duration
+                .trim_end_matches(')')
+                .chars()
+                .all(|c| c.is_ascii_digit() || c == ':') =>
+        {
+            rest.trim()
+        }
+        _ => title.trim(),
+    };
+
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+fn parse_tracklist(lines: &[String]) -> Vec<String> {
+    let mut tracklist = vec![];
+
+    for line in lines
+        .iter()
+        .skip_while(|line| !is_field(line, &TRACKLIST_FIELDS))
+        .skip(1)
+    {
+        if line.is_empty() || is_field(line, &DISC_PREFIXES) {
+            continue;
+        }
+
+        match parse_track(line) {
+            Some(track) => tracklist.push(track),
+            None if tracklist.is_empty() => continue,
+            None => break,
+        }
+    }
+
+    tracklist
+}
+
+pub(crate) fn parse_topic_page(raw_html: &str) -> Result<Option<TopicDetails>, ParseError> {
+    let html = Html::parse_document(raw_html);
+
+    let title_selector = Selector::parse(r#"a#topic-title"#)?;
+    let post_selector = Selector::parse(r#"div.post_body"#)?;
+
+    let (Some(title), Some(post)) = (
+        html.select(&title_selector).next(),
+        html.select(&post_selector).next(),
+    ) else {
+        return Ok(None);
+    };
+
+    let lines = get_lines(post);
+
+    Ok(Some(TopicDetails {
+        title: get_text(title),
+        year: get_field_value(&lines, &RELEASE_YEAR_FIELDS)
+            .and_then(|value| value.get(..4))
+            .and_then(|year| year.parse().ok()),
+        format: get_field_value(&lines, &AUDIO_CODEC_FIELDS).map(ToString::to_string),
+        tracklist: parse_tracklist(&lines),
+    }))
+}
+
+/// Music forums have the "lossless" or "lossy" suffix in their names, which
+/// tells them apart from the video and book forums found by the same query.
+pub(crate) fn is_music_forum(forum_name: &str) -> bool {
+    forum_name.to_lowercase().contains(LOSSLESS_FORUM_TEXT)
+}
+
+/// Link to the following page of the search results.
+#[derive(Debug, PartialEq)]
+pub(crate) struct NextPage {
+    pub(crate) search_id: String,
+    pub(crate) start: u64,
+}
+
+/// Finds the page following the one starting at `current_start` among the
+/// page links like `tracker.php?search_id=...&start=50`.
+pub(crate) fn parse_next_page(
+    raw_html: &str,
+    current_start: u64,
+) -> Result<Option<NextPage>, ParseError> {
+    let html = Html::parse_document(raw_html);
+    let page_link_selector = Selector::parse(r#"a.pg[href]"#)?;
+
+    let next_page = html
+        .select(&page_link_selector)
+        .filter_map(|link| {
+            let (_, query) = link.value().attr("href")?.split_once('?')?;
+            let mut search_id = None;
+            let mut start = None;
+
+            for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+                match key {
+                    "search_id" => search_id = Some(value.to_string()),
+                    "start" => start = value.parse::<u64>().ok(),
+                    _ => (),
+                }
+            }
+
+            Some(NextPage {
+                search_id: search_id?,
+                start: start?,
+            })
+        })
+        .filter(|page| page.start > current_start)
+        .min_by_key(|page| page.start);
+
+    Ok(next_page)
+}
+
+/// Captcha of the login form, solved by entering the code shown on the image.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Captcha {
+    pub image_url: String,
+    pub sid: String,
+    /// Name of the form field for the code, which is different every time.
+    pub code_field: String,
+}
+
+pub(crate) fn parse_captcha(raw_html: &str) -> Result<Option<Captcha>, ParseError> {
+    let html = Html::parse_document(raw_html);
+
+    let image_selector = Selector::parse(r#"img[src*="/captcha/"]"#)?;
+    let sid_selector = Selector::parse(r#"input[name="cap_sid"]"#)?;
+    let code_selector = Selector::parse(r#"input[name^="cap_code_"]"#)?;
+
+    let image_url = html
+        .select(&image_selector)
+        .next()
+        .and_then(|image| image.value().attr("src"));
+    let sid = html
+        .select(&sid_selector)
+        .next()
+        .and_then(|input| input.value().attr("value"));
+    let code_field = html
+        .select(&code_selector)
+        .next()
+        .and_then(|input| input.value().attr("name"));
+
+    Ok(match (image_url, sid, code_field) {
+        (Some(image_url), Some(sid), Some(code_field)) => Some(Captcha {
+            image_url: image_url.to_string(),
+            sid: sid.to_string(),
+            code_field: code_field.to_string(),
+        }),
+        _ => None,
+    })
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum AuthError {
     #[error("Captcha verification is required.")]
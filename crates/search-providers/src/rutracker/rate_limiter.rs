@@ -0,0 +1,131 @@
+use rand::Rng;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const DOWNLOAD_QUOTA_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Politeness settings for the tracker requests.
+#[derive(Clone, Debug)]
+pub struct RuTrackerRateLimitOptions {
+    /// Requests are spread evenly, so there are no bursts.
+    pub requests_per_minute: u32,
+    /// Random delay of up to this duration is added to every request.
+    pub max_jitter: Duration,
+    /// How many torrent files can be downloaded in 24 hours, if limited.
+    pub daily_downloads: Option<u32>,
+    /// Longer waits for a request slot aren't slept through, but reported as
+    /// `RateLimited`, so the caller can tell it's waiting.
+    pub max_wait: Duration,
+}
+
+impl Default for RuTrackerRateLimitOptions {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: 20,
+            max_jitter: Duration::from_secs(2),
+            daily_downloads: None,
+            max_wait: Duration::from_secs(10),
+        }
+    }
+}
+
+struct RateLimiterState {
+    /// When the next request is allowed to be sent.
+    next_request_at: Option<Instant>,
+    /// Torrent downloads within the last 24 hours, oldest first.
+    downloads: VecDeque<Instant>,
+}
+
+/// Shared by all requests of the client, so concurrent track requests don't
+/// add up to a burst. Download quota is kept in memory only.
+pub(crate) struct RateLimiter {
+    options: RuTrackerRateLimitOptions,
+    state: Mutex<RateLimiterState>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(options: RuTrackerRateLimitOptions) -> Self {
+        Self {
+            options,
+            state: Mutex::new(RateLimiterState {
+                next_request_at: None,
+                downloads: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Reserves the earliest free slot for a request, returning how long to
+    /// wait for it. Slots further than `max_wait` away aren't reserved.
+    pub(crate) fn reserve_request(&self, now: Instant) -> Result<Duration, Duration> {
+        let interval = Duration::from_secs(60) / self.options.requests_per_minute.max(1);
+        let mut state = self.state.lock().expect("Unable to lock rate limiter");
+
+        let slot = match state.next_request_at {
+            Some(next_request_at) if next_request_at > now => next_request_at,
+            _ => now,
+        };
+
+        if slot - now > self.options.max_wait {
+            return Err(slot - now);
+        }
+
+        state.next_request_at = Some(slot + interval);
+
+        Ok(slot - now)
+    }
+
+    /// Counts a torrent download against the daily quota, or returns how
+    /// long to wait until the quota allows one more.
+    pub(crate) fn reserve_download(&self, now: Instant) -> Result<(), Duration> {
+        let Some(daily_downloads) = self.options.daily_downloads else {
+            return Ok(());
+        };
+        let mut state = self.state.lock().expect("Unable to lock rate limiter");
+
+        while state.downloads.front().is_some_and(|downloaded_at| {
+            now.duration_since(*downloaded_at) >= DOWNLOAD_QUOTA_PERIOD
+        }) {
+            state.downloads.pop_front();
+        }
+
+        if state.downloads.len() >= daily_downloads as usize {
+            let oldest = state.downloads.front().copied().unwrap_or(now);
+
+            return Err(DOWNLOAD_QUOTA_PERIOD - now.duration_since(oldest));
+        }
+
+        state.downloads.push_back(now);
+
+        Ok(())
+    }
+
+    /// Gives back the download reserved at the moment, when it has failed.
+    pub(crate) fn release_download(&self, reserved_at: Instant) {
+        let mut state = self.state.lock().expect("Unable to lock rate limiter");
+
+        if let Some(index) = state
+            .downloads
+            .iter()
+            .rposition(|downloaded_at| *downloaded_at == reserved_at)
+        {
+            state.downloads.remove(index);
+        }
+    }
+
+    /// Waits for a request slot, or returns how long it would take when
+    /// that's longer than `max_wait`.
+    pub(crate) async fn wait_for_request(&self) -> Result<(), Duration> {
+        let jitter = match self.options.max_jitter {
+            max_jitter if max_jitter.is_zero() => Duration::ZERO,
+            max_jitter => rand::thread_rng().gen_range(Duration::ZERO..max_jitter),
+        };
+        let delay = self.reserve_request(Instant::now())? + jitter;
+
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+
+        Ok(())
+    }
+}
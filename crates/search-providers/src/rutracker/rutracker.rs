@@ -1,12 +1,22 @@
 use crate::rutracker::parser::{
-    parse_and_validate_auth_state, parse_search_results, AuthError, ParseError,
+    is_music_forum, parse_and_validate_auth_state, parse_captcha, parse_next_page,
+    parse_search_results, parse_topic_page, AuthError, Captcha, ParseError, TopicDetails,
 };
+use crate::rutracker::rate_limiter::{RateLimiter, RuTrackerRateLimitOptions};
 use crate::TopicData;
+use reqwest::cookie::{CookieStore, Jar};
 use reqwest::redirect::Policy;
-use reqwest::{Client, StatusCode};
+use reqwest::{Client, Proxy, RequestBuilder, Response, StatusCode};
 use serde::Serialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::warn;
 
-const RU_TRACKER_HOST: &str = "https://rutracker.net";
+const DEFAULT_HOST: &str = "https://rutracker.net";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
 const MAGIC_LOGIN_WORD: &str = "вход";
 
 #[derive(Debug, thiserror::Error)]
@@ -19,85 +29,451 @@ pub enum RuTrackerClientError {
     AuthError(#[from] AuthError),
     #[error("Unexpected response status: {0}")]
     BadStatus(StatusCode),
+    #[error("Daily torrent download quota is exceeded, retry in {0:?}")]
+    DownloadQuotaExceeded(Duration),
+    #[error("Response is not a torrent file")]
+    NotTorrent,
+    #[error("Request rate limit is reached, retry in {0:?}")]
+    RateLimited(Duration),
+}
+
+/// Where the tracker is reached at.
+#[derive(Clone, Debug)]
+pub struct RuTrackerConnectionOptions {
+    /// Mirrors of the tracker, e.g. `https://rutracker.org`, in order of
+    /// preference. When one is unreachable, the next one is used.
+    pub hosts: Vec<String>,
+    pub proxy: Option<Proxy>,
+    pub rate_limit: RuTrackerRateLimitOptions,
+}
+
+impl Default for RuTrackerConnectionOptions {
+    fn default() -> Self {
+        Self {
+            hosts: vec![DEFAULT_HOST.to_string()],
+            proxy: None,
+            rate_limit: RuTrackerRateLimitOptions::default(),
+        }
+    }
+}
+
+fn error_for_server_status(response: Response) -> Result<Response, RuTrackerClientError> {
+    match response.status() {
+        status if status.is_server_error() => Err(RuTrackerClientError::BadStatus(status)),
+        _ => Ok(response),
+    }
+}
+
+/// Whether the request is worth retrying on another mirror.
+fn is_host_unavailable<T>(result: &Result<T, RuTrackerClientError>) -> bool {
+    match result {
+        Err(RuTrackerClientError::ReqwestError(error)) => error.is_connect() || error.is_timeout(),
+        Err(RuTrackerClientError::BadStatus(status)) => status.is_server_error(),
+        _ => false,
+    }
+}
+
+/// How `search_music` queries the tracker.
+#[derive(Clone, Debug)]
+pub struct RuTrackerSearchOptions {
+    /// Forums (the `f` parameter) to search in. When empty, all forums are
+    /// searched and only those with "lossless" or "lossy" in the name are kept.
+    pub forum_ids: Vec<u64>,
+    /// How many pages of results (50 per page) to fetch at most.
+    pub max_pages: u32,
+}
+
+impl Default for RuTrackerSearchOptions {
+    fn default() -> Self {
+        Self {
+            forum_ids: vec![],
+            max_pages: 1,
+        }
+    }
 }
 
 pub struct RuTrackerClient {
     client: Client,
+    hosts: Vec<String>,
+    /// Index of the mirror the requests go to.
+    current_host: AtomicUsize,
+    username: String,
+    password: String,
+    search_options: RuTrackerSearchOptions,
+    /// Incremented on every login, so requests that failed with the same
+    /// expired session log in only once.
+    session: AtomicU64,
+    login_lock: Mutex<()>,
+    /// Captcha shown on the last login attempt, until it's solved.
+    pending_captcha: std::sync::Mutex<Option<Captcha>>,
+    rate_limiter: RateLimiter,
 }
 
 impl RuTrackerClient {
-    pub async fn create(username: &str, password: &str) -> Result<Self, RuTrackerClientError> {
-        let client = Client::builder()
+    /// Creates a client without a session, see `log_in`. Cookies of a
+    /// previous session in the store are reused until they expire.
+    pub fn new<C: CookieStore + 'static>(
+        username: &str,
+        password: &str,
+        connection_options: RuTrackerConnectionOptions,
+        search_options: RuTrackerSearchOptions,
+        cookie_store: Arc<C>,
+    ) -> Self {
+        let mut builder = Client::builder()
             .redirect(Policy::limited(10))
-            .cookie_store(true)
-            .build()
-            .expect("Failed to create HTTP Client");
+            .connect_timeout(CONNECT_TIMEOUT)
+            .cookie_provider(cookie_store);
 
-        #[derive(Serialize)]
-        struct LoginForm {
-            login_username: String,
-            login_password: String,
-            login: String,
+        if let Some(proxy) = connection_options.proxy {
+            builder = builder.proxy(proxy);
         }
 
-        let form = LoginForm {
-            login_username: username.to_string(),
-            login_password: password.to_string(),
-            login: MAGIC_LOGIN_WORD.to_string(),
+        let client = builder.build().expect("Failed to create HTTP Client");
+        let hosts = match connection_options.hosts {
+            hosts if hosts.is_empty() => vec![DEFAULT_HOST.to_string()],
+            hosts => hosts
+                .into_iter()
+                .map(|host| host.trim_end_matches('/').to_string())
+                .collect(),
         };
 
-        let response = client
-            .post(format!("{}/forum/login.php", RU_TRACKER_HOST))
-            .form(&form)
-            .send()
-            .await?;
+        Self {
+            client,
+            hosts,
+            current_host: AtomicUsize::new(0),
+            username: username.to_string(),
+            password: password.to_string(),
+            search_options,
+            session: AtomicU64::new(0),
+            login_lock: Mutex::new(()),
+            pending_captcha: std::sync::Mutex::new(None),
+            rate_limiter: RateLimiter::new(connection_options.rate_limit),
+        }
+    }
+
+    pub async fn create(
+        username: &str,
+        password: &str,
+        search_options: RuTrackerSearchOptions,
+    ) -> Result<Self, RuTrackerClientError> {
+        let client = Self::new(
+            username,
+            password,
+            RuTrackerConnectionOptions::default(),
+            search_options,
+            Arc::new(Jar::default()),
+        );
+
+        client.log_in().await?;
+
+        Ok(client)
+    }
+
+    /// Fails with `AuthError::CaptchaVerificationIsRequired` when the tracker
+    /// asks for a captcha, which then has to be passed to `solve_captcha`.
+    pub async fn log_in(&self) -> Result<(), RuTrackerClientError> {
+        let _guard = self.login_lock.lock().await;
+
+        self.with_failover(|| self.submit_login_form(None)).await
+    }
+
+    pub fn pending_captcha(&self) -> Option<Captcha> {
+        self.pending_captcha
+            .lock()
+            .expect("Unable to lock pending captcha")
+            .clone()
+    }
+
+    /// Logs in with the code from the pending captcha image. When the code
+    /// is wrong, a new captcha becomes pending.
+    pub async fn solve_captcha(&self, code: &str) -> Result<(), RuTrackerClientError> {
+        let _guard = self.login_lock.lock().await;
+
+        self.with_failover(|| self.submit_login_form(Some(code)))
+            .await
+    }
+
+    async fn submit_login_form(
+        &self,
+        captcha_code: Option<&str>,
+    ) -> Result<(), RuTrackerClientError> {
+        let mut form = vec![
+            ("login_username".to_string(), self.username.clone()),
+            ("login_password".to_string(), self.password.clone()),
+            ("login".to_string(), MAGIC_LOGIN_WORD.to_string()),
+        ];
+
+        if let (Some(captcha), Some(code)) = (self.pending_captcha(), captcha_code) {
+            form.push(("cap_sid".to_string(), captcha.sid));
+            form.push((captcha.code_field, code.to_string()));
+        }
+
+        let request = self.client.post(self.url("/forum/login.php")).form(&form);
+        let response = self.send(request).await?;
+        let response = error_for_server_status(response)?;
 
         let raw_html = response.text().await?;
+        let auth_state = parse_and_validate_auth_state(&raw_html);
 
-        parse_and_validate_auth_state(&raw_html)?;
+        let captcha = match &auth_state {
+            Err(AuthError::CaptchaVerificationIsRequired) => parse_captcha(&raw_html)?,
+            _ => None,
+        };
+
+        if captcha.is_some() {
+            warn!("RuTracker asks for a captcha to log in");
+        }
+
+        *self
+            .pending_captcha
+            .lock()
+            .expect("Unable to lock pending captcha") = captcha;
+
+        auth_state?;
+        self.session.fetch_add(1, Ordering::SeqCst);
 
-        Ok(Self { client })
+        Ok(())
+    }
+
+    fn url(&self, path: &str) -> String {
+        let index = self.current_host.load(Ordering::SeqCst);
+
+        format!("{}{}", self.hosts[index], path)
+    }
+
+    /// Runs the call, moving on to the next mirror while the current one is
+    /// unreachable.
+    async fn with_failover<T, F, Fut>(&self, call: F) -> Result<T, RuTrackerClientError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, RuTrackerClientError>>,
+    {
+        for _ in 1..self.hosts.len() {
+            let index = self.current_host.load(Ordering::SeqCst);
+            let result = call().await;
+
+            if !is_host_unavailable(&result) {
+                return result;
+            }
+
+            if let Err(error) = &result {
+                warn!(%error, host = self.hosts[index], "RuTracker host is unavailable");
+            }
+
+            // The result can't be kept across awaits as the errors aren't Send.
+            drop(result);
+
+            let next_index = (index + 1) % self.hosts.len();
+
+            // Concurrent requests may have already switched to the next mirror.
+            if self
+                .current_host
+                .compare_exchange(index, next_index, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                warn!(
+                    host = self.hosts[next_index],
+                    "Switched to another RuTracker mirror"
+                );
+            }
+        }
+
+        call().await
+    }
+
+    /// Sends the request once the rate limit allows. Fails with `RateLimited`
+    /// instead of waiting longer than allowed by the rate limit options.
+    async fn send(&self, request: RequestBuilder) -> Result<Response, RuTrackerClientError> {
+        self.rate_limiter
+            .wait_for_request()
+            .await
+            .map_err(RuTrackerClientError::RateLimited)?;
+
+        Ok(request.send().await?)
+    }
+
+    /// Runs the call, logging in again and retrying it once if the session
+    /// turns out to be expired.
+    async fn with_session<T, F, Fut>(&self, call: F) -> Result<T, RuTrackerClientError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, RuTrackerClientError>>,
+    {
+        let session = self.session.load(Ordering::SeqCst);
+        let result = call().await;
+
+        if !matches!(
+            result,
+            Err(RuTrackerClientError::AuthError(AuthError::UnknownAuthError))
+        ) {
+            return result;
+        }
+
+        // The result can't be kept across awaits as the errors aren't Send.
+        drop(result);
+        self.relogin(session).await?;
+
+        call().await
+    }
+
+    async fn relogin(&self, expired_session: u64) -> Result<(), RuTrackerClientError> {
+        let _guard = self.login_lock.lock().await;
+
+        // Another request may have already logged in while this one waited.
+        if self.session.load(Ordering::SeqCst) != expired_session {
+            return Ok(());
+        }
+
+        // Logging in again would only bring another captcha.
+        if self.pending_captcha().is_some() {
+            return Err(AuthError::CaptchaVerificationIsRequired.into());
+        }
+
+        warn!("RuTracker session has expired, logging in again...");
+
+        self.with_failover(|| self.submit_login_form(None)).await
     }
 
     pub async fn search_music(
         &self,
         query_str: &str,
+    ) -> Result<Vec<TopicData>, RuTrackerClientError> {
+        self.with_session(|| self.with_failover(|| self.search_music_once(query_str)))
+            .await
+    }
+
+    async fn search_music_once(
+        &self,
+        query_str: &str,
     ) -> Result<Vec<TopicData>, RuTrackerClientError> {
         #[derive(Serialize)]
         struct Query {
             nm: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            f: Option<String>,
         }
 
+        let forum_ids = &self.search_options.forum_ids;
         let query = Query {
             nm: query_str.to_string(),
+            f: (!forum_ids.is_empty()).then(|| {
+                forum_ids
+                    .iter()
+                    .map(u64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            }),
         };
 
-        let response = self
+        let mut raw_html = self.fetch_search_page(&query).await?;
+        let mut results = parse_search_results(&raw_html)?;
+        let mut pages = 1;
+        let mut start = 0;
+
+        while pages < self.search_options.max_pages {
+            let Some(next_page) = parse_next_page(&raw_html, start)? else {
+                break;
+            };
+
+            #[derive(Serialize)]
+            struct PageQuery {
+                search_id: String,
+                start: u64,
+            }
+
+            start = next_page.start;
+            raw_html = self
+                .fetch_search_page(&PageQuery {
+                    search_id: next_page.search_id,
+                    start,
+                })
+                .await?;
+            results.extend(parse_search_results(&raw_html)?);
+            pages += 1;
+        }
+
+        // The forum filter already restricts the search to the requested forums.
+        if forum_ids.is_empty() {
+            results.retain(|topic| is_music_forum(&topic.forum_name));
+        }
+
+        Ok(results)
+    }
+
+    async fn fetch_search_page<Q: Serialize>(
+        &self,
+        query: &Q,
+    ) -> Result<String, RuTrackerClientError> {
+        let request = self.client.get(self.url("/forum/tracker.php")).query(query);
+        let response = self.send(request).await?;
+        let response = error_for_server_status(response)?;
+
+        let raw_html = response.text().await?;
+
+        parse_and_validate_auth_state(&raw_html)?;
+
+        Ok(raw_html)
+    }
+
+    /// Returns `None` when the page has no topic, e.g. it has been deleted.
+    pub async fn get_topic(
+        &self,
+        topic_id: u64,
+    ) -> Result<Option<TopicDetails>, RuTrackerClientError> {
+        self.with_session(|| self.with_failover(|| self.get_topic_once(topic_id)))
+            .await
+    }
+
+    async fn get_topic_once(
+        &self,
+        topic_id: u64,
+    ) -> Result<Option<TopicDetails>, RuTrackerClientError> {
+        let request = self
             .client
-            .get(format!("{}/forum/tracker.php", RU_TRACKER_HOST))
-            .query(&query)
-            .send()
-            .await?;
+            .get(self.url("/forum/viewtopic.php"))
+            .query(&[("t", topic_id)]);
+        let response = self.send(request).await?;
+        let response = error_for_server_status(response)?;
 
         let raw_html = response.text().await?;
 
         parse_and_validate_auth_state(&raw_html)?;
 
-        Ok(parse_search_results(&raw_html)?)
+        Ok(parse_topic_page(&raw_html)?)
     }
 
+    /// Fails with `DownloadQuotaExceeded` when the daily quota is spent.
+    /// Failed downloads don't count against the quota.
     pub async fn download_torrent(
         &self,
         download_id: u64,
     ) -> Result<Vec<u8>, RuTrackerClientError> {
-        let response = self
+        // Reserved upfront, so concurrent downloads can't exceed the quota.
+        let reserved_at = Instant::now();
+        self.rate_limiter
+            .reserve_download(reserved_at)
+            .map_err(RuTrackerClientError::DownloadQuotaExceeded)?;
+
+        let result = self
+            .with_session(|| self.with_failover(|| self.download_torrent_once(download_id)))
+            .await;
+
+        if result.is_err() {
+            self.rate_limiter.release_download(reserved_at);
+        }
+
+        result
+    }
+
+    async fn download_torrent_once(
+        &self,
+        download_id: u64,
+    ) -> Result<Vec<u8>, RuTrackerClientError> {
+        let request = self
             .client
-            .get(format!(
-                "{}/forum/dl.php?t={}",
-                RU_TRACKER_HOST, download_id
-            ))
-            .send()
-            .await?;
+            .get(self.url("/forum/dl.php"))
+            .query(&[("t", download_id)]);
+        let response = self.send(request).await?;
         let status = response.status();
 
         if status != StatusCode::OK {
@@ -106,15 +482,26 @@ impl RuTrackerClient {
             return Err(RuTrackerClientError::BadStatus(status));
         }
 
-        Ok(response.bytes().await?.to_vec())
+        let data = response.bytes().await?.to_vec();
+
+        // Torrent files are bencoded dictionaries, anything else is a page,
+        // e.g. the login form.
+        if !data.starts_with(b"d") {
+            parse_and_validate_auth_state(&String::from_utf8_lossy(&data))?;
+            return Err(RuTrackerClientError::NotTorrent);
+        }
+
+        Ok(data)
     }
 
     pub async fn check_connection(&self) -> Result<(), RuTrackerClientError> {
-        let response = self
-            .client
-            .get(format!("{}", RU_TRACKER_HOST))
-            .send()
-            .await?;
+        self.with_session(|| self.with_failover(|| self.check_connection_once()))
+            .await
+    }
+
+    async fn check_connection_once(&self) -> Result<(), RuTrackerClientError> {
+        let request = self.client.get(self.url("/forum/index.php"));
+        let response = self.send(request).await?;
         let status = response.status();
 
         if status != StatusCode::OK {
@@ -1,10 +1,19 @@
-use crate::rutracker::parser::parse_search_results;
-use crate::{DownloadId, TopicData, TopicId};
+use crate::rutracker::parser::{
+    is_music_forum, parse_and_validate_auth_state, parse_captcha, parse_next_page,
+    parse_search_results, parse_topic_page, NextPage,
+};
+use crate::rutracker::rate_limiter::RateLimiter;
+use crate::RuTrackerRateLimitOptions;
+use crate::{AuthError, Captcha, DownloadId, TopicData, TopicDetails, TopicId, TopicStatus};
+use std::time::{Duration, Instant};
 
 #[test]
 fn test_parsing_of_search_results() {
     let results = parse_search_results(include_str!("fixtures/search_results.html"))
-        .expect("Expected successful parse results");
+        .expect("Expected successful parse results")
+        .into_iter()
+        .filter(|topic| is_music_forum(&topic.forum_name))
+        .collect::<Vec<_>>();
 
     let expected_results = vec![
         TopicData {
@@ -13,6 +22,14 @@ fn test_parsing_of_search_results() {
             topic_id: TopicId(1183770),
             download_id: DownloadId(1183770),
             seeds_number: 18,
+            leeches_number: 1,
+            downloads_number: 2968,
+            size: 447129784,
+            forum_id: Some(1818),
+            forum_name: "Trance (lossless)".into(),
+            author: "DrStandBy".into(),
+            added_at: 1505371128,
+            status: TopicStatus::Approved,
         },
         TopicData {
             #[rustfmt::skip]
@@ -20,6 +37,14 @@ fn test_parsing_of_search_results() {
             topic_id: TopicId(1184081),
             download_id: DownloadId(1184081),
             seeds_number: 11,
+            leeches_number: 1,
+            downloads_number: 1710,
+            size: 545959122,
+            forum_id: Some(1818),
+            forum_name: "Trance (lossless)".into(),
+            author: "DrStandBy".into(),
+            added_at: 1496220672,
+            status: TopicStatus::Approved,
         },
         TopicData {
             #[rustfmt::skip]
@@ -27,6 +52,44 @@ fn test_parsing_of_search_results() {
             topic_id: TopicId(5318721),
             download_id: DownloadId(5318721),
             seeds_number: 8,
+            leeches_number: 0,
+            downloads_number: 3416,
+            size: 530180022,
+            forum_id: Some(1818),
+            forum_name: "Trance (lossless)".into(),
+            author: "StazzOn".into(),
+            added_at: 1480487011,
+            status: TopicStatus::Approved,
+        },
+        TopicData {
+            #[rustfmt::skip]
+            title: "(Trance) Robert Miles - Dreamland (Remastered) - 2016, MP3, 320 kbps".into(),
+            topic_id: TopicId(5309922),
+            download_id: DownloadId(5309922),
+            seeds_number: 9,
+            leeches_number: 0,
+            downloads_number: 5228,
+            size: 188233781,
+            forum_id: Some(1819),
+            forum_name: "Trance (lossy)".into(),
+            author: "StazzOn".into(),
+            added_at: 1479115790,
+            status: TopicStatus::Approved,
+        },
+        TopicData {
+            #[rustfmt::skip]
+            title: "(Dream House) Robert Miles - Dreamland (Including One and One) - 1996 [WEB], AAC (tracks) 256 kbps".into(),
+            topic_id: TopicId(4737164),
+            download_id: DownloadId(4737164),
+            seeds_number: 2,
+            leeches_number: 0,
+            downloads_number: 1702,
+            size: 145378309,
+            forum_id: Some(2240),
+            forum_name: "Музыка Lossy (AAC-iTunes)".into(),
+            author: "Wadilla Killer".into(),
+            added_at: 1399825108,
+            status: TopicStatus::Approved,
         },
         TopicData {
             #[rustfmt::skip]
@@ -34,6 +97,44 @@ fn test_parsing_of_search_results() {
             topic_id: TopicId(3418878),
             download_id: DownloadId(3418878),
             seeds_number: 4,
+            leeches_number: 0,
+            downloads_number: 7906,
+            size: 664059511,
+            forum_id: Some(1818),
+            forum_name: "Trance (lossless)".into(),
+            author: "spock82".into(),
+            added_at: 1297930840,
+            status: TopicStatus::Approved,
+        },
+        TopicData {
+            #[rustfmt::skip]
+            title: "(Trance) Robert Miles - Dreamland - 1996 (Urban, 533 002-2), FLAC (image+.cue) lossless".into(),
+            topic_id: TopicId(3199643),
+            download_id: DownloadId(3199643),
+            seeds_number: 10,
+            leeches_number: 1,
+            downloads_number: 3976,
+            size: 450353378,
+            forum_id: Some(1818),
+            forum_name: "Trance (lossless)".into(),
+            author: "Serge85".into(),
+            added_at: 1286705094,
+            status: TopicStatus::Approved,
+        },
+        TopicData {
+            #[rustfmt::skip]
+            title: "(Trance, Dream House, Downtempo) Robert Miles - Dreamland~New Edition (Japan) - 1996, FLAC (image+.cue), lossless".into(),
+            topic_id: TopicId(2495343),
+            download_id: DownloadId(2495343),
+            seeds_number: 7,
+            leeches_number: 2,
+            downloads_number: 4904,
+            size: 572896465,
+            forum_id: Some(1818),
+            forum_name: "Trance (lossless)".into(),
+            author: "Konstantin_slash".into(),
+            added_at: 1260108693,
+            status: TopicStatus::Approved,
         },
         TopicData {
             #[rustfmt::skip]
@@ -41,23 +142,294 @@ fn test_parsing_of_search_results() {
             topic_id: TopicId(1201152),
             download_id: DownloadId(1201152),
             seeds_number: 3,
+            leeches_number: 1,
+            downloads_number: 2474,
+            size: 428560959,
+            forum_id: Some(1927),
+            forum_name: "Музыка lossless (ALAC)".into(),
+            author: "Denis Sokolove".into(),
+            added_at: 1224964453,
+            status: TopicStatus::Approved,
         },
         TopicData {
             #[rustfmt::skip]
-            title: "(Trance) Robert Miles - Dreamland (Remastered) - 2016, MP3, 320 kbps".into(),
-            topic_id: TopicId(5309922),
-            download_id: DownloadId(5309922),
-            seeds_number: 9,
+            title: "(Trance, Dreamhouse) Robert Miles - Dreamland - 1996, APE (image+.cue), lossless".into(),
+            topic_id: TopicId(1182981),
+            download_id: DownloadId(1182981),
+            seeds_number: 2,
+            leeches_number: 0,
+            downloads_number: 2325,
+            size: 407884085,
+            forum_id: Some(1818),
+            forum_name: "Trance (lossless)".into(),
+            author: "mike@".into(),
+            added_at: 1224366806,
+            status: TopicStatus::Approved,
         },
         TopicData {
             #[rustfmt::skip]
-            title: "(Dream House) Robert Miles - Dreamland (Including One and One) - 1996 [WEB], AAC (tracks) 256 kbps".into(),
-            topic_id: TopicId(4737164),
-            download_id: DownloadId(4737164),
-            seeds_number: 2,
+            title: "(Trance) [WEB] Robert Miles - Dreamland (Deluxe Edition) - 2024, FLAC (tracks), lossless".into(),
+            topic_id: TopicId(6512345),
+            download_id: DownloadId(6512345),
+            seeds_number: 1,
+            leeches_number: 3,
+            downloads_number: 12,
+            size: 1206751842,
+            forum_id: Some(1818),
+            forum_name: "Trance (lossless)".into(),
+            author: "Tom & Jerry".into(),
+            added_at: 1716892800,
+            status: TopicStatus::NotApproved,
         },
     ];
 
-    assert_eq!(7, results.len());
+    assert_eq!(11, results.len());
     assert_eq!(expected_results, results);
 }
+
+#[test]
+fn test_parsing_of_search_results_of_all_forums() {
+    let results = parse_search_results(include_str!("fixtures/search_results.html"))
+        .expect("Expected successful parse results");
+
+    assert_eq!(16, results.len());
+    assert_eq!(
+        5,
+        results
+            .iter()
+            .filter(|topic| !is_music_forum(&topic.forum_name))
+            .count()
+    );
+}
+
+#[test]
+fn test_parsing_of_search_results_with_unexpected_forum_links() {
+    let html = include_str!("fixtures/search_results.html")
+        .replace(
+            r#"tracker.php?f=1819""#,
+            r#"viewforum.php?f=1819&amp;start=50""#,
+        )
+        .replace(r#"tracker.php?f=2240""#, r##"#""##);
+    let results = parse_search_results(&html).expect("Expected successful parse results");
+    let forum_id = |topic_id: u64| {
+        results
+            .iter()
+            .find(|topic| topic.topic_id == TopicId(topic_id))
+            .map(|topic| topic.forum_id)
+    };
+
+    assert_eq!(16, results.len());
+    assert_eq!(Some(Some(1819)), forum_id(5309922));
+    assert_eq!(Some(None), forum_id(4737164));
+}
+
+#[test]
+fn test_parsing_of_next_page() {
+    let raw_html = r#"
+        <p class="small bold">Страницы: &nbsp;
+            <a class="pg" href="tracker.php?search_id=gKzN5a1qL2xP&amp;start=0">1</a>,
+            <b>2</b>,
+            <a class="pg" href="tracker.php?search_id=gKzN5a1qL2xP&amp;start=100">3</a>
+            ...
+            <a class="pg" href="tracker.php?search_id=gKzN5a1qL2xP&amp;start=450">10</a>
+            <a class="pg" href="tracker.php?search_id=gKzN5a1qL2xP&amp;start=100">След.</a>
+        </p>
+    "#;
+
+    assert_eq!(
+        Some(NextPage {
+            search_id: "gKzN5a1qL2xP".into(),
+            start: 100,
+        }),
+        parse_next_page(raw_html, 50).expect("Expected successful parse")
+    );
+    assert_eq!(
+        None,
+        parse_next_page(raw_html, 450).expect("Expected successful parse")
+    );
+    assert_eq!(
+        None,
+        parse_next_page(include_str!("fixtures/search_results.html"), 0)
+            .expect("Expected successful parse")
+    );
+}
+
+#[test]
+fn test_parsing_of_topic_page() {
+    let details =
+        parse_topic_page(include_str!("fixtures/topic.html")).expect("Expected successful parse");
+
+    assert_eq!(
+        Some(TopicDetails {
+            title: "(Trance) Robert Miles - Dreamland (Remastered) - 2016, MP3, 320 kbps".into(),
+            year: Some(2016),
+            format: Some("MP3".into()),
+            tracklist: vec![
+                "Robert Miles - Children (Dream Version)".into(),
+                "Robert Miles - Fable (Message Version)".into(),
+                "Robert Miles - Fantasya".into(),
+                "Robert Miles - Landscape".into(),
+                "Robert Miles - In My Dreams".into(),
+                "Robert Miles - Princess Of Light".into(),
+                "Robert Miles - Fable (Dream Version)".into(),
+                "Robert Miles - In The Dawn".into(),
+                "Robert Miles - Children".into(),
+                "Robert Miles - Red Zone".into(),
+                "Robert Miles - One & One (Radio Version) (feat. Maria Nayler)".into(),
+                "Robert Miles - 4 Us".into(),
+            ],
+        }),
+        details
+    );
+    assert_eq!(
+        None,
+        parse_topic_page(include_str!("fixtures/search_results.html"))
+            .expect("Expected successful parse")
+    );
+}
+
+#[test]
+fn test_parsing_of_tracklist_in_spoiler() {
+    let raw_html = r#"
+        <a id="topic-title" href="viewtopic.php?t=1">Robert Miles - Dreamland - 1996, FLAC (tracks), lossless</a>
+        <div class="post_body">
+            <span class="post-b">Год издания</span>: 1996 (2CD)<br>
+            <span class="post-b">Аудиокодек</span>: FLAC (*.flac)<br>
+            <div class="sp-wrap"><div class="sp-head">Треклист</div><div class="sp-body">
+                CD 1<br>
+                1. Children (04:03)<br>
+                2) Fable (06:50)<br>
+                <br>
+                CD 2<br>
+                01 - Landscape<br>
+            </div></div>
+            <span class="post-b">Лог создания рипа</span>
+        </div>
+    "#;
+
+    let details = parse_topic_page(raw_html)
+        .expect("Expected successful parse")
+        .expect("Expected topic details");
+
+    assert_eq!(Some(1996), details.year);
+    assert_eq!(Some("FLAC (*.flac)".to_string()), details.format);
+    assert_eq!(
+        vec!["Children".to_string(), "Fable".into(), "Landscape".into()],
+        details.tracklist
+    );
+}
+
+#[test]
+fn test_parsing_of_captcha() {
+    let raw_html = include_str!("fixtures/login_captcha.html");
+
+    assert!(matches!(
+        parse_and_validate_auth_state(raw_html),
+        Err(AuthError::CaptchaVerificationIsRequired)
+    ));
+    assert_eq!(
+        Some(Captcha {
+            #[rustfmt::skip]
+            image_url: "https://static.rutracker.cc/captcha/3/54/f2a0e8cb13b1e7bd1ea0cb4ba5de0b30.jpg?1687203821".into(),
+            sid: "vU3kCyZQ7fHnY9TzqL0E".into(),
+            code_field: "cap_code_f2a0e8cb13b1e7bd1ea0cb4ba5de0b30".into(),
+        }),
+        parse_captcha(raw_html).expect("Expected successful parse")
+    );
+    assert_eq!(
+        None,
+        parse_captcha(include_str!("fixtures/index_logged_out.html"))
+            .expect("Expected successful parse")
+    );
+}
+
+#[test]
+fn test_spreading_requests_evenly() {
+    let rate_limiter = RateLimiter::new(RuTrackerRateLimitOptions {
+        requests_per_minute: 30,
+        max_jitter: Duration::ZERO,
+        daily_downloads: None,
+        max_wait: Duration::from_secs(4),
+    });
+    let now = Instant::now();
+
+    assert_eq!(Ok(Duration::ZERO), rate_limiter.reserve_request(now));
+    assert_eq!(
+        Ok(Duration::from_secs(2)),
+        rate_limiter.reserve_request(now)
+    );
+    assert_eq!(
+        Ok(Duration::from_secs(4)),
+        rate_limiter.reserve_request(now)
+    );
+    assert_eq!(
+        Ok(Duration::ZERO),
+        rate_limiter.reserve_request(now + Duration::from_secs(10))
+    );
+}
+
+#[test]
+fn test_reporting_long_waits_for_requests() {
+    let rate_limiter = RateLimiter::new(RuTrackerRateLimitOptions {
+        requests_per_minute: 30,
+        max_jitter: Duration::ZERO,
+        daily_downloads: None,
+        max_wait: Duration::from_secs(2),
+    });
+    let now = Instant::now();
+
+    assert_eq!(Ok(Duration::ZERO), rate_limiter.reserve_request(now));
+    assert_eq!(
+        Ok(Duration::from_secs(2)),
+        rate_limiter.reserve_request(now)
+    );
+    // The slot isn't taken, so the wait doesn't grow.
+    assert_eq!(
+        Err(Duration::from_secs(4)),
+        rate_limiter.reserve_request(now)
+    );
+    assert_eq!(
+        Err(Duration::from_secs(4)),
+        rate_limiter.reserve_request(now)
+    );
+    assert_eq!(
+        Ok(Duration::from_secs(2)),
+        rate_limiter.reserve_request(now + Duration::from_secs(2))
+    );
+}
+
+#[test]
+fn test_limiting_daily_downloads() {
+    let rate_limiter = RateLimiter::new(RuTrackerRateLimitOptions {
+        daily_downloads: Some(2),
+        ..Default::default()
+    });
+    let now = Instant::now();
+    let hour = Duration::from_secs(60 * 60);
+
+    assert_eq!(Ok(()), rate_limiter.reserve_download(now));
+    assert_eq!(Ok(()), rate_limiter.reserve_download(now + hour));
+    assert_eq!(
+        Err(hour * 22),
+        rate_limiter.reserve_download(now + hour * 2)
+    );
+    assert_eq!(Ok(()), rate_limiter.reserve_download(now + hour * 24));
+}
+
+#[test]
+fn test_releasing_failed_downloads() {
+    let rate_limiter = RateLimiter::new(RuTrackerRateLimitOptions {
+        daily_downloads: Some(1),
+        ..Default::default()
+    });
+    let now = Instant::now();
+    let hour = Duration::from_secs(60 * 60);
+
+    assert_eq!(Ok(()), rate_limiter.reserve_download(now));
+    rate_limiter.release_download(now);
+    assert_eq!(Ok(()), rate_limiter.reserve_download(now + hour));
+    assert_eq!(
+        Err(hour * 23),
+        rate_limiter.reserve_download(now + hour * 2)
+    );
+}
@@ -0,0 +1,8 @@
+mod parser;
+pub use parser::*;
+
+mod torznab;
+pub use torznab::*;
+
+#[cfg(test)]
+mod tests;
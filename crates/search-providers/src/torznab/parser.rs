@@ -0,0 +1,202 @@
+use roxmltree::{Document, Node};
+
+const TORZNAB_NAMESPACE: &str = "http://torznab.com/schemas/2015/feed";
+
+#[derive(Debug, thiserror::Error)]
+pub enum TorznabParseError {
+    #[error(transparent)]
+    XmlError(#[from] roxmltree::Error),
+    #[error("Torznab error {code}: {description}")]
+    ApiError { code: String, description: String },
+    #[error("Unexpected document: <{0}>")]
+    UnexpectedDocument(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TorznabCategory {
+    pub id: u32,
+    pub name: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct TorznabCaps {
+    pub search_available: bool,
+    /// Parameters of the `t=music` search, if it's available.
+    pub music_search_params: Option<Vec<String>>,
+    /// Categories along with their subcategories.
+    pub categories: Vec<TorznabCategory>,
+}
+
+impl TorznabCaps {
+    pub fn supports_music_param(&self, param: &str) -> bool {
+        self.music_search_params
+            .as_ref()
+            .is_some_and(|params| params.iter().any(|p| p == param))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TorznabItem {
+    pub title: String,
+    pub guid: String,
+    /// Link to the torrent file; some indexers give a magnet link here.
+    pub link: Option<String>,
+    pub magnet_url: Option<String>,
+    pub info_hash: Option<String>,
+    pub seeders: u64,
+    pub size: Option<u64>,
+    pub categories: Vec<u32>,
+}
+
+/// Torznab reports errors as an `<error>` document with the 200 status.
+fn check_error(document: &Document) -> Result<(), TorznabParseError> {
+    let root = document.root_element();
+
+    if root.has_tag_name("error") {
+        return Err(TorznabParseError::ApiError {
+            code: root.attribute("code").unwrap_or_default().to_string(),
+            description: root
+                .attribute("description")
+                .unwrap_or_default()
+                .to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Looks for the error document in the response body which isn't
+/// necessarily XML, e.g. for the failed request.
+pub(crate) fn parse_api_error(raw_xml: &str) -> Result<(), TorznabParseError> {
+    match Document::parse(raw_xml) {
+        Ok(document) => check_error(&document),
+        Err(_) => Ok(()),
+    }
+}
+
+fn expect_root(document: &Document, tag_name: &str) -> Result<(), TorznabParseError> {
+    check_error(document)?;
+
+    let root = document.root_element();
+    if !root.has_tag_name(tag_name) {
+        return Err(TorznabParseError::UnexpectedDocument(
+            root.tag_name().name().to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn parse_category(node: Node) -> Option<TorznabCategory> {
+    Some(TorznabCategory {
+        id: node.attribute("id")?.parse().ok()?,
+        name: node.attribute("name").unwrap_or_default().to_string(),
+    })
+}
+
+pub(crate) fn parse_caps(raw_xml: &str) -> Result<TorznabCaps, TorznabParseError> {
+    let document = Document::parse(raw_xml)?;
+    expect_root(&document, "caps")?;
+
+    let mut caps = TorznabCaps::default();
+
+    for node in document.descendants().filter(Node::is_element) {
+        let is_available = node.attribute("available") == Some("yes");
+
+        match node.tag_name().name() {
+            "search" => caps.search_available = is_available,
+            "music-search" if is_available => {
+                caps.music_search_params.replace(
+                    node.attribute("supportedParams")
+                        .unwrap_or_default()
+                        .split(',')
+                        .map(|param| param.trim().to_string())
+                        .filter(|param| !param.is_empty())
+                        .collect(),
+                );
+            }
+            "category" | "subcat" => caps.categories.extend(parse_category(node)),
+            _ => (),
+        }
+    }
+
+    Ok(caps)
+}
+
+fn get_child_text(node: Node, tag_name: &str) -> Option<String> {
+    node.children()
+        .find(|child| child.has_tag_name(tag_name))
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+fn get_torznab_attrs<'a>(node: Node<'a, 'a>, name: &'a str) -> impl Iterator<Item = &'a str> {
+    node.children()
+        .filter(|child| child.has_tag_name((TORZNAB_NAMESPACE, "attr")))
+        .filter(move |child| child.attribute("name") == Some(name))
+        .filter_map(|child| child.attribute("value"))
+}
+
+fn parse_item(node: Node) -> Option<TorznabItem> {
+    let title = get_child_text(node, "title")?;
+    let guid = get_child_text(node, "guid")?;
+    let enclosure_url = node
+        .children()
+        .find(|child| child.has_tag_name("enclosure"))
+        .and_then(|child| child.attribute("url"))
+        .map(str::to_string);
+    let link = enclosure_url.or_else(|| get_child_text(node, "link"));
+    let magnet_url = get_torznab_attrs(node, "magneturl")
+        .next()
+        .map(str::to_string)
+        .or_else(|| link.clone().filter(|link| link.starts_with("magnet:")));
+
+    // Neither the torrent file nor the magnet link, nothing to download.
+    if link.is_none() && magnet_url.is_none() {
+        return None;
+    }
+
+    let mut categories: Vec<u32> = get_torznab_attrs(node, "category")
+        .filter_map(|value| value.parse().ok())
+        .collect();
+    if categories.is_empty() {
+        categories = node
+            .children()
+            .filter(|child| child.has_tag_name("category"))
+            .filter_map(|child| child.text()?.trim().parse().ok())
+            .collect();
+    }
+
+    Some(TorznabItem {
+        title,
+        guid,
+        link,
+        magnet_url,
+        info_hash: get_torznab_attrs(node, "infohash")
+            .next()
+            .map(str::to_lowercase),
+        seeders: get_torznab_attrs(node, "seeders")
+            .next()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default(),
+        size: get_child_text(node, "size").and_then(|size| size.parse().ok()),
+        categories,
+    })
+}
+
+pub(crate) fn parse_search_results(raw_xml: &str) -> Result<Vec<TorznabItem>, TorznabParseError> {
+    let document = Document::parse(raw_xml)?;
+    expect_root(&document, "rss")?;
+
+    let mut results: Vec<_> = document
+        .descendants()
+        .filter(|node| node.has_tag_name("item"))
+        .filter_map(parse_item)
+        .collect();
+
+    // Well seeded torrents go first.
+    results.sort_by_key(|item| std::cmp::Reverse(item.seeders));
+
+    Ok(results)
+}
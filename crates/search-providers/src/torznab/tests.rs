@@ -0,0 +1,149 @@
+use crate::torznab::parser::{parse_api_error, parse_caps, parse_search_results};
+use crate::torznab::torznab::build_search_params;
+use crate::{TorznabCaps, TorznabCategory, TorznabItem, TorznabParseError, TorznabQuery};
+
+fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_parsing_of_caps() {
+    let caps = parse_caps(include_str!("fixtures/caps.xml")).expect("Expected successful parse");
+
+    assert_eq!(
+        TorznabCaps {
+            search_available: true,
+            music_search_params: Some(vec![
+                "q".into(),
+                "artist".into(),
+                "album".into(),
+                "label".into(),
+                "year".into()
+            ]),
+            categories: vec![
+                TorznabCategory {
+                    id: 3000,
+                    name: "Audio".into()
+                },
+                TorznabCategory {
+                    id: 3010,
+                    name: "Audio/MP3".into()
+                },
+                TorznabCategory {
+                    id: 3040,
+                    name: "Audio/Lossless".into()
+                },
+                TorznabCategory {
+                    id: 5000,
+                    name: "TV".into()
+                },
+                TorznabCategory {
+                    id: 100231,
+                    name: "Lossless Music".into()
+                },
+            ],
+        },
+        caps
+    );
+}
+
+#[test]
+fn test_parsing_of_search_results() {
+    let results = parse_search_results(include_str!("fixtures/search_results.xml"))
+        .expect("Expected successful parse");
+
+    let magnet = "magnet:?xt=urn:btih:ff02638e98b7c57f9a79041624057f706edb1024&dn=Ted+Irens";
+    let expected_results = vec![
+        TorznabItem {
+            title: "Ted Irens - Life @ Mirror (2012) [FLAC]".into(),
+            guid: "https://other.example.org/t/87413".into(),
+            link: Some(magnet.into()),
+            magnet_url: Some(magnet.into()),
+            info_hash: Some("ff02638e98b7c57f9a79041624057f706edb1024".into()),
+            seeders: 25,
+            size: Some(412551502),
+            categories: vec![3040, 100231],
+        },
+        TorznabItem {
+            title: "Ted Irens - Life @ Mirror (2012) [MP3 320]".into(),
+            guid: "https://tracker.example.org/torrent/2201".into(),
+            #[rustfmt::skip]
+            link: Some("http://127.0.0.1:9117/dl/example/?jackett_apikey=secret&path=2201&file=Ted+Irens".into()),
+            magnet_url: None,
+            info_hash: Some("2ec5340de73d63d6faa162a9dd435acb462d8737".into()),
+            seeders: 3,
+            size: Some(123731968),
+            categories: vec![3010],
+        },
+    ];
+
+    assert_eq!(expected_results, results);
+}
+
+#[test]
+fn test_parsing_of_api_error() {
+    let error_xml = include_str!("fixtures/error.xml");
+
+    assert!(matches!(
+        parse_search_results(error_xml),
+        Err(TorznabParseError::ApiError { code, description })
+            if code == "100" && description == "Invalid API Key"
+    ));
+    assert!(parse_api_error(error_xml).is_err());
+    assert!(parse_api_error("Bad Gateway").is_ok());
+}
+
+#[test]
+fn test_building_music_search_params() {
+    let caps = parse_caps(include_str!("fixtures/caps.xml")).unwrap();
+    let query = TorznabQuery {
+        q: "Ted Irens - Life @ Mirror".into(),
+        artist: Some("Ted Irens".into()),
+        album: Some("Life @ Mirror".into()),
+    };
+
+    assert_eq!(
+        params(&[
+            ("t", "music"),
+            ("artist", "Ted Irens"),
+            ("album", "Life @ Mirror"),
+            ("cat", "3000,3010,3040"),
+        ]),
+        build_search_params(&caps, &[], &query)
+    );
+    assert_eq!(
+        params(&[
+            ("t", "music"),
+            ("artist", "Ted Irens"),
+            ("album", "Life @ Mirror"),
+            ("cat", "100231"),
+        ]),
+        build_search_params(&caps, &[100231], &query)
+    );
+}
+
+#[test]
+fn test_building_search_params_without_music_search() {
+    let caps = TorznabCaps {
+        search_available: true,
+        music_search_params: Some(vec!["q".into()]),
+        categories: vec![],
+    };
+    let query = TorznabQuery {
+        q: "Ted Irens - Life @ Mirror".into(),
+        artist: Some("Ted Irens".into()),
+        album: Some("Life @ Mirror".into()),
+    };
+
+    assert_eq!(
+        params(&[("t", "music"), ("q", "Ted Irens - Life @ Mirror")]),
+        build_search_params(&caps, &[], &query)
+    );
+    assert_eq!(
+        params(&[("t", "search"), ("q", "Ted Irens - Life @ Mirror")]),
+        build_search_params(&TorznabCaps::default(), &[], &query)
+    );
+}
@@ -0,0 +1,194 @@
+use crate::torznab::parser::{
+    parse_api_error, parse_caps, parse_search_results, TorznabCaps, TorznabItem, TorznabParseError,
+};
+use reqwest::header::LOCATION;
+use reqwest::redirect::Policy;
+use reqwest::{Client, StatusCode};
+use std::sync::Mutex;
+
+/// Newznab category range reserved for audio.
+const AUDIO_CATEGORIES: std::ops::Range<u32> = 3000..4000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TorznabClientError {
+    #[error(transparent)]
+    ReqwestError(#[from] reqwest::Error),
+    #[error(transparent)]
+    ParseError(#[from] TorznabParseError),
+    #[error("Unexpected response status: {0}")]
+    BadStatus(StatusCode),
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TorznabQuery {
+    pub q: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TorznabDownload {
+    File(Vec<u8>),
+    Magnet(String),
+}
+
+pub struct TorznabClient {
+    client: Client,
+    base_url: String,
+    api_key: String,
+    categories: Vec<u32>,
+    caps: Mutex<Option<TorznabCaps>>,
+}
+
+/// Builds the query parameters of the search using the most specific search
+/// function the indexer supports.
+pub(crate) fn build_search_params(
+    caps: &TorznabCaps,
+    categories: &[u32],
+    query: &TorznabQuery,
+) -> Vec<(String, String)> {
+    let mut params = vec![];
+
+    match (&query.artist, &query.album) {
+        (Some(artist), album)
+            if caps.supports_music_param("artist")
+                && (album.is_none() || caps.supports_music_param("album")) =>
+        {
+            params.push(("t".to_string(), "music".to_string()));
+            params.push(("artist".to_string(), artist.clone()));
+            if let Some(album) = album {
+                params.push(("album".to_string(), album.clone()));
+            }
+        }
+        _ if caps.supports_music_param("q") => {
+            params.push(("t".to_string(), "music".to_string()));
+            params.push(("q".to_string(), query.q.clone()));
+        }
+        _ => {
+            params.push(("t".to_string(), "search".to_string()));
+            params.push(("q".to_string(), query.q.clone()));
+        }
+    }
+
+    // Without configured categories search in the audio ones the indexer has.
+    let categories: Vec<String> = match categories {
+        [] => caps
+            .categories
+            .iter()
+            .map(|category| category.id)
+            .filter(|id| AUDIO_CATEGORIES.contains(id))
+            .map(|id| id.to_string())
+            .collect(),
+        categories => categories.iter().map(|id| id.to_string()).collect(),
+    };
+
+    if !categories.is_empty() {
+        params.push(("cat".to_string(), categories.join(",")));
+    }
+
+    params
+}
+
+impl TorznabClient {
+    pub fn create(base_url: &str, api_key: &str, categories: Vec<u32>) -> Self {
+        // Indexers may redirect the download link to a magnet link which
+        // can't be followed.
+        let client = Client::builder()
+            .redirect(Policy::custom(|attempt| {
+                if attempt.url().scheme() == "magnet" || attempt.previous().len() >= 10 {
+                    attempt.stop()
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .build()
+            .expect("Failed to create HTTP Client");
+
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            categories,
+            caps: Mutex::new(None),
+        }
+    }
+
+    async fn request(&self, params: &[(String, String)]) -> Result<String, TorznabClientError> {
+        let response = self
+            .client
+            .get(format!("{}/api", self.base_url))
+            .query(&[("apikey", &self.api_key)])
+            .query(params)
+            .send()
+            .await?;
+        let status = response.status();
+        let raw_xml = response.text().await?;
+
+        if status != StatusCode::OK {
+            // Error document explains the failure better than the status.
+            parse_api_error(&raw_xml)?;
+            return Err(TorznabClientError::BadStatus(status));
+        }
+
+        Ok(raw_xml)
+    }
+
+    pub async fn get_caps(&self) -> Result<TorznabCaps, TorznabClientError> {
+        if let Some(caps) = self.caps.lock().unwrap().as_ref() {
+            return Ok(caps.clone());
+        }
+
+        let raw_xml = self
+            .request(&[("t".to_string(), "caps".to_string())])
+            .await?;
+        let caps = parse_caps(&raw_xml)?;
+
+        self.caps.lock().unwrap().replace(caps.clone());
+
+        Ok(caps)
+    }
+
+    pub async fn search_music(
+        &self,
+        query: &TorznabQuery,
+    ) -> Result<Vec<TorznabItem>, TorznabClientError> {
+        let caps = self.get_caps().await?;
+        let params = build_search_params(&caps, &self.categories, query);
+        let raw_xml = self.request(&params).await?;
+
+        Ok(parse_search_results(&raw_xml)?)
+    }
+
+    pub async fn download(&self, url: &str) -> Result<TorznabDownload, TorznabClientError> {
+        let response = self.client.get(url).send().await?;
+        let status = response.status();
+
+        if status.is_redirection() {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .filter(|location| location.starts_with("magnet:"));
+
+            return match location {
+                Some(magnet) => Ok(TorznabDownload::Magnet(magnet.to_string())),
+                None => Err(TorznabClientError::BadStatus(status)),
+            };
+        }
+
+        if status != StatusCode::OK {
+            return Err(TorznabClientError::BadStatus(status));
+        }
+
+        Ok(TorznabDownload::File(response.bytes().await?.to_vec()))
+    }
+
+    pub async fn check_connection(&self) -> Result<(), TorznabClientError> {
+        let raw_xml = self
+            .request(&[("t".to_string(), "caps".to_string())])
+            .await?;
+        parse_caps(&raw_xml)?;
+
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
-use serde::Deserialize;
+use crate::services::audio_processor::AudioCodec;
+use crate::services::track_request_processor::FilePreference;
+use serde::{Deserialize, Deserializer};
+use std::str::FromStr;
 
 fn default_bind_address() -> String {
     "0.0.0.0:8080".to_string()
@@ -8,12 +11,173 @@ fn default_shutdown_timeout() -> u64 {
     30u64
 }
 
+fn default_ffmpeg_path() -> String {
+    "ffmpeg".to_string()
+}
+
+fn default_audio_processing_directory() -> String {
+    std::env::temp_dir()
+        .join("channel-bot")
+        .to_string_lossy()
+        .to_string()
+}
+
+fn default_loudness_target() -> f64 {
+    -23.0
+}
+
+fn default_loudness_true_peak() -> f64 {
+    -1.0
+}
+
+fn default_loudness_range() -> f64 {
+    7.0
+}
+
+fn default_search_provider_enabled() -> bool {
+    true
+}
+
+fn default_search_cache_ttl() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_local_library_rescan_interval() -> u64 {
+    60 * 60
+}
+
+fn default_rutracker_max_pages() -> u32 {
+    1
+}
+
+fn default_rutracker_requests_per_minute() -> u32 {
+    20
+}
+
+fn default_rutracker_max_jitter_ms() -> u64 {
+    2000
+}
+
+fn default_rutracker_max_wait_ms() -> u64 {
+    10000
+}
+
+fn default_file_selection_preference() -> FilePreference {
+    FilePreference::Lossless
+}
+
+/// Values of flattened structs always come as strings from the environment,
+/// so non-string fields have to be parsed explicitly.
+fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+fn option_from_str<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    from_str(deserializer).map(Some)
+}
+
+fn comma_separated_from_str<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    String::deserialize(deserializer)?
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(|value| value.parse().map_err(serde::de::Error::custom))
+        .collect()
+}
+
 #[derive(Clone, Debug, Deserialize)]
-pub(crate) struct RuTrackerCredentials {
-    #[serde(rename = "rutracker_username")]
+pub(crate) struct RuTrackerConfig {
+    #[serde(
+        default = "default_search_provider_enabled",
+        deserialize_with = "from_str",
+        rename = "rutracker_enabled"
+    )]
+    pub(crate) enabled: bool,
+    #[serde(default, deserialize_with = "from_str", rename = "rutracker_priority")]
+    pub(crate) priority: i32,
+    #[serde(default, rename = "rutracker_username")]
     pub(crate) username: String,
-    #[serde(rename = "rutracker_password")]
+    #[serde(default, rename = "rutracker_password")]
     pub(crate) password: String,
+    #[serde(
+        default,
+        deserialize_with = "comma_separated_from_str",
+        rename = "rutracker_forums"
+    )]
+    pub(crate) forum_ids: Vec<u64>,
+    #[serde(
+        default = "default_rutracker_max_pages",
+        deserialize_with = "from_str",
+        rename = "rutracker_max_pages"
+    )]
+    pub(crate) max_pages: u32,
+    #[serde(
+        default,
+        deserialize_with = "comma_separated_from_str",
+        rename = "rutracker_hosts"
+    )]
+    pub(crate) hosts: Vec<String>,
+    #[serde(default, rename = "rutracker_proxy")]
+    pub(crate) proxy: Option<String>,
+    #[serde(
+        default = "default_rutracker_requests_per_minute",
+        deserialize_with = "from_str",
+        rename = "rutracker_requests_per_minute"
+    )]
+    pub(crate) requests_per_minute: u32,
+    #[serde(
+        default = "default_rutracker_max_jitter_ms",
+        deserialize_with = "from_str",
+        rename = "rutracker_max_jitter_ms"
+    )]
+    pub(crate) max_jitter_ms: u64,
+    #[serde(
+        default,
+        deserialize_with = "option_from_str",
+        rename = "rutracker_daily_downloads"
+    )]
+    pub(crate) daily_downloads: Option<u32>,
+    #[serde(
+        default = "default_rutracker_max_wait_ms",
+        deserialize_with = "from_str",
+        rename = "rutracker_max_wait_ms"
+    )]
+    pub(crate) max_wait_ms: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct TorznabConfig {
+    #[serde(default, deserialize_with = "from_str", rename = "torznab_enabled")]
+    pub(crate) enabled: bool,
+    #[serde(default, deserialize_with = "from_str", rename = "torznab_priority")]
+    pub(crate) priority: i32,
+    #[serde(default, rename = "torznab_url")]
+    pub(crate) url: String,
+    #[serde(default, rename = "torznab_api_key")]
+    pub(crate) api_key: String,
+    #[serde(
+        default,
+        deserialize_with = "comma_separated_from_str",
+        rename = "torznab_categories"
+    )]
+    pub(crate) categories: Vec<u32>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -26,6 +190,8 @@ pub(crate) struct TransmissionConfig {
     pub(crate) username: Option<String>,
     #[serde(default, rename = "transmission_password")]
     pub(crate) password: Option<String>,
+    #[serde(default, rename = "transmission_proxy")]
+    pub(crate) proxy: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -36,6 +202,60 @@ pub(crate) struct RadioManagerConfig {
     pub(crate) username: String,
     #[serde(rename = "radiomanager_password")]
     pub(crate) password: String,
+    #[serde(default, rename = "radiomanager_proxy")]
+    pub(crate) proxy: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct AudioProcessingConfig {
+    #[serde(default, rename = "audio_processing_codec")]
+    pub(crate) codec: Option<AudioCodec>,
+    #[serde(default, rename = "audio_processing_bitrate")]
+    pub(crate) bitrate: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "from_str",
+        rename = "audio_processing_loudness_normalization"
+    )]
+    pub(crate) loudness_normalization: bool,
+    #[serde(
+        default = "default_loudness_target",
+        deserialize_with = "from_str",
+        rename = "audio_processing_loudness_target"
+    )]
+    pub(crate) loudness_target: f64,
+    #[serde(
+        default = "default_loudness_true_peak",
+        deserialize_with = "from_str",
+        rename = "audio_processing_loudness_true_peak"
+    )]
+    pub(crate) loudness_true_peak: f64,
+    #[serde(
+        default = "default_loudness_range",
+        deserialize_with = "from_str",
+        rename = "audio_processing_loudness_range"
+    )]
+    pub(crate) loudness_range: f64,
+    #[serde(
+        default = "default_audio_processing_directory",
+        rename = "audio_processing_directory"
+    )]
+    pub(crate) directory: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct FileSelectionConfig {
+    #[serde(
+        default = "default_file_selection_preference",
+        rename = "file_selection_preference"
+    )]
+    pub(crate) preference: FilePreference,
+    #[serde(
+        default,
+        rename = "file_selection_max_size",
+        deserialize_with = "option_from_str"
+    )]
+    pub(crate) max_file_size: Option<u64>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -46,13 +266,43 @@ pub(crate) struct Config {
     pub(crate) shutdown_timeout: u64,
     pub(crate) download_directory: String,
     pub(crate) state_storage_directory: String,
+    /// Session cookies are saved to the state storage directory, encrypted
+    /// with this key, only when it's set.
+    #[serde(default)]
+    pub(crate) cookies_encryption_key: Option<String>,
+    /// Seconds to keep search results and torrent files for, zero disables
+    /// the cache.
+    #[serde(default = "default_search_cache_ttl")]
+    pub(crate) search_cache_ttl: u64,
+    #[serde(flatten)]
+    pub(crate) rutracker: RuTrackerConfig,
     #[serde(flatten)]
-    pub(crate) rutracker: RuTrackerCredentials,
+    pub(crate) torznab: TorznabConfig,
     #[serde(flatten)]
     pub(crate) transmission: TransmissionConfig,
     #[serde(flatten)]
     pub(crate) radiomanager: RadioManagerConfig,
     pub(crate) openai_api_key: String,
+    #[serde(default)]
+    pub(crate) openai_proxy: Option<String>,
+    #[serde(default)]
+    pub(crate) metadata_index_path: Option<String>,
+    #[serde(default)]
+    pub(crate) ranking_policies_path: Option<String>,
+    #[serde(default)]
+    pub(crate) query_planner_path: Option<String>,
+    #[serde(default)]
+    pub(crate) local_library_path: Option<String>,
+    /// Seconds between the scans of the local library, so the files added
+    /// later are found too. Zero disables the rescans.
+    #[serde(default = "default_local_library_rescan_interval")]
+    pub(crate) local_library_rescan_interval: u64,
+    #[serde(default = "default_ffmpeg_path")]
+    pub(crate) ffmpeg_path: String,
+    #[serde(flatten)]
+    pub(crate) audio_processing: AudioProcessingConfig,
+    #[serde(flatten)]
+    pub(crate) file_selection: FileSelectionConfig,
 }
 
 impl Config {
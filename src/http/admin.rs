@@ -0,0 +1,51 @@
+use actix_web::{web, HttpResponse, Responder};
+use search_providers::{AuthError, RuTrackerClient, RuTrackerClientError};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::{error, info};
+
+pub(crate) async fn get_rutracker_captcha(
+    rutracker_client: web::Data<Option<Arc<RuTrackerClient>>>,
+) -> impl Responder {
+    let Some(rutracker_client) = rutracker_client.as_ref() else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    match rutracker_client.pending_captcha() {
+        Some(captcha) => HttpResponse::Ok().json(serde_json::json!({
+            "imageUrl": captcha.image_url,
+        })),
+        None => HttpResponse::NoContent().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SolveCaptchaData {
+    code: String,
+}
+
+pub(crate) async fn solve_rutracker_captcha(
+    rutracker_client: web::Data<Option<Arc<RuTrackerClient>>>,
+    params: web::Json<SolveCaptchaData>,
+) -> impl Responder {
+    let Some(rutracker_client) = rutracker_client.as_ref() else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    match rutracker_client.solve_captcha(&params.code).await {
+        Ok(()) => {
+            info!("Logged in to RuTracker");
+            HttpResponse::NoContent().finish()
+        }
+        Err(RuTrackerClientError::AuthError(AuthError::CaptchaVerificationIsRequired)) => {
+            HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                "error": "Captcha code is wrong",
+                "imageUrl": rutracker_client.pending_captcha().map(|captcha| captcha.image_url),
+            }))
+        }
+        Err(error) => {
+            error!(?error, "Unable to log in to RuTracker");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -1,14 +1,14 @@
-use crate::services::{RadioManagerClient, TransmissionClient};
+use crate::services::track_request_processor::SearchProviderTrait;
+use crate::services::{RadioManagerClient, SearchProviderRegistry, TransmissionClient};
 use actix_web::web::Data;
 use actix_web::{HttpResponse, Responder};
-use search_providers::RuTrackerClient;
 use std::sync::Arc;
 use tracing::error;
 
 pub(crate) async fn readiness_check(
     transmission_client: Data<Arc<TransmissionClient>>,
     radio_manager_client: Data<Arc<RadioManagerClient>>,
-    rutracker_client: Data<Arc<RuTrackerClient>>,
+    search_provider_registry: Data<Arc<SearchProviderRegistry>>,
 ) -> impl Responder {
     if let Err(error) = transmission_client.check_connection().await {
         error!(?error, "Readiness check failed");
@@ -18,7 +18,7 @@ pub(crate) async fn readiness_check(
         error!(?error, "Readiness check failed");
     }
 
-    if let Err(error) = rutracker_client.check_connection().await {
+    if let Err(error) = search_provider_registry.check_connection().await {
         error!(?error, "Readiness check failed");
     }
 
@@ -1,7 +1,12 @@
+mod admin;
 mod health;
+mod search;
 mod track_request;
 
+pub(crate) use admin::{get_rutracker_captcha, solve_rutracker_captcha};
 pub(crate) use health::readiness_check;
+pub(crate) use search::search;
 pub(crate) use track_request::{
-    get_track_request_statuses, make_track_request, make_tracks_suggestion,
+    get_track_request, get_track_request_statuses, make_track_request, make_tracks_suggestion,
+    override_track_request,
 };
@@ -0,0 +1,44 @@
+use crate::services::track_request_processor::{
+    AudioMetadata, ProcessRequestError, RadioManagerChannelId,
+};
+use crate::services::TrackRequestProcessor;
+use actix_web::{web, HttpResponse, Responder};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::error;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SearchData {
+    #[serde(flatten)]
+    metadata: AudioMetadata,
+    target_channel_id: RadioManagerChannelId,
+    /// How many of the best topics to fetch the torrents of.
+    #[serde(default)]
+    fetch_top: usize,
+}
+
+pub(crate) async fn search(
+    track_request_processor: web::Data<Arc<TrackRequestProcessor>>,
+    params: web::Json<SearchData>,
+) -> impl Responder {
+    let query = params.into_inner();
+
+    match track_request_processor
+        .search(&query.metadata, &query.target_channel_id, query.fetch_top)
+        .await
+    {
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(ProcessRequestError::SearchProviderError(error)) if error.retry_after().is_some() => {
+            let retry_after = error.retry_after().unwrap_or_default();
+
+            HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", retry_after.as_secs().max(1).to_string()))
+                .finish()
+        }
+        Err(error) => {
+            error!(?error, "Unable to search for the track");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
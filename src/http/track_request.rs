@@ -1,5 +1,7 @@
 use crate::services::track_request_processor::{
-    AudioMetadata, CreateRequestOptions, RadioManagerChannelId, TrackRequestController,
+    AudioMetadata, CreateRequestError, CreateRequestOptions, OverrideRequestError,
+    RadioManagerChannelId, RequestId, RequestOverride, TrackRequestController,
+    TrackRequestControllerError,
 };
 use crate::services::{OpenAIService, RadioManagerClient, TrackRequestProcessor};
 use crate::types::UserId;
@@ -14,6 +16,8 @@ pub(crate) struct MakeTrackRequestData {
     #[serde(flatten)]
     metadata: AudioMetadata,
     target_channel_id: RadioManagerChannelId,
+    #[serde(default)]
+    validate_metadata: bool,
 }
 
 pub(crate) async fn make_track_request(
@@ -23,10 +27,33 @@ pub(crate) async fn make_track_request(
     let query = params.into_inner();
     let user_id = UserId(1); // Not used yet
 
+    let options = CreateRequestOptions {
+        validate_metadata: query.validate_metadata,
+    };
+
     let request_id = match track_request_controller
-        .create_request(&user_id, &query.metadata, &query.target_channel_id)
+        .create_request(
+            &user_id,
+            &query.metadata,
+            &options,
+            &query.target_channel_id,
+        )
         .await
     {
+        Err(TrackRequestControllerError::TrackRequestError(
+            CreateRequestError::UnknownRecording,
+        )) => {
+            return HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                "error": "Requested track does not correspond to a known recording",
+            }));
+        }
+        Err(TrackRequestControllerError::TrackRequestError(
+            CreateRequestError::MetadataValidationNotConfigured,
+        )) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Metadata validation is not configured",
+            }));
+        }
         Err(error) => {
             error!(?error, "Unable to create track request");
             return HttpResponse::InternalServerError().finish();
@@ -73,9 +100,13 @@ pub(crate) async fn make_tracks_suggestion(
     info!("Suggested tracks are: {:?}", suggested_tracks);
 
     let mut request_ids = vec![];
+    let options = CreateRequestOptions {
+        validate_metadata: false,
+    };
+
     for track in suggested_tracks {
         let request_id = match track_request_controller
-            .create_request(&user_id, &track, &query.target_channel_id)
+            .create_request(&user_id, &track, &options, &query.target_channel_id)
             .await
         {
             Ok(request_id) => request_id,
@@ -108,3 +139,47 @@ pub(crate) async fn get_track_request_statuses(
 
     HttpResponse::Ok().json(statuses)
 }
+
+pub(crate) async fn get_track_request(
+    track_request_processor: web::Data<Arc<TrackRequestProcessor>>,
+    request_id: web::Path<RequestId>,
+) -> impl Responder {
+    let user_id = UserId(1); // Not used yet
+
+    match track_request_processor
+        .get_request_details(&user_id, &request_id)
+        .await
+    {
+        Ok(Some(details)) => HttpResponse::Ok().json(details),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(error) => {
+            error!(?error, "Unable to get track request details");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+pub(crate) async fn override_track_request(
+    track_request_controller: web::Data<Arc<TrackRequestController>>,
+    request_id: web::Path<RequestId>,
+    params: web::Json<RequestOverride>,
+) -> impl Responder {
+    let user_id = UserId(1); // Not used yet
+
+    match track_request_controller
+        .override_request(&user_id, &request_id, params.into_inner())
+        .await
+    {
+        Ok(()) => HttpResponse::Accepted().finish(),
+        Err(OverrideRequestError::RequestNotFound) => HttpResponse::NotFound().finish(),
+        Err(
+            error @ (OverrideRequestError::NoTorrent | OverrideRequestError::InvalidFileIndex(_)),
+        ) => HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": error.to_string(),
+        })),
+        Err(error) => {
+            error!(?error, "Unable to override track request");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -1,16 +1,30 @@
+use crate::services::search_provider_registry::merge_search_results;
+use crate::services::torrent_parser::TorrentFile;
 use crate::services::track_request_processor::{
-    DownloadId, RadioManagerChannelId, RadioManagerChannelTrack, RadioManagerClientError,
-    RadioManagerClientTrait, RadioManagerLinkId, RadioManagerTrackId, RequestId,
-    SearchProviderError, SearchProviderTrait, StateStorageError, StateStorageTrait, TopicData,
-    TopicId, Torrent, TorrentClientError, TorrentClientTrait, TorrentId, TorrentStatus,
-    TrackRequestProcessingContext, TrackRequestProcessingState, TrackRequestProcessingStatus,
+    AudioMetadata, AudioProcessorError, AudioProcessorTrait, AudioSplitterError,
+    AudioSplitterTrait, AudioTags, DownloadId, LocalLibraryError, LocalLibraryTrait,
+    MetadataProviderError, MetadataProviderTrait, RadioManagerChannelId, RadioManagerChannelTrack,
+    RadioManagerClientError, RadioManagerClientTrait, RadioManagerLinkId, RadioManagerTrackId,
+    RateLimitedError, RequestId, SearchProviderError, SearchProviderTrait, SearchQuery,
+    StateStorageError, StateStorageTrait, TopicData, TopicId, Torrent, TorrentClientError,
+    TorrentClientTrait, TorrentId, TorrentSource, TorrentStatus, TrackRequestProcessingContext,
+    TrackRequestProcessingState, TrackRequestProcessingStatus,
+};
+use crate::services::{
+    radio_manager_client, AudioProcessor, CachedSearchProvider, Ffmpeg, LocalLibrary,
+    MetadataIndex, RadioManagerClient, SearchProviderRegistry, TransmissionClient,
 };
-use crate::services::{radio_manager_client, RadioManagerClient, TransmissionClient};
 use crate::storage::on_disk::OnDiskStorage;
 use crate::types::UserId;
 use async_trait::async_trait;
-use search_providers::RuTrackerClient;
+use futures::future::join_all;
+use search_providers::{
+    RuTrackerClient, RuTrackerClientError, TopicStatus, TorznabClient, TorznabDownload,
+    TorznabQuery,
+};
 use std::collections::HashMap;
+use std::time::Duration;
+use tracing::{debug, warn};
 use uuid::Uuid;
 
 #[async_trait]
@@ -230,7 +244,9 @@ impl StateStorageTrait for OnDiskStorage {
                     });
 
                 match status {
-                    Some(TrackRequestProcessingStatus::Processing) | None => {
+                    Some(TrackRequestProcessingStatus::Processing)
+                    | Some(TrackRequestProcessingStatus::WaitingForRateLimit)
+                    | None => {
                         tasks.push((UserId(user_id), RequestId(request_id)));
                     }
                     _ => (),
@@ -260,22 +276,93 @@ impl TorrentClientTrait for TransmissionClient {
         Ok(TorrentId(torrent_id))
     }
 
+    async fn add_magnet(&self, magnet_link: &str) -> Result<TorrentId, TorrentClientError> {
+        let torrent_id = TransmissionClient::add_magnet(self, magnet_link)
+            .await
+            .map_err(|err| TorrentClientError(Box::from(err)))?;
+
+        Ok(TorrentId(torrent_id))
+    }
+
+    async fn start_torrent(&self, torrent_id: &TorrentId) -> Result<(), TorrentClientError> {
+        self.start(torrent_id)
+            .await
+            .map_err(|err| TorrentClientError(Box::from(err)))
+    }
+
+    async fn get_files(
+        &self,
+        torrent_id: &TorrentId,
+    ) -> Result<Option<Vec<TorrentFile>>, TorrentClientError> {
+        let torrent = self
+            .get(torrent_id)
+            .await
+            .map_err(|err| TorrentClientError(Box::from(err)))?;
+
+        if torrent.metadata_percent_complete.unwrap_or_default() < 1.0 {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            torrent
+                .files
+                .unwrap_or_default()
+                .into_iter()
+                .map(|f| TorrentFile {
+                    path: f.name,
+                    length: f.length as u64,
+                    padding: false,
+                })
+                .collect(),
+        ))
+    }
+
+    async fn select_files(
+        &self,
+        torrent_id: &TorrentId,
+        selected_files_indexes: Vec<i32>,
+    ) -> Result<(), TorrentClientError> {
+        let files_count = self
+            .get(torrent_id)
+            .await
+            .map_err(|err| TorrentClientError(Box::from(err)))?
+            .files
+            .map_or(0, |files| files.len());
+        let unwanted_files_indexes = (0..files_count as i32)
+            .filter(|index| !selected_files_indexes.contains(index))
+            .collect::<Vec<_>>();
+
+        self.deselect_files(torrent_id, &unwanted_files_indexes)
+            .await
+            .map_err(|err| TorrentClientError(Box::from(err)))?;
+        TransmissionClient::select_files(self, torrent_id, &selected_files_indexes)
+            .await
+            .map_err(|err| TorrentClientError(Box::from(err)))?;
+
+        Ok(())
+    }
+
     async fn get_torrent(&self, torrent_id: &TorrentId) -> Result<Torrent, TorrentClientError> {
         let torrent = self
             .get(torrent_id)
             .await
             .map_err(|err| TorrentClientError(Box::from(err)))?;
 
+        let wanted = torrent.wanted.unwrap_or_default();
+
         Ok(Torrent {
             status: match torrent.status {
                 Some(transmission_rpc::types::TorrentStatus::Seeding) => TorrentStatus::Complete,
                 _ => TorrentStatus::Downloading,
             },
+            // Only the files selected for download are of interest.
             files: torrent
                 .files
                 .unwrap_or_default()
                 .into_iter()
-                .map(|f| f.name)
+                .enumerate()
+                .filter(|(index, _)| wanted.get(*index) != Some(&0))
+                .map(|(_, f)| f.name)
                 .collect(),
         })
     }
@@ -293,16 +380,106 @@ impl Into<TopicData> for search_providers::TopicData {
     fn into(self) -> TopicData {
         TopicData {
             title: self.title,
-            download_id: DownloadId(*self.download_id),
-            topic_id: TopicId(*self.topic_id),
+            download_id: DownloadId(self.download_id.to_string()),
+            topic_id: TopicId(self.topic_id.to_string()),
+            provider: "rutracker".to_string(),
+            info_hash: None,
+            seeders: Some(self.seeds_number),
+            leechers: Some(self.leeches_number),
+            size: Some(self.size),
+            category: Some(self.forum_name),
+            author: Some(self.author),
+            published_at: Some(self.added_at),
+            verified: Some(matches!(self.status, TopicStatus::Approved)),
+            score: None,
+            query: None,
+        }
+    }
+}
+
+/// Waits for the tracker rate limit are told apart, so the request shows it.
+fn to_search_provider_error(error: RuTrackerClientError) -> SearchProviderError {
+    match error {
+        RuTrackerClientError::DownloadQuotaExceeded(retry_after)
+        | RuTrackerClientError::RateLimited(retry_after) => {
+            SearchProviderError(Box::new(RateLimitedError { retry_after }))
         }
+        error => SearchProviderError(Box::new(error)),
     }
 }
 
 #[async_trait]
 impl SearchProviderTrait for RuTrackerClient {
-    async fn find_all(&self, query: &str) -> Result<Vec<TopicData>, SearchProviderError> {
-        self.search_music(query)
+    async fn find_all(&self, query: &SearchQuery) -> Result<Vec<TopicData>, SearchProviderError> {
+        self.search_music(&query.text)
+            .await
+            .map(|results| results.into_iter().map(Into::into).collect())
+            .map_err(to_search_provider_error)
+    }
+
+    async fn download_torrent(
+        &self,
+        topic: &TopicData,
+    ) -> Result<TorrentSource, SearchProviderError> {
+        let download_id = topic
+            .download_id
+            .parse()
+            .map_err(|error| SearchProviderError(Box::new(error)))?;
+
+        RuTrackerClient::download_torrent(&self, download_id)
+            .await
+            .map(TorrentSource::File)
+            .map_err(to_search_provider_error)
+    }
+
+    async fn get_tracklist(
+        &self,
+        topic: &TopicData,
+    ) -> Result<Option<Vec<String>>, SearchProviderError> {
+        let topic_id = topic
+            .topic_id
+            .parse()
+            .map_err(|error| SearchProviderError(Box::new(error)))?;
+
+        self.get_topic(topic_id)
+            .await
+            .map(|details| details.map(|details| details.tracklist))
+            .map_err(to_search_provider_error)
+    }
+
+    async fn check_connection(&self) -> Result<(), SearchProviderError> {
+        RuTrackerClient::check_connection(&self)
+            .await
+            .map_err(|error| SearchProviderError(Box::new(error)))
+    }
+}
+
+impl Into<TopicData> for search_providers::TorznabItem {
+    fn into(self) -> TopicData {
+        TopicData {
+            title: self.title,
+            // Magnet link is preferred as it doesn't need a roundtrip to the indexer.
+            download_id: DownloadId(self.magnet_url.or(self.link).unwrap_or_default()),
+            topic_id: TopicId(self.guid),
+            provider: "torznab".to_string(),
+            info_hash: self.info_hash,
+            seeders: Some(self.seeders),
+            size: self.size,
+            ..TopicData::default()
+        }
+    }
+}
+
+#[async_trait]
+impl SearchProviderTrait for TorznabClient {
+    async fn find_all(&self, query: &SearchQuery) -> Result<Vec<TopicData>, SearchProviderError> {
+        let query = TorznabQuery {
+            q: query.text.clone(),
+            artist: query.artist.clone(),
+            album: query.album.clone(),
+        };
+
+        self.search_music(&query)
             .await
             .map(|results| results.into_iter().map(Into::into).collect())
             .map_err(|error| SearchProviderError(Box::new(error)))
@@ -310,14 +487,150 @@ impl SearchProviderTrait for RuTrackerClient {
 
     async fn download_torrent(
         &self,
-        download_id: &DownloadId,
-    ) -> Result<Vec<u8>, SearchProviderError> {
-        RuTrackerClient::download_torrent(&self, **download_id)
+        topic: &TopicData,
+    ) -> Result<TorrentSource, SearchProviderError> {
+        if topic.download_id.starts_with("magnet:") {
+            return Ok(TorrentSource::Magnet(topic.download_id.to_string()));
+        }
+
+        match self.download(&topic.download_id).await {
+            Ok(TorznabDownload::File(data)) => Ok(TorrentSource::File(data)),
+            Ok(TorznabDownload::Magnet(magnet)) => Ok(TorrentSource::Magnet(magnet)),
+            Err(error) => Err(SearchProviderError(Box::new(error))),
+        }
+    }
+
+    async fn check_connection(&self) -> Result<(), SearchProviderError> {
+        TorznabClient::check_connection(&self)
             .await
             .map_err(|error| SearchProviderError(Box::new(error)))
     }
 }
 
+#[async_trait]
+impl SearchProviderTrait for CachedSearchProvider {
+    async fn find_all(&self, query: &SearchQuery) -> Result<Vec<TopicData>, SearchProviderError> {
+        if let Some(results) = self.get_search_results(query).await {
+            debug!(%query, "Using cached search results");
+            return Ok(results);
+        }
+
+        let results = self.provider.find_all(query).await?;
+        self.save_search_results(query, &results).await;
+
+        Ok(results)
+    }
+
+    async fn download_torrent(
+        &self,
+        topic: &TopicData,
+    ) -> Result<TorrentSource, SearchProviderError> {
+        if let Some(source) = self.get_torrent(topic).await {
+            debug!(download_id = &*topic.download_id, "Using cached torrent");
+            return Ok(source);
+        }
+
+        let source = self.provider.download_torrent(topic).await?;
+        self.save_torrent(topic, &source).await;
+
+        Ok(source)
+    }
+
+    async fn get_tracklist(
+        &self,
+        topic: &TopicData,
+    ) -> Result<Option<Vec<String>>, SearchProviderError> {
+        self.provider.get_tracklist(topic).await
+    }
+
+    async fn check_connection(&self) -> Result<(), SearchProviderError> {
+        self.provider.check_connection().await
+    }
+}
+
+#[async_trait]
+impl SearchProviderTrait for SearchProviderRegistry {
+    async fn find_all(&self, query: &SearchQuery) -> Result<Vec<TopicData>, SearchProviderError> {
+        // Errors are turned into strings as they aren't Send.
+        let results = join_all(self.providers().iter().map(|p| async move {
+            match p.provider.find_all(query).await {
+                Ok(results) => Ok(results
+                    .into_iter()
+                    .map(|topic| TopicData {
+                        provider: p.name.clone(),
+                        ..topic
+                    })
+                    .collect::<Vec<_>>()),
+                Err(error) => Err((error.retry_after(), format!("{}: {}", p.name, error))),
+            }
+        }))
+        .await;
+
+        let mut errors = vec![];
+        let mut found_results = vec![];
+        let mut retry_after = None;
+
+        for result in results {
+            match result {
+                Ok(results) => found_results.push(results),
+                Err((provider_retry_after, error)) => {
+                    warn!(%error, "Search provider failed");
+                    errors.push(error);
+                    retry_after = retry_after.max(provider_retry_after);
+                }
+            }
+        }
+
+        // Searching again after the wait is better than missing the results
+        // of the provider.
+        if let Some(retry_after) = retry_after {
+            return Err(SearchProviderError(Box::new(RateLimitedError {
+                retry_after,
+            })));
+        }
+
+        if found_results.is_empty() && !errors.is_empty() {
+            return Err(SearchProviderError(Box::from(errors.join("; "))));
+        }
+
+        Ok(merge_search_results(found_results))
+    }
+
+    async fn download_torrent(
+        &self,
+        topic: &TopicData,
+    ) -> Result<TorrentSource, SearchProviderError> {
+        match self.get(&topic.provider) {
+            Some(p) => p.provider.download_torrent(topic).await,
+            None => Err(SearchProviderError(Box::from(format!(
+                "Unknown search provider: {}",
+                topic.provider
+            )))),
+        }
+    }
+
+    async fn get_tracklist(
+        &self,
+        topic: &TopicData,
+    ) -> Result<Option<Vec<String>>, SearchProviderError> {
+        match self.get(&topic.provider) {
+            Some(p) => p.provider.get_tracklist(topic).await,
+            None => Err(SearchProviderError(Box::from(format!(
+                "Unknown search provider: {}",
+                topic.provider
+            )))),
+        }
+    }
+
+    async fn check_connection(&self) -> Result<(), SearchProviderError> {
+        for p in self.providers() {
+            p.provider.check_connection().await?;
+        }
+
+        Ok(())
+    }
+}
+
 impl Into<RadioManagerChannelTrack> for radio_manager_client::RadioManagerChannelTrack {
     fn into(self) -> RadioManagerChannelTrack {
         RadioManagerChannelTrack {
@@ -368,3 +681,70 @@ impl RadioManagerClientTrait for RadioManagerClient {
         Ok(tracks.into_iter().map(Into::into).collect())
     }
 }
+
+#[async_trait]
+impl LocalLibraryTrait for LocalLibrary {
+    async fn find_track(
+        &self,
+        metadata: &AudioMetadata,
+    ) -> Result<Option<String>, LocalLibraryError> {
+        Ok(LocalLibrary::find_track(self, metadata).map(|track| track.path))
+    }
+}
+
+#[async_trait]
+impl MetadataProviderTrait for MetadataIndex {
+    async fn find_recording(
+        &self,
+        metadata: &AudioMetadata,
+    ) -> Result<Option<AudioMetadata>, MetadataProviderError> {
+        Ok(MetadataIndex::find_recording(self, metadata))
+    }
+}
+
+#[async_trait]
+impl AudioSplitterTrait for Ffmpeg {
+    async fn split_track(
+        &self,
+        path_to_image_file: &str,
+        start: Duration,
+        end: Option<Duration>,
+        path_to_output_file: &str,
+    ) -> Result<(), AudioSplitterError> {
+        self.extract_range(path_to_image_file, start, end, path_to_output_file)
+            .await
+            .map_err(|error| AudioSplitterError(Box::new(error)))
+    }
+}
+
+#[async_trait]
+impl AudioProcessorTrait for AudioProcessor {
+    async fn process_audio_file(
+        &self,
+        request_id: &RequestId,
+        path_to_audio_file: &str,
+        tags: &AudioTags,
+    ) -> Result<String, AudioProcessorError> {
+        let file_stem = std::path::Path::new(path_to_audio_file)
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy();
+
+        self.process(
+            path_to_audio_file,
+            &format!("{}/{}", request_id, file_stem),
+            tags,
+        )
+        .await
+        .map_err(|error| AudioProcessorError(Box::new(error)))
+    }
+
+    async fn remove_processed_files(
+        &self,
+        request_id: &RequestId,
+    ) -> Result<(), AudioProcessorError> {
+        self.remove(&request_id.to_string())
+            .await
+            .map_err(|error| AudioProcessorError(Box::new(error)))
+    }
+}
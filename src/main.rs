@@ -1,15 +1,26 @@
 use crate::config::Config;
-use crate::services::track_request_processor::TrackRequestController;
+use crate::services::audio_processor::AudioProcessingOptions;
+use crate::services::ffmpeg::LoudnessTarget;
+use crate::services::query_planner::QueryPlanner;
+use crate::services::ranking::RankingPolicies;
+use crate::services::track_request_processor::{
+    FileSelectionOptions, SearchProviderTrait, TrackRequestController,
+};
 use crate::services::{
-    OpenAIService, RadioManagerClient, TrackRequestProcessor, TransmissionClient,
+    AudioProcessor, CachedSearchProvider, Ffmpeg, LocalLibrary, MetadataIndex, OpenAIService,
+    PersistentCookieJar, RadioManagerClient, SearchProviderRegistry, TrackRequestProcessor,
+    TransmissionClient,
 };
 use crate::storage::on_disk::OnDiskStorage;
 use actix_rt::signal::unix;
 use actix_web::web::Data;
 use actix_web::{web, App, HttpServer};
 use futures_lite::FutureExt;
+use reqwest::Proxy;
+use std::path::Path;
 use std::sync::Arc;
-use tracing::{debug, error, info};
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
 
 mod config;
 mod http;
@@ -38,14 +49,110 @@ async fn main() -> std::io::Result<()> {
         config.state_storage_directory.clone(),
     ));
 
-    debug!("Init rutracker client...");
-    let rutracker_client = Arc::from(
-        search_providers::RuTrackerClient::create(
+    let create_proxy = |url: &Option<String>| {
+        url.as_deref()
+            .map(|url| Proxy::all(url).expect("Invalid proxy URL"))
+    };
+
+    let open_cookie_jar = |name: &str| {
+        Arc::new(match &config.cookies_encryption_key {
+            Some(key) => PersistentCookieJar::open(
+                Path::new(&config.state_storage_directory)
+                    .join("cookies")
+                    .join(name),
+                key,
+            ),
+            None => PersistentCookieJar::in_memory(),
+        })
+    };
+
+    let search_cache_ttl = Duration::from_secs(config.search_cache_ttl);
+    if !search_cache_ttl.is_zero() {
+        match CachedSearchProvider::remove_expired(&state_storage, search_cache_ttl).await {
+            Ok(removed) => debug!("Removed {} expired search cache entries", removed),
+            Err(error) => warn!(?error, "Unable to remove expired search cache entries"),
+        }
+    }
+    // Every provider is cached on its own, so results of one provider being
+    // down aren't cached as if there was nothing to find.
+    let with_cache = |name: &str, provider: Arc<dyn SearchProviderTrait + Send + Sync>| {
+        let provider: Arc<dyn SearchProviderTrait + Send + Sync> = match search_cache_ttl.is_zero()
+        {
+            true => provider,
+            false => Arc::new(CachedSearchProvider::new(
+                name,
+                provider,
+                state_storage.clone(),
+                search_cache_ttl,
+            )),
+        };
+        provider
+    };
+
+    debug!("Init search providers...");
+    let mut search_provider_registry = SearchProviderRegistry::new();
+
+    let rutracker_client = if config.rutracker.enabled {
+        debug!("Init rutracker client...");
+        let rutracker_client = Arc::new(search_providers::RuTrackerClient::new(
             &config.rutracker.username,
             &config.rutracker.password,
-        )
-        .await
-        .expect("Unable to initialize RuTracker client"),
+            search_providers::RuTrackerConnectionOptions {
+                hosts: config.rutracker.hosts.clone(),
+                proxy: create_proxy(&config.rutracker.proxy),
+                rate_limit: search_providers::RuTrackerRateLimitOptions {
+                    requests_per_minute: config.rutracker.requests_per_minute,
+                    max_jitter: Duration::from_millis(config.rutracker.max_jitter_ms),
+                    daily_downloads: config.rutracker.daily_downloads,
+                    max_wait: Duration::from_millis(config.rutracker.max_wait_ms),
+                },
+            },
+            search_providers::RuTrackerSearchOptions {
+                forum_ids: config.rutracker.forum_ids.clone(),
+                max_pages: config.rutracker.max_pages,
+            },
+            open_cookie_jar("rutracker"),
+        ));
+
+        // Saved session is reused when it's still valid, otherwise it logs in.
+        // The bot keeps working with the other providers until an operator
+        // solves the captcha via the admin endpoint.
+        if let Err(error) = rutracker_client.check_connection().await {
+            warn!(
+                ?error,
+                "Unable to log in to RuTracker, searching it is unavailable until the session is established"
+            );
+        }
+
+        search_provider_registry.register(
+            "rutracker",
+            config.rutracker.priority,
+            with_cache("rutracker", rutracker_client.clone()),
+        );
+
+        Some(rutracker_client)
+    } else {
+        None
+    };
+
+    if config.torznab.enabled {
+        debug!("Init torznab client...");
+        let torznab_client = Arc::from(search_providers::TorznabClient::create(
+            &config.torznab.url,
+            &config.torznab.api_key,
+            config.torznab.categories.clone(),
+        ));
+        search_provider_registry.register(
+            "torznab",
+            config.torznab.priority,
+            with_cache("torznab", torznab_client),
+        );
+    }
+
+    let search_provider_registry = Arc::new(search_provider_registry);
+    info!(
+        "Enabled {} search provider(s)",
+        search_provider_registry.providers().len()
     );
 
     debug!("Init transmission client...");
@@ -54,6 +161,7 @@ async fn main() -> std::io::Result<()> {
         config.transmission.username.clone(),
         config.transmission.password.clone(),
         config.transmission.download_directory.clone(),
+        create_proxy(&config.transmission.proxy),
     ));
 
     debug!("Init radio manager client...");
@@ -62,20 +170,128 @@ async fn main() -> std::io::Result<()> {
             &config.radiomanager.endpoint,
             &config.radiomanager.username,
             &config.radiomanager.password,
+            open_cookie_jar("radiomanager"),
+            create_proxy(&config.radiomanager.proxy),
         )
         .await
         .expect("Unable to initialize RadioManager client"),
     );
 
+    debug!("Init metadata index...");
+    let metadata_index = match &config.metadata_index_path {
+        Some(path) => {
+            let metadata_index = MetadataIndex::load(path)
+                .await
+                .expect("Unable to load metadata index");
+            info!(
+                "Loaded {} recording(s) into metadata index",
+                metadata_index.len()
+            );
+            Some(Arc::new(metadata_index))
+        }
+        None => {
+            info!("Metadata index is not configured, requests can't be validated");
+            None
+        }
+    };
+
+    debug!("Init ranking policies...");
+    let ranking_policies = match &config.ranking_policies_path {
+        Some(path) => RankingPolicies::load(path)
+            .await
+            .expect("Unable to load ranking policies"),
+        None => RankingPolicies::default(),
+    };
+
+    debug!("Init query planner...");
+    let query_planner = match &config.query_planner_path {
+        Some(path) => QueryPlanner::load(path)
+            .await
+            .expect("Unable to load query planner settings"),
+        None => QueryPlanner::default(),
+    };
+
+    debug!("Init ffmpeg...");
+    let ffmpeg = Arc::new(Ffmpeg::create(config.ffmpeg_path.clone()));
+
+    debug!("Init local library...");
+    let local_library = config.local_library_path.clone().map(|path| {
+        let local_library = Arc::new(LocalLibrary::empty());
+
+        // Scanning a large library takes a while, so requests are served in
+        // the meantime, and rescans pick up the files added later.
+        let ffmpeg = ffmpeg.clone();
+        let rescan_interval = Duration::from_secs(config.local_library_rescan_interval);
+
+        actix_rt::spawn({
+            let local_library = local_library.clone();
+
+            async move {
+                loop {
+                    match local_library.scan(&path, &ffmpeg).await {
+                        Ok(()) => {
+                            info!("Indexed {} track(s) in local library", local_library.len())
+                        }
+                        Err(error) => warn!(?error, "Unable to scan local library"),
+                    }
+
+                    if rescan_interval.is_zero() {
+                        break;
+                    }
+
+                    actix_rt::time::sleep(rescan_interval).await;
+                }
+            }
+        });
+
+        local_library
+    });
+
+    debug!("Init audio processor...");
+    let audio_processor = Arc::new(AudioProcessor::create(
+        ffmpeg.clone(),
+        AudioProcessingOptions {
+            codec: config.audio_processing.codec,
+            bitrate: config.audio_processing.bitrate.clone(),
+            loudness_target: config.audio_processing.loudness_normalization.then(|| {
+                LoudnessTarget {
+                    integrated: config.audio_processing.loudness_target,
+                    true_peak: config.audio_processing.loudness_true_peak,
+                    range: config.audio_processing.loudness_range,
+                }
+            }),
+            output_directory: config.audio_processing.directory.clone(),
+        },
+    ));
+
     debug!("Init track request processor...");
     let track_request_processor = {
-        Arc::new(TrackRequestProcessor::new(
+        let mut track_request_processor = TrackRequestProcessor::new(
             state_storage.clone(),
-            rutracker_client.clone(),
+            search_provider_registry.clone(),
             transmission_client.clone(),
             radio_manager_client.clone(),
+            ffmpeg.clone(),
+            audio_processor.clone(),
             config.download_directory.clone(),
-        ))
+        )
+        .with_file_selection(FileSelectionOptions {
+            preference: config.file_selection.preference,
+            max_file_size: config.file_selection.max_file_size,
+        })
+        .with_ranking_policies(ranking_policies)
+        .with_query_planner(query_planner);
+
+        if let Some(local_library) = local_library {
+            track_request_processor = track_request_processor.with_local_library(local_library);
+        }
+
+        if let Some(metadata_index) = metadata_index {
+            track_request_processor =
+                track_request_processor.with_metadata_provider(metadata_index);
+        }
+
+        Arc::new(track_request_processor)
     };
 
     debug!("Init track request controller...");
@@ -86,7 +302,10 @@ async fn main() -> std::io::Result<()> {
     );
 
     debug!("Init OpenAI client...");
-    let openai_service = Arc::new(OpenAIService::create(config.openai_api_key.clone()));
+    let openai_service = Arc::new(OpenAIService::create(
+        config.openai_api_key.clone(),
+        create_proxy(&config.openai_proxy),
+    ));
 
     let shutdown_timeout = config.shutdown_timeout.clone();
     let bind_address = config.bind_address.clone();
@@ -100,12 +319,27 @@ async fn main() -> std::io::Result<()> {
                 .app_data(Data::new(Arc::clone(&openai_service)))
                 .app_data(Data::new(Arc::clone(&radio_manager_client)))
                 .app_data(Data::new(Arc::clone(&transmission_client)))
-                .app_data(Data::new(Arc::clone(&rutracker_client)))
+                .app_data(Data::new(Arc::clone(&search_provider_registry)))
+                .app_data(Data::new(rutracker_client.clone()))
                 .service(web::resource("/").route(web::get().to(http::get_track_request_statuses)))
                 .service(web::resource("/create").route(web::post().to(http::make_track_request)))
+                .service(
+                    web::resource("/requests/{request_id}")
+                        .route(web::get().to(http::get_track_request)),
+                )
+                .service(
+                    web::resource("/requests/{request_id}/override")
+                        .route(web::post().to(http::override_track_request)),
+                )
                 .service(
                     web::resource("/suggest").route(web::post().to(http::make_tracks_suggestion)),
                 )
+                .service(web::resource("/search").route(web::post().to(http::search)))
+                .service(
+                    web::resource("/admin/rutracker/captcha")
+                        .route(web::get().to(http::get_rutracker_captcha))
+                        .route(web::post().to(http::solve_rutracker_captcha)),
+                )
                 .route("/health/alive", web::get().to(http::readiness_check))
                 .route("/health/ready", web::get().to(http::readiness_check))
         }
@@ -0,0 +1,342 @@
+use crate::services::ffmpeg::{AudioStreamInfo, FfmpegError, LoudnessTarget, TranscodeOptions};
+use crate::services::track_request_processor::AudioTags;
+use crate::services::Ffmpeg;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::debug;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum AudioCodec {
+    Mp3,
+    Aac,
+    Opus,
+    Flac,
+}
+
+impl AudioCodec {
+    fn encoder(&self) -> &'static str {
+        match self {
+            AudioCodec::Mp3 => "libmp3lame",
+            AudioCodec::Aac => "aac",
+            AudioCodec::Opus => "libopus",
+            AudioCodec::Flac => "flac",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            AudioCodec::Mp3 => "mp3",
+            AudioCodec::Aac => "m4a",
+            AudioCodec::Opus => "opus",
+            AudioCodec::Flac => "flac",
+        }
+    }
+
+    fn is_lossless(&self) -> bool {
+        matches!(self, AudioCodec::Flac)
+    }
+}
+
+/// Encoders of the source codecs whose names differ from the codec, used
+/// when the audio is normalized without transcoding. APE has no encoder, so
+/// such audio is written as FLAC.
+const SOURCE_ENCODERS: [(&str, &str, Option<&str>); 4] = [
+    ("mp3", "libmp3lame", None),
+    ("opus", "libopus", None),
+    ("vorbis", "libvorbis", None),
+    ("ape", "flac", Some("flac")),
+];
+
+const LOSSLESS_CODECS: [&str; 5] = ["flac", "alac", "ape", "wavpack", "tta"];
+
+fn is_lossless_codec(codec: &str) -> bool {
+    LOSSLESS_CODECS.contains(&codec) || codec.starts_with("pcm_")
+}
+
+/// Opus is only encoded at 48 kHz.
+const OPUS_SAMPLE_RATE: u32 = 48000;
+
+/// Containers ffmpeg can embed cover art into.
+const COVER_ART_CONTAINERS: [&str; 3] = ["mp3", "flac", "m4a"];
+
+#[derive(Clone, Debug)]
+pub(crate) struct AudioProcessingOptions {
+    pub(crate) codec: Option<AudioCodec>,
+    pub(crate) bitrate: Option<String>,
+    pub(crate) loudness_target: Option<LoudnessTarget>,
+    pub(crate) output_directory: String,
+}
+
+pub(crate) struct AudioProcessor {
+    ffmpeg: Arc<Ffmpeg>,
+    options: AudioProcessingOptions,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum AudioProcessorError {
+    #[error(transparent)]
+    FfmpegError(#[from] FfmpegError),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+impl AudioProcessor {
+    pub(crate) fn create(ffmpeg: Arc<Ffmpeg>, options: AudioProcessingOptions) -> Self {
+        Self { ffmpeg, options }
+    }
+
+    /// Writes the tags into the working copy of the audio file named
+    /// `output_name` (without extension) in the output directory, transcoding
+    /// and normalizing it if configured. Returns the path to the working copy.
+    pub(crate) async fn process(
+        &self,
+        path_to_audio_file: &str,
+        output_name: &str,
+        tags: &AudioTags,
+    ) -> Result<String, AudioProcessorError> {
+        // Normalized audio is re-encoded, so it's written like the source
+        // unless another codec is configured.
+        let (source, audio_filter) = match &self.options.loudness_target {
+            Some(target) => {
+                debug!(path_to_audio_file, "Measuring loudness...");
+                let source = self.ffmpeg.probe_audio_stream(path_to_audio_file).await?;
+                let measurement = self
+                    .ffmpeg
+                    .measure_loudness(path_to_audio_file, target)
+                    .await?;
+                (
+                    Some(source),
+                    Some(Ffmpeg::loudness_filter(target, &measurement)),
+                )
+            }
+            None => (None, None),
+        };
+
+        let (output_path, options) = self.transcode_options(
+            path_to_audio_file,
+            output_name,
+            tags,
+            source.as_ref(),
+            audio_filter,
+        );
+
+        if let Some(parent) = Path::new(&output_path).parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        debug!(path_to_audio_file, output_path, "Writing audio file...");
+
+        self.ffmpeg
+            .transcode(path_to_audio_file, &output_path, &options)
+            .await?;
+
+        Ok(output_path)
+    }
+
+    /// Returns the path to the working copy along with the options it's
+    /// written with. Without normalization the audio stream is copied as is.
+    /// Otherwise it's re-encoded with the configured codec, or the codec and
+    /// bitrate of the source, at the source sample rate, as the `loudnorm`
+    /// filter upsamples to 192 kHz.
+    fn transcode_options(
+        &self,
+        path_to_audio_file: &str,
+        output_name: &str,
+        tags: &AudioTags,
+        source: Option<&AudioStreamInfo>,
+        audio_filter: Option<String>,
+    ) -> (String, TranscodeOptions) {
+        let source_extension = Path::new(path_to_audio_file)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let (extension, encoder, bitrate, sample_rate) = match (self.options.codec, source) {
+            (Some(codec), source) => (
+                codec.extension().to_string(),
+                codec.encoder().to_string(),
+                self.options
+                    .bitrate
+                    .clone()
+                    .filter(|_| !codec.is_lossless()),
+                source
+                    .and_then(|source| source.sample_rate)
+                    .map(|rate| match codec {
+                        AudioCodec::Opus => OPUS_SAMPLE_RATE,
+                        _ => rate,
+                    }),
+            ),
+            (None, Some(source)) => {
+                let (encoder, extension) = SOURCE_ENCODERS
+                    .iter()
+                    .find(|(codec, _, _)| *codec == source.codec)
+                    .map_or((source.codec.as_str(), None), |(_, encoder, extension)| {
+                        (*encoder, *extension)
+                    });
+
+                (
+                    extension.map_or(source_extension, String::from),
+                    encoder.to_string(),
+                    source
+                        .bitrate
+                        .clone()
+                        .filter(|_| !is_lossless_codec(&source.codec)),
+                    source.sample_rate,
+                )
+            }
+            (None, None) => (source_extension, "copy".to_string(), None, None),
+        };
+        let output_path = format!(
+            "{}/{}.{}",
+            self.options.output_directory, output_name, extension
+        );
+
+        let mut metadata = vec![
+            ("artist".to_string(), tags.metadata.artist.clone()),
+            ("title".to_string(), tags.metadata.title.clone()),
+            ("album".to_string(), tags.metadata.album.clone()),
+        ];
+        if let Some(track_number) = tags.track_number {
+            metadata.push(("track".to_string(), track_number.to_string()));
+        }
+        if let Some(year) = tags.year {
+            metadata.push(("date".to_string(), year.to_string()));
+        }
+
+        let options = TranscodeOptions {
+            encoder: Some(encoder),
+            bitrate,
+            sample_rate,
+            audio_filter,
+            tags: metadata,
+            cover_art_path: tags
+                .path_to_cover_art
+                .clone()
+                .filter(|_| COVER_ART_CONTAINERS.contains(&extension.as_str())),
+        };
+
+        (output_path, options)
+    }
+
+    /// Removes the working copies made under the `output_dir_name` directory.
+    pub(crate) async fn remove(&self, output_dir_name: &str) -> Result<(), AudioProcessorError> {
+        let path = format!("{}/{}", self.options.output_directory, output_dir_name);
+
+        match tokio::fs::remove_dir_all(path).await {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ffmpeg::transcode_args;
+
+    fn create_processor(codec: Option<AudioCodec>) -> AudioProcessor {
+        AudioProcessor::create(
+            Arc::new(Ffmpeg::create("ffmpeg".into())),
+            AudioProcessingOptions {
+                codec,
+                bitrate: Some("192k".into()),
+                loudness_target: Some(LoudnessTarget {
+                    integrated: -14.0,
+                    true_peak: -1.0,
+                    range: 11.0,
+                }),
+                output_directory: "processed".into(),
+            },
+        )
+    }
+
+    fn create_args(
+        processor: &AudioProcessor,
+        path_to_audio_file: &str,
+        source: &AudioStreamInfo,
+    ) -> Vec<String> {
+        let (output_path, options) = processor.transcode_options(
+            path_to_audio_file,
+            "1/track",
+            &AudioTags::default(),
+            Some(source),
+            Some("loudnorm".into()),
+        );
+
+        transcode_args(path_to_audio_file, &output_path, &options)
+    }
+
+    fn create_source(codec: &str, sample_rate: u32, bitrate: Option<&str>) -> AudioStreamInfo {
+        AudioStreamInfo {
+            codec: codec.into(),
+            sample_rate: Some(sample_rate),
+            bitrate: bitrate.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn test_normalizing_in_source_codec() {
+        let processor = create_processor(None);
+
+        assert_eq!(
+            [
+                "-i",
+                "track.m4a",
+                "-map",
+                "0:a",
+                "-map_metadata",
+                "-1",
+                "-af",
+                "loudnorm",
+                "-c:a",
+                "alac",
+                "-ar",
+                "44100",
+                "-metadata",
+                "artist=",
+                "-metadata",
+                "title=",
+                "-metadata",
+                "album=",
+                "processed/1/track.m4a",
+            ]
+            .map(String::from)
+            .to_vec(),
+            create_args(
+                &processor,
+                "track.m4a",
+                &create_source("alac", 44100, Some("1002k"))
+            )
+        );
+
+        let args = create_args(
+            &processor,
+            "track.mp3",
+            &create_source("mp3", 48000, Some("320k")),
+        );
+        assert!(args.windows(2).any(|pair| pair == ["-c:a", "libmp3lame"]));
+        assert!(args.windows(2).any(|pair| pair == ["-b:a", "320k"]));
+        assert!(args.windows(2).any(|pair| pair == ["-ar", "48000"]));
+
+        let args = create_args(&processor, "track.ape", &create_source("ape", 44100, None));
+        assert!(args.windows(2).any(|pair| pair == ["-c:a", "flac"]));
+        assert!(!args.contains(&"-b:a".to_string()));
+        assert_eq!(Some(&"processed/1/track.flac".to_string()), args.last());
+    }
+
+    #[test]
+    fn test_normalizing_in_configured_codec() {
+        let args = create_args(
+            &create_processor(Some(AudioCodec::Opus)),
+            "track.flac",
+            &create_source("flac", 44100, None),
+        );
+
+        assert!(args.windows(2).any(|pair| pair == ["-c:a", "libopus"]));
+        assert!(args.windows(2).any(|pair| pair == ["-b:a", "192k"]));
+        assert!(args.windows(2).any(|pair| pair == ["-ar", "48000"]));
+        assert_eq!(Some(&"processed/1/track.opus".to_string()), args.last());
+    }
+}
@@ -0,0 +1,183 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use cookie_store::{Cookie, CookieStore};
+use reqwest::header::HeaderValue;
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use tracing::{debug, warn};
+
+const NONCE_SIZE: usize = 12;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum CookieJarError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error("Unable to encrypt or decrypt cookies")]
+    CipherFailure,
+}
+
+struct CookieJarFile {
+    path: PathBuf,
+    cipher: Aes256Gcm,
+}
+
+impl CookieJarFile {
+    fn read(&self) -> Result<Option<CookieStore>, CookieJarError> {
+        let data = match std::fs::read(&self.path) {
+            Ok(data) => data,
+            Err(error) if matches!(error.kind(), std::io::ErrorKind::NotFound) => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+
+        if data.len() < NONCE_SIZE {
+            return Err(CookieJarError::CipherFailure);
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_SIZE);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| CookieJarError::CipherFailure)?;
+        let cookies: Vec<Cookie<'static>> = serde_json::from_slice(&plaintext)?;
+
+        Ok(CookieStore::from_cookies(cookies.into_iter().map(Ok::<_, Infallible>), false).ok())
+    }
+
+    fn write(&self, store: &CookieStore) -> Result<(), CookieJarError> {
+        // Session cookies are kept too, the saved session is validated on start.
+        let plaintext = serde_json::to_vec(&store.iter_unexpired().collect::<Vec<_>>())?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| CookieJarError::CipherFailure)?;
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let temp_path = self.path.with_extension("tmp");
+        std::fs::write(&temp_path, [nonce.as_slice(), &ciphertext].concat())?;
+        std::fs::rename(temp_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+/// Cookie jar of an HTTP client that is saved to disk, encrypted, whenever
+/// the server sets cookies, so sessions survive restarts.
+pub(crate) struct PersistentCookieJar {
+    store: RwLock<CookieStore>,
+    file: Option<CookieJarFile>,
+}
+
+impl PersistentCookieJar {
+    /// Jar that isn't saved anywhere.
+    pub(crate) fn in_memory() -> Self {
+        Self {
+            store: RwLock::new(CookieStore::default()),
+            file: None,
+        }
+    }
+
+    /// Loads the cookies saved at the path, starting with an empty jar when
+    /// they can't be read, e.g. because the key has changed.
+    pub(crate) fn open(path: impl Into<PathBuf>, key: &str) -> Self {
+        let file = CookieJarFile {
+            path: path.into(),
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&Sha256::digest(key))),
+        };
+
+        let store = match file.read() {
+            Ok(Some(store)) => {
+                debug!(path = ?file.path, "Loaded saved cookies");
+                store
+            }
+            Ok(None) => CookieStore::default(),
+            Err(error) => {
+                warn!(?error, path = ?file.path, "Unable to load saved cookies");
+                CookieStore::default()
+            }
+        };
+
+        Self {
+            store: RwLock::new(store),
+            file: Some(file),
+        }
+    }
+}
+
+impl reqwest::cookie::CookieStore for PersistentCookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let mut store = self.store.write().expect("Unable to lock cookie store");
+
+        for header in cookie_headers {
+            if let Ok(cookie) = header.to_str() {
+                let _ = store.parse(cookie, url);
+            }
+        }
+
+        // Written synchronously as the HTTP client calls this only for the
+        // responses setting cookies, which is mostly the login.
+        if let Some(file) = &self.file {
+            if let Err(error) = file.write(&store) {
+                warn!(?error, path = ?file.path, "Unable to save cookies");
+            }
+        }
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let store = self.store.read().expect("Unable to lock cookie store");
+        let value = store
+            .get_request_values(url)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        if value.is_empty() {
+            return None;
+        }
+
+        HeaderValue::from_str(&value).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::cookie::CookieStore as _;
+
+    fn set_cookie(jar: &PersistentCookieJar, cookie: &str, url: &Url) {
+        let header = HeaderValue::from_str(cookie).unwrap();
+        jar.set_cookies(&mut std::iter::once(&header), url);
+    }
+
+    #[test]
+    fn test_saving_cookies_encrypted() {
+        let path = std::env::temp_dir().join(format!("cookies-{}", uuid::Uuid::new_v4()));
+        let url = Url::parse("https://rutracker.net/forum/login.php").unwrap();
+
+        let jar = PersistentCookieJar::open(&path, "secret");
+        set_cookie(&jar, "bb_session=0-12345-abcdef; path=/forum/", &url);
+        set_cookie(&jar, "bb_t=a%3A0; path=/forum/; Max-Age=0", &url);
+
+        let saved = std::fs::read(&path).unwrap();
+        assert!(!String::from_utf8_lossy(&saved).contains("abcdef"));
+
+        let reopened = PersistentCookieJar::open(&path, "secret");
+        assert_eq!(
+            Some(HeaderValue::from_static("bb_session=0-12345-abcdef")),
+            reopened.cookies(&url)
+        );
+
+        let with_wrong_key = PersistentCookieJar::open(&path, "other secret");
+        assert_eq!(None, with_wrong_key.cookies(&url));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
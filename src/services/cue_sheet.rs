@@ -0,0 +1,231 @@
+use encoding_rs::WINDOWS_1251;
+use std::time::Duration;
+
+const FRAMES_PER_SECOND: u64 = 75;
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct CueTrack {
+    pub(crate) number: u32,
+    pub(crate) title: String,
+    pub(crate) performer: Option<String>,
+    pub(crate) file: String,
+    pub(crate) start: Duration,
+    pub(crate) end: Option<Duration>,
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub(crate) struct CueSheet {
+    pub(crate) performer: Option<String>,
+    pub(crate) title: Option<String>,
+    pub(crate) tracks: Vec<CueTrack>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum CueSheetError {
+    #[error("Invalid index time: {0}")]
+    InvalidIndexTime(String),
+    #[error("Track {0} is defined before any FILE command")]
+    MissingFile(u32),
+}
+
+fn unquote(value: &str) -> String {
+    value.trim().trim_matches('"').to_string()
+}
+
+/// Parses `mm:ss:ff` index time where `ff` is a frame (1/75 of a second).
+fn parse_index_time(value: &str) -> Result<Duration, CueSheetError> {
+    let parts = value
+        .split(':')
+        .map(|part| part.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| CueSheetError::InvalidIndexTime(value.to_string()))?;
+
+    match parts[..] {
+        [minutes, seconds, frames] => Ok(Duration::from_millis(
+            (minutes * 60 + seconds) * 1000 + frames * 1000 / FRAMES_PER_SECOND,
+        )),
+        _ => Err(CueSheetError::InvalidIndexTime(value.to_string())),
+    }
+}
+
+/// Splits the command line into the keyword and its arguments, e.g.
+/// `FILE "Album.flac" WAVE` into `("FILE", "\"Album.flac\" WAVE")`.
+fn split_command(line: &str) -> (String, &str) {
+    let line = line.trim();
+
+    match line.split_once(char::is_whitespace) {
+        Some((command, args)) => (command.to_uppercase(), args.trim()),
+        None => (line.to_uppercase(), ""),
+    }
+}
+
+/// Strips the trailing file type from the FILE command arguments.
+fn parse_file_name(args: &str) -> String {
+    if let Some(quoted) = args.strip_prefix('"') {
+        match quoted.find('"') {
+            Some(end) => quoted[..end].to_string(),
+            None => unquote(args),
+        }
+    } else {
+        match args.rsplit_once(char::is_whitespace) {
+            Some((name, _file_type)) => name.to_string(),
+            None => args.to_string(),
+        }
+    }
+}
+
+/// Cue sheets of the older rips are mostly saved in CP1251 rather than UTF-8.
+pub(crate) fn decode_cue_sheet(contents: &[u8]) -> String {
+    match std::str::from_utf8(contents) {
+        Ok(contents) => contents.to_string(),
+        Err(_) => WINDOWS_1251
+            .decode_without_bom_handling(contents)
+            .0
+            .into_owned(),
+    }
+}
+
+pub(crate) fn parse_cue_sheet(contents: &str) -> Result<CueSheet, CueSheetError> {
+    let mut sheet = CueSheet::default();
+    let mut current_file: Option<String> = None;
+    let mut current_track: Option<CueTrack> = None;
+
+    for line in contents.trim_start_matches('\u{feff}').lines() {
+        let (command, args) = split_command(line);
+
+        match command.as_str() {
+            "FILE" => {
+                current_file.replace(parse_file_name(args));
+            }
+            "TRACK" => {
+                if let Some(track) = current_track.take() {
+                    sheet.tracks.push(track);
+                }
+                let number = args
+                    .split_whitespace()
+                    .next()
+                    .and_then(|n| n.parse::<u32>().ok())
+                    .unwrap_or_default();
+                let file = current_file
+                    .clone()
+                    .ok_or(CueSheetError::MissingFile(number))?;
+
+                current_track.replace(CueTrack {
+                    number,
+                    title: String::new(),
+                    performer: None,
+                    file,
+                    start: Duration::ZERO,
+                    end: None,
+                });
+            }
+            "TITLE" => match current_track.as_mut() {
+                Some(track) => track.title = unquote(args),
+                None => {
+                    sheet.title.replace(unquote(args));
+                }
+            },
+            "PERFORMER" => match current_track.as_mut() {
+                Some(track) => {
+                    track.performer.replace(unquote(args));
+                }
+                None => {
+                    sheet.performer.replace(unquote(args));
+                }
+            },
+            "INDEX" => {
+                let mut parts = args.split_whitespace();
+                if let (Some("01"), Some(time), Some(track)) =
+                    (parts.next(), parts.next(), current_track.as_mut())
+                {
+                    track.start = parse_index_time(time)?;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if let Some(track) = current_track.take() {
+        sheet.tracks.push(track);
+    }
+
+    // Each track lasts until the next track within the same file starts.
+    let starts = sheet
+        .tracks
+        .iter()
+        .map(|t| (t.file.clone(), t.start))
+        .collect::<Vec<_>>();
+    for (index, track) in sheet.tracks.iter_mut().enumerate() {
+        track.end = starts
+            .get(index + 1)
+            .filter(|(file, _)| file == &track.file)
+            .map(|(_, start)| *start);
+    }
+
+    Ok(sheet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoding_cp1251_cue_sheet() {
+        let (contents, _, _) = WINDOWS_1251.encode(
+            "PERFORMER \"Тед Айренс\"\n\
+             FILE \"Жизнь в зеркале.flac\" WAVE\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   TITLE \"Воскресный завтрак\"\n\
+             \x20   INDEX 01 00:00:00\n",
+        );
+        assert!(std::str::from_utf8(&contents).is_err());
+
+        let sheet = parse_cue_sheet(&decode_cue_sheet(&contents)).unwrap();
+
+        assert_eq!(Some("Тед Айренс".to_string()), sheet.performer);
+        assert_eq!("Воскресный завтрак", sheet.tracks[0].title);
+        assert_eq!("Жизнь в зеркале.flac", sheet.tracks[0].file);
+    }
+
+    #[test]
+    fn test_parsing_cue_sheet() {
+        let sheet = parse_cue_sheet(include_str!(
+            "../../tests/fixtures/Ted Irens - Life @ Mirror.cue"
+        ))
+        .unwrap();
+
+        assert_eq!(Some("Ted Irens".to_string()), sheet.performer);
+        assert_eq!(Some("Life @ Mirror".to_string()), sheet.title);
+        assert_eq!(3, sheet.tracks.len());
+        assert_eq!(
+            CueTrack {
+                number: 1,
+                title: "Sunday Breakfast".into(),
+                performer: Some("Ted Irens".into()),
+                file: "Ted Irens - Life @ Mirror.flac".into(),
+                start: Duration::ZERO,
+                end: Some(Duration::from_millis(312_400)),
+            },
+            sheet.tracks[0]
+        );
+        assert_eq!(
+            CueTrack {
+                number: 3,
+                title: "Another Moon Night".into(),
+                performer: Some("Ted Irens".into()),
+                file: "Ted Irens - Life @ Mirror.flac".into(),
+                start: Duration::from_millis(624_133),
+                end: None,
+            },
+            sheet.tracks[2]
+        );
+    }
+
+    #[test]
+    fn test_parsing_invalid_index_time() {
+        let result =
+            parse_cue_sheet("FILE \"a.flac\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:xx:00");
+
+        assert!(matches!(result, Err(CueSheetError::InvalidIndexTime(_))));
+    }
+}
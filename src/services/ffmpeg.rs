@@ -0,0 +1,435 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::process::Output;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::debug;
+
+pub(crate) struct Ffmpeg {
+    binary: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum FfmpegError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("ffmpeg exited with {status}: {stderr}")]
+    Failed { status: String, stderr: String },
+    #[error("Unable to parse loudness measurement: {0}")]
+    LoudnessMeasurement(String),
+    #[error("Unable to find audio stream: {0}")]
+    AudioStream(String),
+}
+
+/// Audio stream of the input file as reported by ffmpeg.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AudioStreamInfo {
+    /// Codec name, e.g. `flac` or `mp3`.
+    pub(crate) codec: String,
+    pub(crate) sample_rate: Option<u32>,
+    /// Bitrate in ffmpeg notation, e.g. `320k`.
+    pub(crate) bitrate: Option<String>,
+}
+
+/// Loudness measured by the first pass of the `loudnorm` filter.
+#[derive(Debug, PartialEq, Deserialize)]
+pub(crate) struct LoudnessMeasurement {
+    pub(crate) input_i: String,
+    pub(crate) input_tp: String,
+    pub(crate) input_lra: String,
+    pub(crate) input_thresh: String,
+    pub(crate) target_offset: String,
+}
+
+/// EBU R128 loudness normalization target.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LoudnessTarget {
+    pub(crate) integrated: f64,
+    pub(crate) true_peak: f64,
+    pub(crate) range: f64,
+}
+
+impl LoudnessTarget {
+    fn filter(&self) -> String {
+        format!(
+            "loudnorm=I={}:TP={}:LRA={}",
+            self.integrated, self.true_peak, self.range
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct TranscodeOptions {
+    pub(crate) encoder: Option<String>,
+    pub(crate) bitrate: Option<String>,
+    pub(crate) sample_rate: Option<u32>,
+    pub(crate) audio_filter: Option<String>,
+    pub(crate) tags: Vec<(String, String)>,
+    pub(crate) cover_art_path: Option<String>,
+}
+
+/// Parses the first audio stream of the input, e.g.
+/// `Stream #0:0: Audio: mp3 (mp3float), 44100 Hz, stereo, fltp, 320 kb/s`.
+fn parse_audio_stream_info(stderr: &str) -> Option<AudioStreamInfo> {
+    let line = stderr
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("Stream #0:") && line.contains("Audio: "))?;
+    let (_, description) = line.split_once("Audio: ")?;
+    let mut parts = description.split(", ");
+    let codec = parts.next()?.split_whitespace().next()?.to_string();
+    let mut sample_rate = None;
+    let mut bitrate = None;
+
+    for part in parts {
+        if let Some(rate) = part.strip_suffix(" Hz") {
+            sample_rate = rate.trim().parse().ok();
+        } else if let Some((rate, _)) = part.split_once(" kb/s") {
+            bitrate = Some(format!("{}k", rate.trim()));
+        }
+    }
+
+    Some(AudioStreamInfo {
+        codec,
+        sample_rate,
+        bitrate,
+    })
+}
+
+/// Arguments of ffmpeg writing the input into the output with the options.
+pub(crate) fn transcode_args(
+    input_path: &str,
+    output_path: &str,
+    options: &TranscodeOptions,
+) -> Vec<String> {
+    let mut args = ["-i", input_path].map(String::from).to_vec();
+
+    if let Some(cover_art_path) = &options.cover_art_path {
+        args.extend(["-i".to_string(), cover_art_path.clone()]);
+    }
+
+    args.extend(["-map", "0:a", "-map_metadata", "-1"].map(String::from));
+
+    if options.cover_art_path.is_some() {
+        args.extend(
+            [
+                "-map",
+                "1:v",
+                "-c:v",
+                "copy",
+                "-disposition:v",
+                "attached_pic",
+            ]
+            .map(String::from),
+        );
+    }
+
+    if let Some(filter) = &options.audio_filter {
+        args.extend(["-af".to_string(), filter.clone()]);
+    }
+
+    if let Some(encoder) = &options.encoder {
+        args.extend(["-c:a".to_string(), encoder.clone()]);
+    }
+
+    if let Some(bitrate) = &options.bitrate {
+        args.extend(["-b:a".to_string(), bitrate.clone()]);
+    }
+
+    if let Some(sample_rate) = options.sample_rate {
+        args.extend(["-ar".to_string(), sample_rate.to_string()]);
+    }
+
+    for (key, value) in &options.tags {
+        args.extend(["-metadata".to_string(), format!("{}={}", key, value)]);
+    }
+
+    args.push(output_path.to_string());
+
+    args
+}
+
+/// The `loudnorm` filter prints its measurement as the last JSON object of
+/// the ffmpeg log.
+fn parse_loudness_measurement(stderr: &str) -> Result<LoudnessMeasurement, FfmpegError> {
+    let json = stderr
+        .rfind('{')
+        .and_then(|start| Some(&stderr[start..start + stderr[start..].find('}')? + 1]))
+        .ok_or_else(|| FfmpegError::LoudnessMeasurement(stderr.to_string()))?;
+
+    serde_json::from_str(json).map_err(|error| FfmpegError::LoudnessMeasurement(error.to_string()))
+}
+
+/// Parses the global section of the `ffmetadata` output, e.g.
+/// `title=Sunday Breakfast`. Special characters are escaped with backslash.
+fn parse_ffmetadata(contents: &str) -> HashMap<String, String> {
+    let mut tags = HashMap::new();
+    let mut lines = contents.lines();
+
+    while let Some(line) = lines.next() {
+        if line.starts_with('[') {
+            // Stream and chapter sections follow the global one.
+            break;
+        }
+        if line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+
+        let mut line = line.to_string();
+        // Escaped newline continues the value on the next line.
+        while line.ends_with('\\') && !line.ends_with("\\\\") {
+            line.pop();
+            line.push('\n');
+            line.push_str(lines.next().unwrap_or_default());
+        }
+
+        let mut key = String::new();
+        let mut value = String::new();
+        let mut has_separator = false;
+        let mut chars = line.chars();
+
+        while let Some(c) = chars.next() {
+            let c = match c {
+                '\\' => chars.next().unwrap_or(c),
+                '=' if !has_separator => {
+                    has_separator = true;
+                    continue;
+                }
+                c => c,
+            };
+
+            if has_separator {
+                value.push(c);
+            } else {
+                key.push(c);
+            }
+        }
+
+        if has_separator && !value.trim().is_empty() {
+            tags.insert(key.to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    tags
+}
+
+impl Ffmpeg {
+    pub(crate) fn create(binary: String) -> Self {
+        Self { binary }
+    }
+
+    async fn execute(&self, args: &[String]) -> Result<Output, FfmpegError> {
+        debug!(?args, "Running ffmpeg...");
+
+        let output = Command::new(&self.binary)
+            .args(["-hide_banner", "-nostdin", "-y"])
+            .args(args)
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(FfmpegError::Failed {
+                status: output.status.to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            });
+        }
+
+        Ok(output)
+    }
+
+    async fn run(&self, args: &[String]) -> Result<String, FfmpegError> {
+        let output = self.execute(args).await?;
+
+        Ok(String::from_utf8_lossy(&output.stderr).to_string())
+    }
+
+    /// Reads the global tags of the audio file, keyed by lowercase name.
+    pub(crate) async fn read_tags(
+        &self,
+        input_path: &str,
+    ) -> Result<HashMap<String, String>, FfmpegError> {
+        let args = ["-i", input_path, "-f", "ffmetadata", "-"].map(String::from);
+        let output = self.execute(&args).await?;
+
+        Ok(parse_ffmetadata(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Cuts the `[start, end)` range out of the audio image into a standalone
+    /// lossless file. Missing `end` means until the end of the image.
+    pub(crate) async fn extract_range(
+        &self,
+        input_path: &str,
+        start: Duration,
+        end: Option<Duration>,
+        output_path: &str,
+    ) -> Result<(), FfmpegError> {
+        let mut args = vec![
+            "-i".to_string(),
+            input_path.to_string(),
+            "-ss".to_string(),
+            format!("{:.3}", start.as_secs_f64()),
+        ];
+
+        if let Some(end) = end {
+            args.push("-to".to_string());
+            args.push(format!("{:.3}", end.as_secs_f64()));
+        }
+
+        args.extend(
+            ["-map", "0:a", "-c:a", "flac", output_path]
+                .into_iter()
+                .map(String::from),
+        );
+
+        self.run(&args).await?;
+
+        Ok(())
+    }
+
+    /// Describes the first audio stream of the input without decoding it.
+    pub(crate) async fn probe_audio_stream(
+        &self,
+        input_path: &str,
+    ) -> Result<AudioStreamInfo, FfmpegError> {
+        let args = [
+            "-i", input_path, "-map", "0:a:0", "-t", "0", "-f", "null", "-",
+        ]
+        .map(String::from);
+        let stderr = self.run(&args).await?;
+
+        parse_audio_stream_info(&stderr).ok_or(FfmpegError::AudioStream(stderr))
+    }
+
+    pub(crate) async fn measure_loudness(
+        &self,
+        input_path: &str,
+        target: &LoudnessTarget,
+    ) -> Result<LoudnessMeasurement, FfmpegError> {
+        let args = [
+            "-i",
+            input_path,
+            "-map",
+            "0:a",
+            "-af",
+            &format!("{}:print_format=json", target.filter()),
+            "-f",
+            "null",
+            "-",
+        ]
+        .map(String::from);
+
+        parse_loudness_measurement(&self.run(&args).await?)
+    }
+
+    /// Returns the second pass `loudnorm` filter which applies the linear
+    /// gain computed from the measurement.
+    pub(crate) fn loudness_filter(
+        target: &LoudnessTarget,
+        measurement: &LoudnessMeasurement,
+    ) -> String {
+        format!(
+            "{}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+            target.filter(),
+            measurement.input_i,
+            measurement.input_tp,
+            measurement.input_lra,
+            measurement.input_thresh,
+            measurement.target_offset
+        )
+    }
+
+    /// Re-encodes (or copies, with the `copy` encoder) the audio stream of the
+    /// input file dropping all existing tags and cover art in favour of the
+    /// given ones.
+    pub(crate) async fn transcode(
+        &self,
+        input_path: &str,
+        output_path: &str,
+        options: &TranscodeOptions,
+    ) -> Result<(), FfmpegError> {
+        self.run(&transcode_args(input_path, output_path, options))
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_loudness_measurement() {
+        let stderr = r#"
+[Parsed_loudnorm_0 @ 0x55d0c8e0c640]
+{
+	"input_i" : "-9.61",
+	"input_tp" : "0.52",
+	"input_lra" : "5.30",
+	"input_thresh" : "-19.79",
+	"output_i" : "-23.64",
+	"output_tp" : "-9.45",
+	"output_lra" : "4.90",
+	"output_thresh" : "-33.78",
+	"normalization_type" : "dynamic",
+	"target_offset" : "0.64"
+}
+"#;
+
+        assert_eq!(
+            LoudnessMeasurement {
+                input_i: "-9.61".into(),
+                input_tp: "0.52".into(),
+                input_lra: "5.30".into(),
+                input_thresh: "-19.79".into(),
+                target_offset: "0.64".into(),
+            },
+            parse_loudness_measurement(stderr).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parsing_ffmetadata() {
+        let contents = ";FFMETADATA1\nTITLE=Sunday Breakfast\nartist=Ted Irens\nALBUM=Life \\= Mirror\ncomment=First\\\nSecond\nencoder=\n[CHAPTER]\ntitle=Chapter 1\n";
+
+        assert_eq!(
+            HashMap::from([
+                ("title".to_string(), "Sunday Breakfast".to_string()),
+                ("artist".to_string(), "Ted Irens".to_string()),
+                ("album".to_string(), "Life = Mirror".to_string()),
+                ("comment".to_string(), "First\nSecond".to_string()),
+            ]),
+            parse_ffmetadata(contents)
+        );
+    }
+
+    #[test]
+    fn test_parsing_audio_stream_info() {
+        let stderr = "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'track.m4a':\n  Duration: 00:05:12.40, start: 0.000000, bitrate: 1006 kb/s\n  Stream #0:0[0x1](und): Audio: alac (alac / 0x63616C61), 44100 Hz, stereo, s16p, 1002 kb/s (default)\n  Stream #0:1[0x0]: Video: mjpeg (Baseline), yuvj420p, 500x500, 90k tbn (attached pic)\n";
+
+        assert_eq!(
+            Some(AudioStreamInfo {
+                codec: "alac".into(),
+                sample_rate: Some(44100),
+                bitrate: Some("1002k".into()),
+            }),
+            parse_audio_stream_info(stderr)
+        );
+        assert_eq!(
+            Some(AudioStreamInfo {
+                codec: "flac".into(),
+                sample_rate: Some(96000),
+                bitrate: None,
+            }),
+            parse_audio_stream_info("  Stream #0:0: Audio: flac, 96000 Hz, stereo, s32 (24 bit)")
+        );
+    }
+
+    #[test]
+    fn test_parsing_missing_loudness_measurement() {
+        assert!(matches!(
+            parse_loudness_measurement("Output file is empty, nothing was encoded"),
+            Err(FfmpegError::LoudnessMeasurement(_))
+        ));
+    }
+}
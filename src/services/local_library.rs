@@ -0,0 +1,340 @@
+use crate::services::ffmpeg::FfmpegError;
+use crate::services::metadata_index::normalize;
+use crate::services::track_request_processor::{is_audio_file, AudioMetadata};
+use crate::services::Ffmpeg;
+use futures::StreamExt;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::{debug, warn};
+
+/// Tags of this many files are read at once while scanning.
+const SCAN_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LibraryTrack {
+    pub(crate) path: String,
+    pub(crate) metadata: AudioMetadata,
+}
+
+type TrackIndex = HashMap<(String, String), Vec<LibraryTrack>>;
+
+/// Audio files already available locally, e.g. on a NAS. Tracks are
+/// identified by their embedded tags, falling back to the directory layout
+/// when the tags are missing.
+pub(crate) struct LocalLibrary {
+    tracks: RwLock<TrackIndex>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum LocalLibraryError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    FfmpegError(#[from] FfmpegError),
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Strips the track number from file names like "01. Title" or "01 - Title".
+fn strip_track_number(name: &str) -> &str {
+    let digits = name.chars().take_while(char::is_ascii_digit).count();
+
+    if digits == 0 || digits > 3 {
+        return name;
+    }
+
+    let rest = name[digits..].trim_start_matches(['.', ' ', '-', '_']);
+
+    if rest.is_empty() {
+        name
+    } else {
+        rest
+    }
+}
+
+/// Guesses the metadata from the `Artist/Album/01. Title.flac` or
+/// `Artist - Album/01. Artist - Title.flac` layout.
+fn parse_metadata_from_path(path: &Path) -> AudioMetadata {
+    let title = file_stem(path);
+    let album_dir = path.parent().map(file_stem).unwrap_or_default();
+    let artist_dir = path
+        .parent()
+        .and_then(Path::parent)
+        .map(file_stem)
+        .unwrap_or_default();
+
+    let (mut artist, album) = match album_dir.split_once(" - ") {
+        Some((artist, album)) => (artist.trim().to_string(), album.trim().to_string()),
+        None => (artist_dir, album_dir.clone()),
+    };
+
+    let title = match strip_track_number(&title).split_once(" - ") {
+        Some((track_artist, title)) => {
+            artist = track_artist.trim().to_string();
+            title.trim().to_string()
+        }
+        None => strip_track_number(&title).trim().to_string(),
+    };
+
+    AudioMetadata {
+        title,
+        artist,
+        album,
+    }
+}
+
+/// Tags take precedence over the metadata guessed from the path.
+fn merge_tags(metadata: AudioMetadata, mut tags: HashMap<String, String>) -> AudioMetadata {
+    AudioMetadata {
+        title: tags.remove("title").unwrap_or(metadata.title),
+        artist: tags
+            .remove("artist")
+            .or_else(|| tags.remove("album_artist"))
+            .unwrap_or(metadata.artist),
+        album: tags.remove("album").unwrap_or(metadata.album),
+    }
+}
+
+/// Symlinked directories are followed, as NAS shares are often laid out
+/// with them. Every directory is walked once, even if linked in a loop.
+async fn find_audio_files(root: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut files = vec![];
+    let mut directories = vec![root.to_path_buf()];
+    let mut visited = HashSet::new();
+
+    while let Some(directory) = directories.pop() {
+        if !visited.insert(tokio::fs::canonicalize(&directory).await?) {
+            continue;
+        }
+
+        let mut entries = tokio::fs::read_dir(&directory).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+
+            match tokio::fs::metadata(&path).await {
+                Ok(metadata) if metadata.is_dir() => directories.push(path),
+                Ok(_) if is_audio_file(&path.to_string_lossy()) => files.push(path),
+                Ok(_) => {}
+                Err(error) => warn!(?error, ?path, "Unable to read the library entry"),
+            }
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+async fn read_track(path: PathBuf, ffmpeg: &Ffmpeg) -> LibraryTrack {
+    let path_str = path.to_string_lossy().to_string();
+    let metadata = parse_metadata_from_path(&path);
+    let metadata = match ffmpeg.read_tags(&path_str).await {
+        Ok(tags) => merge_tags(metadata, tags),
+        Err(error) => {
+            warn!(?error, path_str, "Unable to read tags of the audio file");
+            metadata
+        }
+    };
+
+    debug!(path_str, "Indexed local track - {}", metadata);
+
+    LibraryTrack {
+        path: path_str,
+        metadata,
+    }
+}
+
+fn index_tracks(tracks: Vec<LibraryTrack>) -> TrackIndex {
+    let mut indexed = TrackIndex::new();
+
+    for track in tracks {
+        indexed
+            .entry((
+                normalize(&track.metadata.artist),
+                normalize(&track.metadata.title),
+            ))
+            .or_default()
+            .push(track);
+    }
+
+    indexed
+}
+
+impl LocalLibrary {
+    pub(crate) fn empty() -> Self {
+        Self::index(vec![])
+    }
+
+    pub(crate) fn index(tracks: Vec<LibraryTrack>) -> Self {
+        Self {
+            tracks: RwLock::new(index_tracks(tracks)),
+        }
+    }
+
+    /// Walks the directory tree reading the tags of every audio file, a few
+    /// at a time. Lookups use the previous index until the scan is done.
+    pub(crate) async fn scan(&self, root: &str, ffmpeg: &Ffmpeg) -> Result<(), LocalLibraryError> {
+        let tracks = futures::stream::iter(find_audio_files(Path::new(root)).await?)
+            .map(|path| read_track(path, ffmpeg))
+            .buffered(SCAN_CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await;
+
+        *self.tracks.write().expect("Unable to lock local library") = index_tracks(tracks);
+
+        Ok(())
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.tracks
+            .read()
+            .expect("Unable to lock local library")
+            .values()
+            .map(Vec::len)
+            .sum()
+    }
+
+    /// Looks up the track ignoring case and punctuation. When the track
+    /// appears on several albums, the requested album is preferred.
+    pub(crate) fn find_track(&self, metadata: &AudioMetadata) -> Option<LibraryTrack> {
+        let tracks = self.tracks.read().expect("Unable to lock local library");
+        let candidates = tracks.get(&(normalize(&metadata.artist), normalize(&metadata.title)))?;
+        let album = normalize(&metadata.album);
+
+        candidates
+            .iter()
+            .find(|candidate| !album.is_empty() && normalize(&candidate.metadata.album) == album)
+            .or_else(|| candidates.first())
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_track(path: &str, artist: &str, title: &str, album: &str) -> LibraryTrack {
+        LibraryTrack {
+            path: path.into(),
+            metadata: AudioMetadata {
+                title: title.into(),
+                artist: artist.into(),
+                album: album.into(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_parsing_metadata_from_path() {
+        assert_eq!(
+            AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Life @ Mirror".into(),
+            },
+            parse_metadata_from_path(Path::new(
+                "/music/Ted Irens/Life @ Mirror/01. Sunday Breakfast.flac"
+            ))
+        );
+        assert_eq!(
+            AudioMetadata {
+                title: "Children".into(),
+                artist: "Robert Miles".into(),
+                album: "Dreamland".into(),
+            },
+            parse_metadata_from_path(Path::new(
+                "/music/Various - Dreamland/02 - Robert Miles - Children.mp3"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_preferring_tags_over_path() {
+        let metadata = merge_tags(
+            parse_metadata_from_path(Path::new("/music/Unknown/Untitled/01. Track 01.flac")),
+            HashMap::from([
+                ("title".to_string(), "Sunday Breakfast".to_string()),
+                ("album_artist".to_string(), "Ted Irens".to_string()),
+            ]),
+        );
+
+        assert_eq!(
+            AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Untitled".into(),
+            },
+            metadata
+        );
+    }
+
+    #[test]
+    fn test_finding_track() {
+        let library = LocalLibrary::index(vec![
+            create_track("/music/a.mp3", "Ted Irens", "Sunday Breakfast", "Singles"),
+            create_track(
+                "/music/b.flac",
+                "Ted Irens",
+                "Sunday Breakfast",
+                "Life @ Mirror",
+            ),
+            create_track("/music/c.flac", "Ted Irens", "Rising Star", "Rising Star"),
+        ]);
+
+        assert_eq!(3, library.len());
+        assert_eq!(
+            Some("/music/b.flac".to_string()),
+            library
+                .find_track(&AudioMetadata {
+                    title: "sunday breakfast".into(),
+                    artist: "TED IRENS".into(),
+                    album: "Life @ Mirror".into(),
+                })
+                .map(|track| track.path)
+        );
+        assert_eq!(
+            Some("/music/a.mp3".to_string()),
+            library
+                .find_track(&AudioMetadata {
+                    title: "Sunday Breakfast".into(),
+                    artist: "Ted Irens".into(),
+                    album: "".into(),
+                })
+                .map(|track| track.path)
+        );
+        assert_eq!(
+            None,
+            library.find_track(&AudioMetadata {
+                title: "Another Moon Night".into(),
+                artist: "Ted Irens".into(),
+                album: "".into(),
+            })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_finding_audio_files_in_symlinked_directories() {
+        let root = std::env::temp_dir().join(format!("local-library-{}", uuid::Uuid::new_v4()));
+        let shares = root.join("shares");
+        std::fs::create_dir_all(shares.join("Ted Irens")).unwrap();
+        std::fs::write(shares.join("Ted Irens/01. Sunday Breakfast.flac"), b"").unwrap();
+        std::fs::write(shares.join("Ted Irens/cover.jpg"), b"").unwrap();
+        std::fs::create_dir_all(root.join("music")).unwrap();
+        std::os::unix::fs::symlink(shares.join("Ted Irens"), root.join("music/Ted Irens")).unwrap();
+        // Loops are only walked once.
+        std::os::unix::fs::symlink(root.join("music"), root.join("music/loop")).unwrap();
+
+        assert_eq!(
+            vec![root.join("music/Ted Irens/01. Sunday Breakfast.flac")],
+            find_audio_files(&root.join("music")).await.unwrap()
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
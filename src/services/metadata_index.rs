@@ -0,0 +1,170 @@
+use crate::services::track_request_processor::AudioMetadata;
+use std::collections::HashMap;
+
+/// Offline index of known recordings, loaded from a tab-separated dump
+/// (for example exported from MusicBrainz) with `artist`, `title` and
+/// `album` columns.
+pub(crate) struct MetadataIndex {
+    recordings: HashMap<(String, String), Vec<AudioMetadata>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum MetadataIndexError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+pub(crate) fn normalize(value: &str) -> String {
+    value
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl MetadataIndex {
+    pub(crate) async fn load(path: &str) -> Result<Self, MetadataIndexError> {
+        let contents = tokio::fs::read_to_string(path).await?;
+
+        Ok(Self::parse(&contents))
+    }
+
+    pub(crate) fn parse(contents: &str) -> Self {
+        let mut recordings: HashMap<(String, String), Vec<AudioMetadata>> = HashMap::new();
+
+        for line in contents.lines() {
+            let mut columns = line.split('\t').map(str::trim);
+            let (artist, title) = match (columns.next(), columns.next()) {
+                (Some(artist), Some(title)) if !artist.is_empty() && !title.is_empty() => {
+                    (artist, title)
+                }
+                _ => continue,
+            };
+            let album = columns.next().unwrap_or_default();
+
+            recordings
+                .entry((normalize(artist), normalize(title)))
+                .or_default()
+                .push(AudioMetadata {
+                    title: title.to_string(),
+                    artist: artist.to_string(),
+                    album: album.to_string(),
+                });
+        }
+
+        Self { recordings }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.recordings.values().map(Vec::len).sum()
+    }
+
+    /// Looks up the recording matching the given metadata, ignoring case and
+    /// punctuation, and returns its canonical spelling. When the recording
+    /// appears on several albums, the requested album is preferred. An album
+    /// the index doesn't know is kept as requested, and a missing one is
+    /// filled in.
+    pub(crate) fn find_recording(&self, metadata: &AudioMetadata) -> Option<AudioMetadata> {
+        let candidates = self
+            .recordings
+            .get(&(normalize(&metadata.artist), normalize(&metadata.title)))?;
+        let album = normalize(&metadata.album);
+
+        if album.is_empty() {
+            return candidates.first().cloned();
+        }
+
+        match candidates
+            .iter()
+            .find(|candidate| normalize(&candidate.album) == album)
+        {
+            Some(candidate) => Some(candidate.clone()),
+            None => candidates.first().map(|candidate| AudioMetadata {
+                album: metadata.album.clone(),
+                ..candidate.clone()
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_index() -> MetadataIndex {
+        MetadataIndex::parse(include_str!("../../tests/fixtures/recordings.tsv"))
+    }
+
+    #[test]
+    fn test_loading_recordings() {
+        assert_eq!(4, create_index().len());
+    }
+
+    #[test]
+    fn test_canonicalizing_spelling() {
+        let recording = create_index().find_recording(&AudioMetadata {
+            title: "sunday  breakfast".into(),
+            artist: "TED IRENS".into(),
+            album: "".into(),
+        });
+
+        assert_eq!(
+            Some(AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Life @ Mirror".into(),
+            }),
+            recording
+        );
+    }
+
+    #[test]
+    fn test_preferring_requested_album() {
+        let recording = create_index().find_recording(&AudioMetadata {
+            title: "Children".into(),
+            artist: "Robert Miles".into(),
+            album: "dreamland".into(),
+        });
+
+        assert_eq!(
+            Some(AudioMetadata {
+                title: "Children".into(),
+                artist: "Robert Miles".into(),
+                album: "Dreamland".into(),
+            }),
+            recording
+        );
+    }
+
+    #[test]
+    fn test_keeping_unknown_album() {
+        let recording = create_index().find_recording(&AudioMetadata {
+            title: "sunday breakfast".into(),
+            artist: "ted irens".into(),
+            album: "Summer Compilation".into(),
+        });
+
+        assert_eq!(
+            Some(AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Summer Compilation".into(),
+            }),
+            recording
+        );
+    }
+
+    #[test]
+    fn test_unknown_recording() {
+        let recording = create_index().find_recording(&AudioMetadata {
+            title: "Not A Real Song".into(),
+            artist: "Ted Irens".into(),
+            album: "".into(),
+        });
+
+        assert_eq!(None, recording);
+    }
+}
@@ -1,6 +1,9 @@
 pub(crate) mod transmission_client;
 pub(crate) use transmission_client::*;
 
+pub(crate) mod cookie_jar;
+pub(crate) use cookie_jar::PersistentCookieJar;
+
 pub(crate) mod radio_manager_client;
 pub(crate) use radio_manager_client::*;
 
@@ -11,3 +14,27 @@ pub(crate) mod track_request_processor;
 pub(crate) use track_request_processor::TrackRequestProcessor;
 
 pub(crate) mod torrent_parser;
+
+pub(crate) mod metadata_index;
+pub(crate) use metadata_index::MetadataIndex;
+
+pub(crate) mod cue_sheet;
+
+pub(crate) mod ffmpeg;
+pub(crate) use ffmpeg::Ffmpeg;
+
+pub(crate) mod audio_processor;
+pub(crate) use audio_processor::AudioProcessor;
+
+pub(crate) mod local_library;
+pub(crate) use local_library::LocalLibrary;
+
+pub(crate) mod ranking;
+
+pub(crate) mod query_planner;
+
+pub(crate) mod search_provider_registry;
+pub(crate) use search_provider_registry::SearchProviderRegistry;
+
+pub(crate) mod search_cache;
+pub(crate) use search_cache::CachedSearchProvider;
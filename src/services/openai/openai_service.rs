@@ -1,5 +1,5 @@
 use crate::services::track_request_processor::AudioMetadata;
-use reqwest::Client;
+use reqwest::{Client, Proxy};
 
 const OPENAI_ENDPOINT: &str = "https://api.openai.com";
 
@@ -15,10 +15,14 @@ pub(crate) enum OpenAIServiceError {
 }
 
 impl OpenAIService {
-    pub(crate) fn create(openai_api_key: String) -> Self {
-        let client = Client::builder()
-            .build()
-            .expect("Failed to create HTTP Client");
+    pub(crate) fn create(openai_api_key: String, proxy: Option<Proxy>) -> Self {
+        let mut builder = Client::builder();
+
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(proxy);
+        }
+
+        let client = builder.build().expect("Failed to create HTTP Client");
 
         Self {
             openai_api_key,
@@ -0,0 +1,322 @@
+use crate::services::metadata_index::normalize;
+use crate::services::ranking::DISCOGRAPHY_TEXTS;
+use crate::services::track_request_processor::{AudioMetadata, SearchQuery, TopicData};
+use crate::utils::contains_ignore_case;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+const FEATURING_MARKERS: [&str; 5] = [" feat. ", " feat ", " ft. ", " featuring ", " при уч. "];
+/// Bracketed parts of titles with these words are dropped, e.g. "(Original Mix)".
+const TAG_WORDS: [&str; 10] = [
+    "mix",
+    "remix",
+    "edit",
+    "feat",
+    "featuring",
+    "ft",
+    "version",
+    "remaster",
+    "remastered",
+    "single",
+];
+/// Albums of singles are often named like "Title - Single".
+const SINGLE_SUFFIX: &str = " - single";
+const COMPILATION_TEXTS: [&str; 4] = ["compilation", "сборник", "greatest hits", "best of"];
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum QueryPlannerError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+/// Cuts off the featured artists, e.g. "Artist feat. Someone" becomes "Artist".
+fn strip_featuring(value: &str) -> &str {
+    let lowercase = value.to_lowercase();
+
+    FEATURING_MARKERS
+        .iter()
+        .filter_map(|marker| lowercase.find(marker))
+        .min()
+        // Lowercase of some characters differs in length, then nothing is cut.
+        .and_then(|index| value.get(..index))
+        .unwrap_or(value)
+}
+
+/// Whether the bracketed part of a title is a tag. Only whole words are
+/// matched, so e.g. "(Credits)" or "(Singles Collection)" are kept.
+fn is_tag(group: &str) -> bool {
+    group
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .any(|word| {
+            TAG_WORDS
+                .iter()
+                .any(|tag_word| word.to_lowercase() == *tag_word)
+        })
+}
+
+/// Drops remix and featuring tags, e.g. "Title (Original Mix) [feat. X]"
+/// becomes "Title".
+pub(crate) fn strip_tags(value: &str) -> String {
+    let mut result = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find(['(', '[']) {
+        let closing = if rest[start..].starts_with('(') {
+            ')'
+        } else {
+            ']'
+        };
+        let Some(length) = rest[start..].find(closing) else {
+            break;
+        };
+        let group = &rest[start..start + length + 1];
+
+        result.push_str(&rest[..start]);
+
+        if !is_tag(group) {
+            result.push_str(group);
+        }
+
+        rest = &rest[start + length + 1..];
+    }
+
+    result.push_str(rest);
+
+    let result = strip_featuring(&result)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let result = match result.to_lowercase().strip_suffix(SINGLE_SUFFIX) {
+        Some(stripped) => result.get(..stripped.len()).unwrap_or(&result).to_string(),
+        None => result,
+    };
+
+    result.trim_end_matches(" -").to_string()
+}
+
+fn is_compilation(album: &str) -> bool {
+    COMPILATION_TEXTS
+        .iter()
+        .any(|text| contains_ignore_case(album, text))
+}
+
+/// Builds the search queries of a track request from whatever metadata it has.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub(crate) struct QueryPlanner {
+    /// Other names of the artists, e.g. `{"Ted Irens": ["Тед Айренс"]}`,
+    /// searched after the name from the metadata.
+    #[serde(default)]
+    aliases: HashMap<String, Vec<String>>,
+}
+
+impl QueryPlanner {
+    pub(crate) async fn load(path: &str) -> Result<Self, QueryPlannerError> {
+        let contents = tokio::fs::read_to_string(path).await?;
+
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    fn aliases_of(&self, artist: &str) -> &[String] {
+        let artist = normalize(artist);
+
+        self.aliases
+            .iter()
+            .find(|(name, _)| normalize(name) == artist)
+            .map_or(&[], |(_, aliases)| aliases.as_slice())
+    }
+
+    /// The release is searched by the title instead of the album for singles,
+    /// compilations and requests without the album.
+    fn searches_by_title(&self, metadata: &AudioMetadata) -> bool {
+        let album = strip_tags(&metadata.album);
+
+        album.is_empty()
+            || normalize(&album) == normalize(&strip_tags(&metadata.title))
+            || is_compilation(&album)
+    }
+
+    /// Queries in order they should be tried: the release by every name of
+    /// the artist first, then discographies. Artist and album are only given
+    /// when the release is searched by the album, otherwise the providers
+    /// search by the text.
+    pub(crate) fn plan(&self, metadata: &AudioMetadata) -> Vec<SearchQuery> {
+        let artist = strip_featuring(&metadata.artist).trim().to_string();
+        let searches_by_title = self.searches_by_title(metadata);
+        let release = match searches_by_title {
+            true => strip_tags(&metadata.title),
+            false => strip_tags(&metadata.album),
+        };
+
+        let mut names = vec![artist.clone()];
+        names.extend(self.aliases_of(&artist).iter().cloned());
+
+        let mut queries = vec![];
+
+        for name in &names {
+            queries.push(SearchQuery {
+                text: match release.is_empty() {
+                    true => name.clone(),
+                    false => format!("{} - {}", name, release),
+                },
+                artist: (!searches_by_title).then(|| name.clone()),
+                album: (!searches_by_title).then(|| release.clone()),
+            });
+        }
+
+        for name in &names {
+            for text in DISCOGRAPHY_TEXTS {
+                queries.push(SearchQuery {
+                    text: format!("{} {}", name, text),
+                    artist: None,
+                    album: None,
+                });
+            }
+        }
+
+        let mut seen = HashSet::new();
+        queries.retain(|query| seen.insert(normalize(&query.text)));
+
+        queries
+    }
+
+    /// Whether the topic is the very release searched for, so there is no
+    /// need to look further, e.g. into discographies.
+    pub(crate) fn is_good_candidate(&self, topic: &TopicData, metadata: &AudioMetadata) -> bool {
+        let release = match self.searches_by_title(metadata) {
+            true => strip_tags(&metadata.title),
+            false => strip_tags(&metadata.album),
+        };
+
+        !release.is_empty() && contains_ignore_case(&topic.title, &release)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(queries: &[SearchQuery]) -> Vec<&str> {
+        queries.iter().map(|query| query.text.as_str()).collect()
+    }
+
+    fn create_metadata(artist: &str, title: &str, album: &str) -> AudioMetadata {
+        AudioMetadata {
+            title: title.into(),
+            artist: artist.into(),
+            album: album.into(),
+        }
+    }
+
+    #[test]
+    fn test_stripping_tags() {
+        assert_eq!(
+            "Sunday Breakfast",
+            strip_tags("Sunday Breakfast (Original Mix)")
+        );
+        assert_eq!("Dreamland", strip_tags("Dreamland [feat. Maria Nayler]"));
+        assert_eq!("Fable", strip_tags("Fable feat. Fabiola"));
+        assert_eq!("Life (Live)", strip_tags("Life (Live) [Radio Edit]"));
+        assert_eq!("Fable", strip_tags("Fable (2011 Remastered)"));
+    }
+
+    #[test]
+    fn test_keeping_brackets_without_tag_words() {
+        assert_eq!("Intro (Credits)", strip_tags("Intro (Credits)"));
+        assert_eq!(
+            "Hits (Singles Collection)",
+            strip_tags("Hits (Singles Collection)")
+        );
+        assert_eq!(
+            "Fable (Remixed by Someone)",
+            strip_tags("Fable (Remixed by Someone)")
+        );
+    }
+
+    #[test]
+    fn test_planning_album_queries() {
+        let planner = QueryPlanner::default();
+        let queries = planner.plan(&create_metadata(
+            "Ted Irens feat. Someone",
+            "Sunday Breakfast (Original Mix)",
+            "Foo",
+        ));
+
+        assert_eq!(
+            vec![
+                "Ted Irens - Foo",
+                "Ted Irens дискография",
+                "Ted Irens discography",
+                "Ted Irens дискографія",
+            ],
+            texts(&queries)
+        );
+        assert_eq!(Some("Ted Irens".into()), queries[0].artist);
+        assert_eq!(Some("Foo".into()), queries[0].album);
+        assert_eq!(None, queries[1].album);
+    }
+
+    #[test]
+    fn test_planning_title_queries_for_singles_and_album_less_requests() {
+        let planner = QueryPlanner::default();
+        let query = SearchQuery {
+            text: "Ted Irens - Rising Star".into(),
+            artist: None,
+            album: None,
+        };
+
+        assert_eq!(
+            query,
+            planner.plan(&create_metadata(
+                "Ted Irens",
+                "Rising Star",
+                "Rising Star - Single"
+            ))[0]
+        );
+        assert_eq!(
+            query,
+            planner.plan(&create_metadata(
+                "Ted Irens",
+                "Rising Star (Radio Edit)",
+                ""
+            ))[0]
+        );
+        assert_eq!(
+            query,
+            planner.plan(&create_metadata(
+                "Ted Irens",
+                "Rising Star",
+                "Greatest Hits"
+            ))[0]
+        );
+    }
+
+    #[test]
+    fn test_planning_queries_with_aliases() {
+        let planner: QueryPlanner =
+            serde_json::from_str(r#"{"aliases": {"ted irens": ["Тед Айренс"]}}"#).unwrap();
+
+        let queries = planner.plan(&create_metadata("Ted Irens", "Sunday Breakfast", "Foo"));
+
+        assert_eq!(
+            vec!["Ted Irens - Foo", "Тед Айренс - Foo"],
+            texts(&queries[..2])
+        );
+        assert_eq!(8, queries.len());
+    }
+
+    #[test]
+    fn test_finding_good_candidates() {
+        let planner = QueryPlanner::default();
+        let metadata = create_metadata("Ted Irens", "Sunday Breakfast", "Foo");
+        let topic = |title: &str| TopicData {
+            title: title.into(),
+            ..TopicData::default()
+        };
+
+        assert!(planner.is_good_candidate(&topic("Ted Irens - Foo [FLAC]"), &metadata));
+        assert!(!planner.is_good_candidate(&topic("Ted Irens - Discography"), &metadata));
+    }
+}
@@ -1,11 +1,14 @@
 use crate::services::track_request_processor::{
     RadioManagerChannelId, RadioManagerLinkId, RadioManagerTrackId,
 };
+use reqwest::cookie::CookieStore;
 use reqwest::redirect::Policy;
-use reqwest::{multipart, Body, Client, Error};
+use reqwest::{multipart, Body, Client, Error, Proxy};
 use serde::Deserialize;
 use std::path::Path;
+use std::sync::Arc;
 use tokio_util::codec::{BytesCodec, FramedRead};
+use tracing::info;
 
 pub(crate) struct RadioManagerClient {
     endpoint: String,
@@ -83,19 +86,42 @@ pub(crate) struct RadioManagerTrack {
 }
 
 impl RadioManagerClient {
-    pub(crate) async fn create(
+    /// Reuses the session from the cookie store when it's still valid,
+    /// otherwise logs in.
+    pub(crate) async fn create<C: CookieStore + 'static>(
         endpoint: &str,
         username: &str,
         password: &str,
+        cookie_store: Arc<C>,
+        proxy: Option<Proxy>,
     ) -> Result<Self, RadioManagerClientError> {
-        let client = Client::builder()
+        let mut builder = Client::builder()
             .redirect(Policy::limited(10))
-            .cookie_store(true)
-            .build()
-            .expect("Failed to create HTTP Client");
+            .cookie_provider(cookie_store);
 
-        client
-            .post(format!("{}api/v2/user/login", endpoint))
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(proxy);
+        }
+
+        let client = builder.build().expect("Failed to create HTTP Client");
+        let client = Self {
+            endpoint: endpoint.into(),
+            client,
+        };
+
+        if client.check_connection().await.is_ok() {
+            info!("Reusing saved RadioManager session");
+            return Ok(client);
+        }
+
+        client.login(username, password).await?;
+
+        Ok(client)
+    }
+
+    async fn login(&self, username: &str, password: &str) -> Result<(), RadioManagerClientError> {
+        self.client
+            .post(format!("{}api/v2/user/login", self.endpoint))
             .form(&serde_json::json!({
                 "login": username.to_string(),
                 "password": password.to_string(),
@@ -108,10 +134,7 @@ impl RadioManagerClient {
             .await?
             .error_for_code()?;
 
-        Ok(Self {
-            endpoint: endpoint.into(),
-            client,
-        })
+        Ok(())
     }
 
     pub(crate) async fn upload_track(
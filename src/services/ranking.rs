@@ -0,0 +1,431 @@
+use crate::services::track_request_processor::{AudioMetadata, RadioManagerChannelId, TopicData};
+use crate::utils::contains_ignore_case;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tracing::debug;
+
+const SINGLE_IMAGE_RIP_TEXT: &str = "image+.cue";
+const LOSSLESS_TEXT: &str = "lossless";
+pub(crate) const DISCOGRAPHY_TEXTS: [&str; 3] = ["дискография", "discography", "дискографія"];
+
+fn default_preferred_formats() -> Vec<String> {
+    ["FLAC", "MP3", "ALAC", "AAC"].map(String::from).to_vec()
+}
+
+fn default_prefer_single_album() -> bool {
+    true
+}
+
+/// How the found topics are ordered before trying them one by one.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub(crate) struct RankingPolicy {
+    /// Audio formats in order of preference, e.g. `["FLAC", "MP3"]`.
+    #[serde(default = "default_preferred_formats")]
+    pub(crate) preferred_formats: Vec<String>,
+    /// Lossy releases of a lower bitrate (in kbps) are skipped.
+    #[serde(default)]
+    pub(crate) min_bitrate: Option<u32>,
+    /// Topics with fewer seeders are skipped.
+    #[serde(default)]
+    pub(crate) min_seeders: u64,
+    #[serde(default = "default_prefer_single_album")]
+    pub(crate) prefer_single_album: bool,
+    /// Discographies larger than this (in bytes) go after everything else.
+    #[serde(default)]
+    pub(crate) max_discography_size: Option<u64>,
+}
+
+impl Default for RankingPolicy {
+    fn default() -> Self {
+        Self {
+            preferred_formats: default_preferred_formats(),
+            min_bitrate: None,
+            min_seeders: 0,
+            prefer_single_album: default_prefer_single_album(),
+            max_discography_size: None,
+        }
+    }
+}
+
+/// Ranking policies loaded from a JSON file like
+/// `{"default": {...}, "channels": {"42": {...}}}`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub(crate) struct RankingPolicies {
+    #[serde(default)]
+    default: RankingPolicy,
+    #[serde(default)]
+    channels: HashMap<u64, RankingPolicy>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum RankingPoliciesError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+impl RankingPolicies {
+    pub(crate) async fn load(path: &str) -> Result<Self, RankingPoliciesError> {
+        let contents = tokio::fs::read_to_string(path).await?;
+
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub(crate) fn for_channel(&self, channel_id: &RadioManagerChannelId) -> &RankingPolicy {
+        self.channels.get(&**channel_id).unwrap_or(&self.default)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ScoreComponent {
+    pub(crate) reason: String,
+    pub(crate) points: i64,
+}
+
+/// Score of the topic along with the explanation of how it was computed.
+/// Topics with a higher score are tried first.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct TopicScore {
+    pub(crate) total: i64,
+    pub(crate) components: Vec<ScoreComponent>,
+}
+
+impl TopicScore {
+    fn add(&mut self, reason: String, points: i64) {
+        self.total += points;
+        self.components.push(ScoreComponent { reason, points });
+    }
+}
+
+/// Finds the bitrate in titles like "Artist - Album - 1996, MP3, 320 kbps".
+fn parse_bitrate(title: &str) -> Option<u32> {
+    let words = title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>();
+
+    words
+        .windows(2)
+        .find(|pair| pair[1].eq_ignore_ascii_case("kbps"))
+        .and_then(|pair| pair[0].parse().ok())
+}
+
+fn is_discography(title: &str) -> bool {
+    DISCOGRAPHY_TEXTS
+        .iter()
+        .any(|text| contains_ignore_case(title, text))
+}
+
+/// Returns `None` if the topic doesn't satisfy the policy at all.
+pub(crate) fn score_topic(
+    topic: &TopicData,
+    metadata: &AudioMetadata,
+    policy: &RankingPolicy,
+) -> Option<TopicScore> {
+    let mut score = TopicScore::default();
+
+    if let Some(seeders) = topic.seeders {
+        if seeders < policy.min_seeders {
+            debug!(topic.title, seeders, "Not enough seeders");
+            return None;
+        }
+
+        match seeders {
+            0 => score.add("no seeders".into(), -100),
+            seeders => score.add(
+                format!("{} seeders", seeders),
+                (seeders.min(30) / 10) as i64 * 5,
+            ),
+        }
+    }
+
+    if let Some((index, format)) = policy
+        .preferred_formats
+        .iter()
+        .enumerate()
+        .find(|(_, format)| contains_ignore_case(&topic.title, format))
+    {
+        score.add(
+            format!("{} format", format),
+            (policy.preferred_formats.len() - index) as i64 * 10,
+        );
+    }
+
+    if contains_ignore_case(&topic.title, LOSSLESS_TEXT) {
+        score.add("lossless".into(), 30);
+    } else if let Some(bitrate) = parse_bitrate(&topic.title) {
+        if policy.min_bitrate.is_some_and(|min| bitrate < min) {
+            debug!(topic.title, bitrate, "Bitrate is too low");
+            return None;
+        }
+
+        score.add(format!("{} kbps", bitrate), bitrate as i64 / 32);
+    }
+
+    let is_discography = is_discography(&topic.title);
+
+    if policy.prefer_single_album {
+        if is_discography {
+            score.add("discography".into(), -20);
+        } else if !metadata.album.is_empty() && contains_ignore_case(&topic.title, &metadata.album)
+        {
+            score.add("requested album".into(), 20);
+        }
+    }
+
+    if let (true, Some(size), Some(max_size)) =
+        (is_discography, topic.size, policy.max_discography_size)
+    {
+        if size > max_size {
+            score.add(format!("discography of {} bytes", size), -500);
+        }
+    }
+
+    // Single image rips need to be split after download, so they're only
+    // tried when no release with separate tracks has the requested track.
+    if contains_ignore_case(&topic.title, SINGLE_IMAGE_RIP_TEXT) {
+        score.add("single image rip".into(), -1000);
+    }
+
+    Some(score)
+}
+
+/// Drops the topics seen earlier in the list, either by the same provider
+/// or, going by the info hash, by another one.
+fn dedup_topics(topics: Vec<TopicData>) -> Vec<TopicData> {
+    let mut seen_topic_ids = HashSet::new();
+    let mut seen_info_hashes = HashSet::new();
+
+    topics
+        .into_iter()
+        .filter(|topic| {
+            let is_new_topic =
+                seen_topic_ids.insert((topic.provider.clone(), topic.topic_id.clone()));
+            let is_new_torrent = match &topic.info_hash {
+                Some(info_hash) => seen_info_hashes.insert(info_hash.to_lowercase()),
+                None => true,
+            };
+
+            is_new_topic && is_new_torrent
+        })
+        .collect()
+}
+
+/// Orders the topics by score, best first, dropping those not satisfying the
+/// policy and the duplicates, e.g. found by several queries. Topics of the
+/// same score keep their order, so the best ranked duplicate is kept.
+pub(crate) fn rank_topics(
+    topics: Vec<TopicData>,
+    metadata: &AudioMetadata,
+    policy: &RankingPolicy,
+) -> Vec<TopicData> {
+    let mut ranked = topics
+        .into_iter()
+        .filter_map(|topic| {
+            let score = score_topic(&topic, metadata, policy)?;
+
+            Some(TopicData {
+                score: Some(score),
+                ..topic
+            })
+        })
+        .collect::<Vec<_>>();
+
+    ranked.sort_by_key(|topic| -topic.score.as_ref().map_or(0, |score| score.total));
+
+    dedup_topics(ranked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::track_request_processor::{DownloadId, TopicId};
+
+    fn create_topic(id: &str, title: &str, seeders: Option<u64>, size: Option<u64>) -> TopicData {
+        TopicData {
+            topic_id: TopicId(id.into()),
+            download_id: DownloadId(id.into()),
+            title: title.into(),
+            seeders,
+            size,
+            ..TopicData::default()
+        }
+    }
+
+    fn create_metadata() -> AudioMetadata {
+        AudioMetadata {
+            title: "Children".into(),
+            artist: "Robert Miles".into(),
+            album: "Dreamland".into(),
+        }
+    }
+
+    fn get_ids(topics: &[TopicData]) -> Vec<&str> {
+        topics.iter().map(|topic| &*topic.topic_id).collect()
+    }
+
+    #[test]
+    fn test_parsing_bitrate() {
+        assert_eq!(Some(320), parse_bitrate("Dreamland - 2016, MP3, 320 kbps"));
+        assert_eq!(
+            Some(256),
+            parse_bitrate("Dreamland [WEB], AAC (tracks) 256 kbps")
+        );
+        assert_eq!(
+            None,
+            parse_bitrate("Dreamland - 1996, FLAC (tracks), lossless")
+        );
+    }
+
+    #[test]
+    fn test_ranking_topics() {
+        let topics = vec![
+            create_topic(
+                "1",
+                "Robert Miles - Dreamland - 2016, MP3, 320 kbps",
+                Some(9),
+                None,
+            ),
+            create_topic(
+                "2",
+                "Robert Miles - Dreamland - 1996, FLAC (image+.cue), lossless",
+                Some(30),
+                None,
+            ),
+            create_topic(
+                "3",
+                "Robert Miles - Dreamland - 1996, FLAC (tracks), lossless",
+                Some(4),
+                None,
+            ),
+            create_topic(
+                "4",
+                "Robert Miles - Discography, FLAC, lossless",
+                Some(50),
+                None,
+            ),
+            create_topic(
+                "5",
+                "Robert Miles - Dreamland - 1996, ALAC, lossless",
+                Some(0),
+                None,
+            ),
+        ];
+
+        let ranked = rank_topics(topics, &create_metadata(), &RankingPolicy::default());
+
+        assert_eq!(vec!["3", "4", "1", "5", "2"], get_ids(&ranked));
+        assert_eq!(
+            Some(TopicScore {
+                total: 90,
+                components: vec![
+                    ScoreComponent {
+                        reason: "4 seeders".into(),
+                        points: 0
+                    },
+                    ScoreComponent {
+                        reason: "FLAC format".into(),
+                        points: 40
+                    },
+                    ScoreComponent {
+                        reason: "lossless".into(),
+                        points: 30
+                    },
+                    ScoreComponent {
+                        reason: "requested album".into(),
+                        points: 20
+                    },
+                ],
+            }),
+            ranked[0].score
+        );
+    }
+
+    #[test]
+    fn test_ranking_topics_by_custom_policy() {
+        let topics = vec![
+            create_topic(
+                "1",
+                "Robert Miles - Dreamland - 2016, MP3, 320 kbps",
+                Some(9),
+                None,
+            ),
+            create_topic(
+                "2",
+                "Robert Miles - Dreamland, MP3, 128 kbps",
+                Some(20),
+                None,
+            ),
+            create_topic(
+                "3",
+                "Robert Miles - Discography, FLAC, lossless",
+                Some(50),
+                Some(20_000_000_000),
+            ),
+            create_topic(
+                "4",
+                "Robert Miles - Dreamland, FLAC, lossless",
+                Some(1),
+                None,
+            ),
+        ];
+        let policy = RankingPolicy {
+            preferred_formats: vec!["MP3".into(), "FLAC".into()],
+            min_bitrate: Some(256),
+            min_seeders: 2,
+            prefer_single_album: false,
+            max_discography_size: Some(10_000_000_000),
+        };
+
+        let ranked = rank_topics(topics, &create_metadata(), &policy);
+
+        assert_eq!(vec!["1", "3"], get_ids(&ranked));
+    }
+
+    #[test]
+    fn test_dropping_duplicates() {
+        let topics = vec![
+            create_topic("1", "Robert Miles - Discography, MP3", Some(10), None),
+            create_topic("2", "Robert Miles - Dreamland, FLAC", Some(10), None),
+            create_topic("1", "Robert Miles - Discography, MP3", Some(10), None),
+            TopicData {
+                provider: "torznab".into(),
+                info_hash: Some("ABCDEF".into()),
+                ..create_topic("3", "Robert Miles - Dreamland, FLAC", Some(10), None)
+            },
+            TopicData {
+                provider: "torznab".into(),
+                info_hash: Some("abcdef".into()),
+                ..create_topic("4", "Robert Miles - Dreamland, FLAC", Some(10), None)
+            },
+        ];
+
+        let ranked = rank_topics(topics, &create_metadata(), &RankingPolicy::default());
+
+        assert_eq!(vec!["2", "3", "1"], get_ids(&ranked));
+    }
+
+    #[test]
+    fn test_choosing_channel_policy() {
+        let policies: RankingPolicies = serde_json::from_str(
+            r#"{"default": {"min_seeders": 1}, "channels": {"42": {"preferred_formats": ["MP3"]}}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            &RankingPolicy {
+                min_seeders: 1,
+                ..RankingPolicy::default()
+            },
+            policies.for_channel(&RadioManagerChannelId(1))
+        );
+        assert_eq!(
+            &RankingPolicy {
+                preferred_formats: vec!["MP3".into()],
+                ..RankingPolicy::default()
+            },
+            policies.for_channel(&RadioManagerChannelId(42))
+        );
+    }
+}
@@ -0,0 +1,415 @@
+use crate::services::metadata_index::normalize;
+use crate::services::track_request_processor::{
+    SearchProviderTrait, SearchQuery, TopicData, TorrentSource,
+};
+use crate::storage::on_disk::OnDiskStorage;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+const SEARCH_RESULTS_PREFIX: &str = "search-cache";
+const TORRENTS_PREFIX: &str = "torrent-cache";
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry<T> {
+    /// Unix timestamp of the moment the entry was saved.
+    cached_at: u64,
+    value: T,
+}
+
+/// Torrent files are kept base64-encoded, as JSON arrays of numbers take
+/// several times the size of the file.
+#[derive(Serialize, Deserialize)]
+enum CachedTorrent {
+    File(String),
+    Magnet(String),
+}
+
+impl From<&TorrentSource> for CachedTorrent {
+    fn from(source: &TorrentSource) -> Self {
+        match source {
+            TorrentSource::File(data) => CachedTorrent::File(STANDARD.encode(data)),
+            TorrentSource::Magnet(magnet) => CachedTorrent::Magnet(magnet.clone()),
+        }
+    }
+}
+
+impl TryFrom<CachedTorrent> for TorrentSource {
+    type Error = base64::DecodeError;
+
+    fn try_from(torrent: CachedTorrent) -> Result<Self, Self::Error> {
+        Ok(match torrent {
+            CachedTorrent::File(data) => TorrentSource::File(STANDARD.decode(data)?),
+            CachedTorrent::Magnet(magnet) => TorrentSource::Magnet(magnet),
+        })
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// File name for the key, as queries may have any characters in them.
+fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key))
+}
+
+fn is_expired<T>(entry: &CacheEntry<T>, ttl: Duration) -> bool {
+    now().saturating_sub(entry.cached_at) >= ttl.as_secs()
+}
+
+/// Search provider keeping the search results and downloaded torrent files
+/// in the state storage for a while, so requests for the same artist hit
+/// the trackers once. Failures aren't cached, so every provider is wrapped
+/// on its own: results of the others aren't saved while one of them is down.
+pub(crate) struct CachedSearchProvider {
+    name: String,
+    pub(crate) provider: Arc<dyn SearchProviderTrait + Send + Sync + 'static>,
+    storage: Arc<OnDiskStorage>,
+    ttl: Duration,
+}
+
+impl CachedSearchProvider {
+    pub(crate) fn new(
+        name: &str,
+        provider: Arc<dyn SearchProviderTrait + Send + Sync + 'static>,
+        storage: Arc<OnDiskStorage>,
+        ttl: Duration,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            provider,
+            storage,
+            ttl,
+        }
+    }
+
+    pub(crate) async fn get_search_results(&self, query: &SearchQuery) -> Option<Vec<TopicData>> {
+        self.get(SEARCH_RESULTS_PREFIX, &self.search_results_key(query))
+            .await
+    }
+
+    pub(crate) async fn save_search_results(&self, query: &SearchQuery, results: &[TopicData]) {
+        self.save(
+            SEARCH_RESULTS_PREFIX,
+            &self.search_results_key(query),
+            results,
+        )
+        .await
+    }
+
+    pub(crate) async fn get_torrent(&self, topic: &TopicData) -> Option<TorrentSource> {
+        let key = self.torrent_key(topic);
+        let torrent = self.get::<CachedTorrent>(TORRENTS_PREFIX, &key).await?;
+
+        match TorrentSource::try_from(torrent) {
+            Ok(source) => Some(source),
+            Err(error) => {
+                warn!(?error, key, "Unable to decode cached torrent");
+                None
+            }
+        }
+    }
+
+    pub(crate) async fn save_torrent(&self, topic: &TopicData, source: &TorrentSource) {
+        self.save(
+            TORRENTS_PREFIX,
+            &self.torrent_key(topic),
+            &CachedTorrent::from(source),
+        )
+        .await
+    }
+
+    /// Removes the expired entries of all providers, which are otherwise only
+    /// replaced when the same query is made again.
+    pub(crate) async fn remove_expired(
+        storage: &OnDiskStorage,
+        ttl: Duration,
+    ) -> Result<usize, std::io::Error> {
+        let mut removed = 0;
+
+        for prefix in [SEARCH_RESULTS_PREFIX, TORRENTS_PREFIX] {
+            for (key, value) in storage.get_all(prefix).await? {
+                let is_expired = match serde_json::from_str::<CacheEntry<serde_json::Value>>(&value)
+                {
+                    Ok(entry) => is_expired(&entry, ttl),
+                    Err(_) => true,
+                };
+
+                if is_expired {
+                    storage.delete(prefix, &key).await?;
+                    removed += 1;
+                }
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Artist and album are a part of the key, as some providers search by
+    /// them rather than by the text.
+    fn search_results_key(&self, query: &SearchQuery) -> String {
+        [
+            Some(&query.text),
+            query.artist.as_ref(),
+            query.album.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .fold(self.name.clone(), |key, value| {
+            format!("{}:{}", key, normalize(value))
+        })
+    }
+
+    /// Download ids are only unique within the provider.
+    fn torrent_key(&self, topic: &TopicData) -> String {
+        format!("{}:{}", self.name, &*topic.download_id)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, prefix: &str, key: &str) -> Option<T> {
+        let value = match self.storage.get(prefix, &hash_key(key)).await {
+            Ok(value) => value?,
+            Err(error) => {
+                warn!(?error, key, "Unable to read cache entry");
+                return None;
+            }
+        };
+
+        match serde_json::from_str::<CacheEntry<T>>(&value) {
+            Ok(entry) if !is_expired(&entry, self.ttl) => Some(entry.value),
+            Ok(_) => None,
+            Err(error) => {
+                warn!(?error, key, "Unable to parse cache entry");
+                None
+            }
+        }
+    }
+
+    async fn save<T: Serialize + ?Sized>(&self, prefix: &str, key: &str, value: &T) {
+        let entry = CacheEntry {
+            cached_at: now(),
+            value,
+        };
+        let result = match serde_json::to_string(&entry) {
+            Ok(value) => self
+                .storage
+                .save(prefix, &hash_key(key), &value)
+                .await
+                .map_err(|error| error.to_string()),
+            Err(error) => Err(error.to_string()),
+        };
+
+        if let Err(error) = result {
+            warn!(?error, key, "Unable to save cache entry");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::track_request_processor::{DownloadId, SearchProviderError, TopicId};
+    use crate::services::SearchProviderRegistry;
+    use async_trait::async_trait;
+    use std::io::{Error, ErrorKind};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn create_storage() -> (Arc<OnDiskStorage>, String) {
+        let path = std::env::temp_dir()
+            .join(format!("search-cache-{}", uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .to_string();
+
+        (Arc::new(OnDiskStorage::create(path.clone())), path)
+    }
+
+    fn create_cache(ttl: Duration) -> (CachedSearchProvider, String) {
+        let (storage, path) = create_storage();
+        let cache = CachedSearchProvider::new(
+            "rutracker",
+            Arc::new(SearchProviderRegistry::new()),
+            storage,
+            ttl,
+        );
+
+        (cache, path)
+    }
+
+    fn create_topic(topic_id: u64) -> TopicData {
+        TopicData {
+            topic_id: TopicId(topic_id.to_string()),
+            download_id: DownloadId(topic_id.to_string()),
+            title: format!("Topic {}", topic_id),
+            provider: "rutracker".into(),
+            ..TopicData::default()
+        }
+    }
+
+    /// Counts the searches, failing them if there are no results to return.
+    #[derive(Default)]
+    struct SearchProviderMock {
+        results: Option<Vec<TopicData>>,
+        searches: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl SearchProviderTrait for SearchProviderMock {
+        async fn find_all(
+            &self,
+            _query: &SearchQuery,
+        ) -> Result<Vec<TopicData>, SearchProviderError> {
+            self.searches.fetch_add(1, Ordering::SeqCst);
+
+            match &self.results {
+                Some(results) => Ok(results.clone()),
+                None => Err(SearchProviderError(Box::new(Error::from(
+                    ErrorKind::ConnectionRefused,
+                )))),
+            }
+        }
+
+        async fn download_torrent(
+            &self,
+            topic: &TopicData,
+        ) -> Result<TorrentSource, SearchProviderError> {
+            Ok(TorrentSource::File(topic.title.as_bytes().to_vec()))
+        }
+    }
+
+    fn create_query(text: &str, album: Option<&str>) -> SearchQuery {
+        SearchQuery {
+            text: text.into(),
+            artist: album.map(|_| "Ted Irens".into()),
+            album: album.map(Into::into),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_caching_search_results_by_normalized_query() {
+        let (cache, path) = create_cache(Duration::from_secs(60));
+        let results = vec![create_topic(1)];
+
+        assert_eq!(
+            None,
+            cache
+                .get_search_results(&create_query("Ted Irens - Foo", None))
+                .await
+        );
+
+        cache
+            .save_search_results(&create_query("Ted Irens - Foo", None), &results)
+            .await;
+
+        assert_eq!(
+            Some(results),
+            cache
+                .get_search_results(&create_query("ted irens  -  foo", None))
+                .await
+        );
+        assert_eq!(
+            None,
+            cache
+                .get_search_results(&create_query("Ted Irens - Foo", Some("Foo")))
+                .await
+        );
+
+        cache
+            .save_torrent(&create_topic(2), &TorrentSource::File(vec![1, 2, 3]))
+            .await;
+
+        assert_eq!(
+            Some(TorrentSource::File(vec![1, 2, 3])),
+            cache.get_torrent(&create_topic(2)).await
+        );
+        assert!(cache
+            .storage
+            .get(TORRENTS_PREFIX, &hash_key("rutracker:2"))
+            .await
+            .unwrap()
+            .unwrap()
+            .contains(r#"{"File":"AQID"}"#));
+        assert_eq!(None, cache.get_torrent(&create_topic(3)).await);
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_ignoring_expired_entries() {
+        let (cache, path) = create_cache(Duration::ZERO);
+
+        cache
+            .save_search_results(&create_query("Ted Irens - Foo", None), &[create_topic(1)])
+            .await;
+
+        assert_eq!(
+            None,
+            cache
+                .get_search_results(&create_query("Ted Irens - Foo", None))
+                .await
+        );
+        assert_eq!(
+            1,
+            CachedSearchProvider::remove_expired(&cache.storage, Duration::ZERO)
+                .await
+                .unwrap()
+        );
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_searching_failed_provider_again() {
+        let (storage, path) = create_storage();
+        let working = Arc::new(SearchProviderMock {
+            results: Some(vec![create_topic(1)]),
+            ..SearchProviderMock::default()
+        });
+        let failing = Arc::new(SearchProviderMock::default());
+        let ttl = Duration::from_secs(60);
+
+        let mut registry = SearchProviderRegistry::new();
+        registry.register(
+            "torznab",
+            0,
+            Arc::new(CachedSearchProvider::new(
+                "torznab",
+                working.clone(),
+                storage.clone(),
+                ttl,
+            )),
+        );
+        registry.register(
+            "rutracker",
+            10,
+            Arc::new(CachedSearchProvider::new(
+                "rutracker",
+                failing.clone(),
+                storage,
+                ttl,
+            )),
+        );
+
+        for _ in 0..2 {
+            assert_eq!(
+                1,
+                registry
+                    .find_all(&create_query("Ted Irens", None))
+                    .await
+                    .unwrap()
+                    .len()
+            );
+        }
+
+        assert_eq!(1, working.searches.load(Ordering::SeqCst));
+        assert_eq!(2, failing.searches.load(Ordering::SeqCst));
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
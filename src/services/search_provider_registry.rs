@@ -0,0 +1,240 @@
+use crate::services::track_request_processor::{SearchProviderTrait, TopicData};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+pub(crate) struct RegisteredSearchProvider {
+    pub(crate) name: String,
+    pub(crate) priority: i32,
+    pub(crate) provider: Arc<dyn SearchProviderTrait + Send + Sync + 'static>,
+}
+
+/// Search providers configured at once. Searches are run by all of them in
+/// parallel and results are merged in order of the provider priority.
+pub(crate) struct SearchProviderRegistry {
+    providers: Vec<RegisteredSearchProvider>,
+}
+
+impl SearchProviderRegistry {
+    pub(crate) fn new() -> Self {
+        Self { providers: vec![] }
+    }
+
+    pub(crate) fn register(
+        &mut self,
+        name: &str,
+        priority: i32,
+        provider: Arc<dyn SearchProviderTrait + Send + Sync + 'static>,
+    ) {
+        self.providers.push(RegisteredSearchProvider {
+            name: name.to_string(),
+            priority,
+            provider,
+        });
+        // Stable sort keeps the registration order among equal priorities.
+        self.providers.sort_by_key(|p| -p.priority);
+    }
+
+    pub(crate) fn providers(&self) -> &[RegisteredSearchProvider] {
+        &self.providers
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&RegisteredSearchProvider> {
+        self.providers.iter().find(|p| p.name == name)
+    }
+}
+
+/// Concatenates results of the providers, given in order of priority, and
+/// drops the torrents already found by a higher priority provider.
+pub(crate) fn merge_search_results(results: Vec<Vec<TopicData>>) -> Vec<TopicData> {
+    let mut seen_info_hashes = HashSet::new();
+
+    results
+        .into_iter()
+        .flatten()
+        .filter(|topic| match &topic.info_hash {
+            Some(info_hash) => seen_info_hashes.insert(info_hash.to_lowercase()),
+            None => true,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::track_request_processor::{
+        DownloadId, RateLimitedError, SearchProviderError, SearchQuery, TopicId, TorrentSource,
+    };
+    use async_trait::async_trait;
+    use std::io::{Error, ErrorKind};
+    use std::time::Duration;
+
+    fn create_topic(provider: &str, topic_id: u64, info_hash: Option<&str>) -> TopicData {
+        TopicData {
+            topic_id: TopicId(topic_id.to_string()),
+            download_id: DownloadId(topic_id.to_string()),
+            title: format!("Topic {}", topic_id),
+            provider: provider.into(),
+            info_hash: info_hash.map(Into::into),
+            ..TopicData::default()
+        }
+    }
+
+    fn create_query() -> SearchQuery {
+        SearchQuery {
+            text: "query".into(),
+            artist: None,
+            album: None,
+        }
+    }
+
+    struct SearchProviderMock(Vec<TopicData>);
+
+    #[async_trait]
+    impl SearchProviderTrait for SearchProviderMock {
+        async fn find_all(
+            &self,
+            _query: &SearchQuery,
+        ) -> Result<Vec<TopicData>, SearchProviderError> {
+            Ok(self.0.clone())
+        }
+
+        async fn download_torrent(
+            &self,
+            topic: &TopicData,
+        ) -> Result<TorrentSource, SearchProviderError> {
+            Ok(TorrentSource::File(topic.title.as_bytes().to_vec()))
+        }
+    }
+
+    struct FailingSearchProviderMock;
+
+    #[async_trait]
+    impl SearchProviderTrait for FailingSearchProviderMock {
+        async fn find_all(
+            &self,
+            _query: &SearchQuery,
+        ) -> Result<Vec<TopicData>, SearchProviderError> {
+            Err(SearchProviderError(Box::new(Error::from(
+                ErrorKind::ConnectionRefused,
+            ))))
+        }
+
+        async fn download_torrent(
+            &self,
+            _topic: &TopicData,
+        ) -> Result<TorrentSource, SearchProviderError> {
+            Err(SearchProviderError(Box::new(Error::from(
+                ErrorKind::ConnectionRefused,
+            ))))
+        }
+    }
+
+    struct RateLimitedSearchProviderMock;
+
+    #[async_trait]
+    impl SearchProviderTrait for RateLimitedSearchProviderMock {
+        async fn find_all(
+            &self,
+            _query: &SearchQuery,
+        ) -> Result<Vec<TopicData>, SearchProviderError> {
+            Err(SearchProviderError(Box::new(RateLimitedError {
+                retry_after: Duration::from_secs(30),
+            })))
+        }
+
+        async fn download_torrent(
+            &self,
+            _topic: &TopicData,
+        ) -> Result<TorrentSource, SearchProviderError> {
+            Err(SearchProviderError(Box::new(RateLimitedError {
+                retry_after: Duration::from_secs(30),
+            })))
+        }
+    }
+
+    #[test]
+    fn test_merging_search_results_by_info_hash() {
+        let results = merge_search_results(vec![
+            vec![
+                create_topic("torznab", 1, Some("AAAA")),
+                create_topic("torznab", 2, None),
+            ],
+            vec![
+                create_topic("rutracker", 3, Some("aaaa")),
+                create_topic("rutracker", 4, None),
+            ],
+        ]);
+
+        assert_eq!(
+            vec![
+                create_topic("torznab", 1, Some("AAAA")),
+                create_topic("torznab", 2, None),
+                create_topic("rutracker", 4, None),
+            ],
+            results
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_searching_by_priority() {
+        let mut registry = SearchProviderRegistry::new();
+        registry.register(
+            "low",
+            0,
+            Arc::new(SearchProviderMock(vec![create_topic("", 1, None)])),
+        );
+        registry.register("failing", 5, Arc::new(FailingSearchProviderMock));
+        registry.register(
+            "high",
+            10,
+            Arc::new(SearchProviderMock(vec![create_topic("", 2, None)])),
+        );
+
+        let results = registry.find_all(&create_query()).await.unwrap();
+
+        assert_eq!(
+            vec![create_topic("high", 2, None), create_topic("low", 1, None)],
+            results
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_downloading_from_topic_provider() {
+        let mut registry = SearchProviderRegistry::new();
+        registry.register("first", 0, Arc::new(SearchProviderMock(vec![])));
+
+        let source = registry
+            .download_torrent(&create_topic("first", 1, None))
+            .await
+            .unwrap();
+
+        assert_eq!(TorrentSource::File(b"Topic 1".to_vec()), source);
+        assert!(registry
+            .download_torrent(&create_topic("unknown", 1, None))
+            .await
+            .is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_failing_if_all_providers_fail() {
+        let mut registry = SearchProviderRegistry::new();
+        registry.register("failing", 0, Arc::new(FailingSearchProviderMock));
+
+        assert!(registry.find_all(&create_query()).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_waiting_for_rate_limited_provider() {
+        let mut registry = SearchProviderRegistry::new();
+        registry.register(
+            "working",
+            0,
+            Arc::new(SearchProviderMock(vec![create_topic("", 1, None)])),
+        );
+        registry.register("limited", 0, Arc::new(RateLimitedSearchProviderMock));
+
+        let error = registry.find_all(&create_query()).await.unwrap_err();
+
+        assert_eq!(Some(Duration::from_secs(30)), error.retry_after());
+    }
+}
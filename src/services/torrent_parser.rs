@@ -1,5 +1,9 @@
 use serde::Deserialize;
+use serde_bencode::value::Value;
 use serde_bytes::ByteBuf;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Deserialize)]
 struct Node(String, i64);
@@ -11,13 +15,16 @@ struct File {
     length: i64,
     #[serde(default)]
     md5sum: Option<String>,
+    #[serde(default)]
+    attr: Option<String>,
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 struct Info {
     name: String,
-    pieces: ByteBuf,
+    #[serde(default)]
+    pieces: Option<ByteBuf>,
     #[serde(rename = "piece length")]
     piece_length: i64,
     #[serde(default)]
@@ -33,6 +40,12 @@ struct Info {
     #[serde(default)]
     #[serde(rename = "root hash")]
     root_hash: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "meta version")]
+    meta_version: Option<u8>,
+    #[serde(default)]
+    #[serde(rename = "file tree")]
+    file_tree: Option<Value>,
 }
 
 #[allow(dead_code)]
@@ -58,36 +71,314 @@ struct Torrent {
     #[serde(default)]
     #[serde(rename = "created by")]
     created_by: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "piece layers")]
+    piece_layers: Option<HashMap<ByteBuf, ByteBuf>>,
 }
 
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum TorrentParserError {
     #[error(transparent)]
     SerdeError(#[from] serde_bencode::Error),
+    #[error("Malformed bencoded data at offset {0}")]
+    MalformedData(usize),
+    #[error("Torrent file has no info dictionary")]
+    MissingInfo,
+    #[error("Malformed file tree: {0}")]
+    MalformedFileTree(String),
+    #[error("Missing piece layer of file: {0}")]
+    MissingPieceLayer(String),
 }
 
-pub(crate) fn get_files_count(torrent_file_content: &[u8]) -> Result<usize, TorrentParserError> {
-    let torrent = serde_bencode::from_bytes::<Torrent>(torrent_file_content)?;
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TorrentFile {
+    pub(crate) path: String,
+    pub(crate) length: u64,
+    /// Padding files (BEP 47) align the files of hybrid torrents to piece
+    /// boundaries. They are never downloaded, but they take up an index.
+    pub(crate) padding: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TorrentMetainfo {
+    pub(crate) name: String,
+    /// Hex-encoded SHA-1 hash of the bencoded info dictionary. For v2-only
+    /// torrents it's the SHA-256 hash truncated to 20 bytes (BEP 52).
+    pub(crate) info_hash: String,
+    /// Hex-encoded SHA-256 hash of the bencoded info dictionary of v2 and
+    /// hybrid torrents.
+    pub(crate) info_hash_v2: Option<String>,
+    pub(crate) private: bool,
+    /// Files in the order the torrent client indexes them: the v1 file list
+    /// if present (the case of hybrid torrents), the v2 file tree otherwise.
+    pub(crate) files: Vec<TorrentFile>,
+}
+
+impl TorrentMetainfo {
+    pub(crate) fn total_size(&self) -> u64 {
+        self.files
+            .iter()
+            .filter(|f| !f.padding)
+            .map(|f| f.length)
+            .sum()
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// File of the v2 file tree along with its merkle root.
+struct FileTreeEntry {
+    path: Vec<String>,
+    length: u64,
+    pieces_root: Option<Vec<u8>>,
+}
+
+/// Walks the v2 `file tree`, where each file is a nested dictionary of path
+/// components ending with an empty key holding the file properties. Keys of
+/// bencoded dictionaries are sorted, so this yields the files in the order
+/// they're defined in.
+fn walk_file_tree(
+    node: &Value,
+    path: &mut Vec<String>,
+    entries: &mut Vec<FileTreeEntry>,
+) -> Result<(), TorrentParserError> {
+    let malformed = |path: &[String]| TorrentParserError::MalformedFileTree(path.join("/"));
+
+    let dict = match node {
+        Value::Dict(dict) => dict,
+        _ => return Err(malformed(path)),
+    };
+
+    let mut keys = dict.keys().collect::<Vec<_>>();
+    keys.sort();
+
+    for key in keys {
+        if key.is_empty() {
+            let properties = match &dict[key] {
+                Value::Dict(properties) => properties,
+                _ => return Err(malformed(path)),
+            };
+            let length = match properties.get(b"length".as_slice()) {
+                Some(Value::Int(length)) if *length >= 0 => *length as u64,
+                _ => return Err(malformed(path)),
+            };
+            let pieces_root = match properties.get(b"pieces root".as_slice()) {
+                Some(Value::Bytes(root)) => Some(root.clone()),
+                _ => None,
+            };
 
-    Ok(torrent.info.files.unwrap_or_default().len())
+            entries.push(FileTreeEntry {
+                path: path.clone(),
+                length,
+                pieces_root,
+            });
+        } else {
+            path.push(String::from_utf8_lossy(key).to_string());
+            walk_file_tree(&dict[key], path, entries)?;
+            path.pop();
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the end offset of the bencoded value starting at `start`.
+fn skip_bencoded_value(data: &[u8], start: usize) -> Result<usize, TorrentParserError> {
+    let malformed = || TorrentParserError::MalformedData(start);
+
+    match data.get(start).ok_or_else(malformed)? {
+        b'i' => Ok(start
+            + data[start..]
+                .iter()
+                .position(|b| *b == b'e')
+                .ok_or_else(malformed)?
+            + 1),
+        b'l' | b'd' => {
+            let mut offset = start + 1;
+            while *data.get(offset).ok_or_else(malformed)? != b'e' {
+                offset = skip_bencoded_value(data, offset)?;
+            }
+            Ok(offset + 1)
+        }
+        b'0'..=b'9' => {
+            let colon = start
+                + data[start..]
+                    .iter()
+                    .position(|b| *b == b':')
+                    .ok_or_else(malformed)?;
+            let length = std::str::from_utf8(&data[start..colon])
+                .ok()
+                .and_then(|length| length.parse::<usize>().ok())
+                .ok_or_else(malformed)?;
+            let end = colon + 1 + length;
+            if end > data.len() {
+                return Err(malformed());
+            }
+            Ok(end)
+        }
+        _ => Err(malformed()),
+    }
+}
+
+/// Finds the raw bencoded info dictionary, which the info-hash is computed
+/// from. Re-serializing the parsed dictionary would lose unknown keys.
+fn find_raw_info(data: &[u8]) -> Result<&[u8], TorrentParserError> {
+    if data.first() != Some(&b'd') {
+        return Err(TorrentParserError::MalformedData(0));
+    }
+
+    let mut offset = 1;
+    while *data
+        .get(offset)
+        .ok_or(TorrentParserError::MalformedData(offset))?
+        != b'e'
+    {
+        let key_end = skip_bencoded_value(data, offset)?;
+        let value_end = skip_bencoded_value(data, key_end)?;
+
+        if &data[offset..key_end] == b"4:info" {
+            return Ok(&data[key_end..value_end]);
+        }
+
+        offset = value_end;
+    }
+
+    Err(TorrentParserError::MissingInfo)
 }
 
-pub(crate) fn get_files(torrent_file_content: &[u8]) -> Result<Vec<String>, TorrentParserError> {
+pub(crate) fn parse_torrent(
+    torrent_file_content: &[u8],
+) -> Result<TorrentMetainfo, TorrentParserError> {
     let torrent = serde_bencode::from_bytes::<Torrent>(torrent_file_content)?;
+    let raw_info = find_raw_info(torrent_file_content)?;
+    let info = torrent.info;
+
+    let is_v1 = info.pieces.is_some();
+    let is_v2 = info.meta_version == Some(2) && info.file_tree.is_some();
+
+    let info_hash_v2 = is_v2.then(|| to_hex(&Sha256::digest(raw_info)));
+    let info_hash = match &info_hash_v2 {
+        Some(info_hash_v2) if !is_v1 => info_hash_v2[..40].to_string(),
+        _ => to_hex(&Sha1::digest(raw_info)),
+    };
+
+    let mut file_tree = vec![];
+    if let (true, Some(node)) = (is_v2, &info.file_tree) {
+        walk_file_tree(node, &mut vec![], &mut file_tree)?;
+
+        // Files spanning more than one piece are verified against their
+        // piece layer, so the torrent is unusable without it.
+        let piece_layers = torrent
+            .piece_layers
+            .unwrap_or_default()
+            .into_keys()
+            .map(ByteBuf::into_vec)
+            .collect::<HashSet<_>>();
+        for entry in &file_tree {
+            let has_layer = entry
+                .pieces_root
+                .as_ref()
+                .is_some_and(|root| piece_layers.contains(root));
+            if entry.length > info.piece_length as u64 && !has_layer {
+                return Err(TorrentParserError::MissingPieceLayer(entry.path.join("/")));
+            }
+        }
+    }
+
+    let files = match (info.files, info.length) {
+        (Some(files), _) => files
+            .into_iter()
+            .map(|f| TorrentFile {
+                path: f.path.join(std::path::MAIN_SEPARATOR_STR),
+                length: f.length as u64,
+                padding: f.attr.is_some_and(|attr| attr.contains('p')),
+            })
+            .collect(),
+        // Single-file torrents describe the only file in the info itself.
+        (None, Some(length)) => vec![TorrentFile {
+            path: info.name.clone(),
+            length: length as u64,
+            padding: false,
+        }],
+        (None, None) => file_tree
+            .into_iter()
+            .map(|entry| TorrentFile {
+                path: entry.path.join(std::path::MAIN_SEPARATOR_STR),
+                length: entry.length,
+                padding: false,
+            })
+            .collect(),
+    };
+
+    Ok(TorrentMetainfo {
+        name: info.name,
+        info_hash,
+        info_hash_v2,
+        private: info.private == Some(1),
+        files,
+    })
+}
 
-    Ok(torrent
-        .info
-        .files
-        .unwrap_or_default()
-        .into_iter()
-        .map(|f| f.path.join(std::path::MAIN_SEPARATOR_STR))
-        .collect())
+pub(crate) fn get_files_count(torrent_file_content: &[u8]) -> Result<usize, TorrentParserError> {
+    Ok(parse_torrent(torrent_file_content)?.files.len())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parsing_torrent_metainfo() {
+        let contents = include_bytes!("../../tests/fixtures/image_cue.torrent");
+        let torrent = parse_torrent(contents).unwrap();
+
+        assert_eq!(
+            TorrentMetainfo {
+                name: "Ted Irens - Life @ Mirror (2012) [FLAC image+.cue]".into(),
+                info_hash: "ff02638e98b7c57f9a79041624057f706edb1024".into(),
+                info_hash_v2: None,
+                private: true,
+                files: vec![
+                    TorrentFile {
+                        path: "Ted Irens - Life @ Mirror.cue".into(),
+                        length: 1024,
+                        padding: false,
+                    },
+                    TorrentFile {
+                        path: "Ted Irens - Life @ Mirror.flac".into(),
+                        length: 412345678,
+                        padding: false,
+                    },
+                    TorrentFile {
+                        path: "Folder.jpg".into(),
+                        length: 204800,
+                        padding: false,
+                    },
+                ],
+            },
+            torrent
+        );
+        assert_eq!(412551502, torrent.total_size());
+    }
+
+    #[test]
+    fn test_computing_info_hash() {
+        let contents = include_bytes!("../../tests/fixtures/example.torrent");
+        let torrent = parse_torrent(contents).unwrap();
+
+        assert_eq!(
+            "2ec5340de73d63d6faa162a9dd435acb462d8737",
+            torrent.info_hash
+        );
+    }
+
+    #[test]
+    fn test_parsing_malformed_torrent() {
+        assert!(parse_torrent(b"d4:infod4:name").is_err());
+    }
+
     #[test]
     fn test_getting_files_count() {
         let contents = include_bytes!("../../tests/fixtures/example.torrent");
@@ -96,10 +387,121 @@ mod tests {
         assert_eq!(18, files_count);
     }
 
+    #[test]
+    fn test_getting_single_file_torrent_files_count() {
+        let contents = include_bytes!("../../tests/fixtures/single_file.torrent");
+        let files_count = get_files_count(contents).unwrap();
+
+        assert_eq!(1, files_count);
+    }
+
+    #[test]
+    fn test_parsing_single_file_torrent() {
+        let contents = include_bytes!("../../tests/fixtures/single_file.torrent");
+        let torrent = parse_torrent(contents).unwrap();
+
+        assert_eq!(
+            TorrentMetainfo {
+                name: "Ted Irens - Rising Star.flac".into(),
+                info_hash: "8d4d3ad1cde628eca012031e915ffe1bf07a5d73".into(),
+                info_hash_v2: None,
+                private: true,
+                files: vec![TorrentFile {
+                    path: "Ted Irens - Rising Star.flac".into(),
+                    length: 31457280,
+                    padding: false,
+                }],
+            },
+            torrent
+        );
+    }
+
+    #[test]
+    fn test_parsing_v2_torrent() {
+        let contents = include_bytes!("../../tests/fixtures/v2_only.torrent");
+        let torrent = parse_torrent(contents).unwrap();
+
+        assert_eq!(
+            TorrentMetainfo {
+                name: "Ted Irens - Two Mountains (Single)".into(),
+                info_hash: "7bb6d2d20e5e2b382146b2d6fae8464f23882ec2".into(),
+                info_hash_v2: Some(
+                    "7bb6d2d20e5e2b382146b2d6fae8464f23882ec260cba1c466b80f102e46c892".into()
+                ),
+                private: true,
+                files: vec![
+                    TorrentFile {
+                        path: "Folder.jpg".into(),
+                        length: 30000,
+                        padding: false,
+                    },
+                    TorrentFile {
+                        path: "Ted Irens - Two Mountains.flac".into(),
+                        length: 200000,
+                        padding: false,
+                    },
+                ],
+            },
+            torrent
+        );
+    }
+
+    #[test]
+    fn test_parsing_hybrid_torrent() {
+        let contents = include_bytes!("../../tests/fixtures/hybrid.torrent");
+        let torrent = parse_torrent(contents).unwrap();
+
+        assert_eq!(
+            TorrentMetainfo {
+                name: "Ted Irens - Two Mountains (Single)".into(),
+                info_hash: "3487d28684ff2fd33d21b5cc900f2e2773b1a9b2".into(),
+                info_hash_v2: Some(
+                    "806f44edf57035fb47b854de1a8823ece3838642912a1cb78d6612fedf71783a".into()
+                ),
+                private: true,
+                files: vec![
+                    TorrentFile {
+                        path: "Ted Irens - Two Mountains.flac".into(),
+                        length: 200000,
+                        padding: false,
+                    },
+                    TorrentFile {
+                        path: format!(".pad{}62144", std::path::MAIN_SEPARATOR),
+                        length: 62144,
+                        padding: true,
+                    },
+                    TorrentFile {
+                        path: "Folder.jpg".into(),
+                        length: 30000,
+                        padding: false,
+                    },
+                ],
+            },
+            torrent
+        );
+        assert_eq!(230000, torrent.total_size());
+        assert_eq!(3, get_files_count(contents).unwrap());
+    }
+
+    #[test]
+    fn test_parsing_v2_torrent_without_piece_layers() {
+        let contents = include_bytes!("../../tests/fixtures/v2_missing_layers.torrent");
+
+        assert!(matches!(
+            parse_torrent(contents),
+            Err(TorrentParserError::MissingPieceLayer(_))
+        ));
+    }
+
     #[test]
     fn test_getting_files_list() {
         let contents = include_bytes!("../../tests/fixtures/example.torrent");
-        let files = get_files(contents).unwrap();
+        let files: Vec<_> = parse_torrent(contents)
+            .unwrap()
+            .files
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
 
         assert_eq!(
             vec![
@@ -1,20 +1,27 @@
 use super::track_request_processor::{
-    AudioMetadata, DownloadId, RadioManagerChannelId, RadioManagerClientError,
-    RadioManagerClientTrait, RadioManagerLinkId, RadioManagerTrackId, RequestId,
-    SearchProviderError, SearchProviderTrait, StateStorageError, StateStorageTrait, TopicData,
-    TopicId, Torrent, TorrentClientError, TorrentClientTrait, TorrentId, TorrentStatus,
-    TrackRequestProcessingContext, TrackRequestProcessingState, TrackRequestProcessingStep,
-    TrackRequestProcessor,
+    AudioMetadata, AudioProcessorError, AudioProcessorTrait, AudioSplitterError,
+    AudioSplitterTrait, AudioTags, CreateRequestError, DownloadId, FilePreference,
+    FileSelectionOptions, LocalLibraryError, LocalLibraryTrait, MetadataProviderError,
+    MetadataProviderTrait, OverrideRequestError, ProcessRequestError, RadioManagerChannelId,
+    RadioManagerClientError, RadioManagerClientTrait, RadioManagerLinkId, RadioManagerTrackId,
+    RateLimitedError, RequestId, SearchProviderError, SearchProviderTrait, SearchQuery,
+    StateStorageError, StateStorageTrait, TopicData, TopicId, Torrent, TorrentClientError,
+    TorrentClientTrait, TorrentId, TorrentSource, TorrentStatus, TrackRequestProcessingContext,
+    TrackRequestProcessingState, TrackRequestProcessingStep, TrackRequestProcessor,
 };
+use crate::services::query_planner::QueryPlanner;
+use crate::services::torrent_parser::TorrentFile;
 use crate::services::track_request_processor::{
     CreateRequestOptions, RadioManagerChannelTrack, TrackRequestProcessingStatus,
 };
 use crate::types::UserId;
 use async_trait::async_trait;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::{Error, ErrorKind};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
 struct StateStorageMock {
     context_storage: Mutex<HashMap<UserId, HashMap<RequestId, TrackRequestProcessingContext>>>,
@@ -196,38 +203,205 @@ struct SearchProviderMock;
 
 #[async_trait]
 impl SearchProviderTrait for SearchProviderMock {
-    async fn find_all(&self, query: &str) -> Result<Vec<TopicData>, SearchProviderError> {
-        match query {
+    async fn find_all(&self, query: &SearchQuery) -> Result<Vec<TopicData>, SearchProviderError> {
+        match query.text.as_str() {
             "Ted Irens - Foo" => Ok(vec![
+                TopicData {
+                    title: "Ted Irens - Foo [FLAC]".into(),
+                    provider: "mock".into(),
+                    info_hash: None,
+                    topic_id: TopicId("1".into()),
+                    download_id: DownloadId("1".into()),
+                    ..TopicData::default()
+                },
                 TopicData {
                     title: "Ted Irens - Foo [MP3]".into(),
-                    topic_id: TopicId(1),
-                    download_id: DownloadId(1),
+                    provider: "mock".into(),
+                    info_hash: None,
+                    topic_id: TopicId("2".into()),
+                    download_id: DownloadId("2".into()),
+                    ..TopicData::default()
+                },
+            ]),
+            "Ted Irens - Bar" => Ok(vec![
+                TopicData {
+                    title: "Ted Irens - Bar [FLAC]".into(),
+                    provider: "mock".into(),
+                    info_hash: None,
+                    topic_id: TopicId("6".into()),
+                    download_id: DownloadId("6".into()),
+                    ..TopicData::default()
                 },
                 TopicData {
-                    title: "Ted Irens - Foo [FLAC]".into(),
-                    topic_id: TopicId(2),
-                    download_id: DownloadId(2),
+                    title: "Ted Irens - Bar [MP3]".into(),
+                    provider: "mock".into(),
+                    info_hash: None,
+                    topic_id: TopicId("7".into()),
+                    download_id: DownloadId("1".into()),
+                    ..TopicData::default()
                 },
             ]),
+            "Ted Irens - Rising Star" => Ok(vec![TopicData {
+                title: "Ted Irens - Rising Star (Single) - 2013, FLAC (tracks)".into(),
+                provider: "mock".into(),
+                info_hash: None,
+                topic_id: TopicId("4".into()),
+                download_id: DownloadId("4".into()),
+                ..TopicData::default()
+            }]),
+            "Robert Miles - Dreamland" => Ok(vec![TopicData {
+                title: "Robert Miles - Dreamland - 1996, FLAC (tracks)".into(),
+                provider: "mock".into(),
+                info_hash: None,
+                topic_id: TopicId("5".into()),
+                download_id: DownloadId("5".into()),
+                ..TopicData::default()
+            }]),
+            "Ted Irens - Life @ Mirror" => Ok(vec![TopicData {
+                title: "Ted Irens - Life @ Mirror - 2012, FLAC (image+.cue)".into(),
+                provider: "mock".into(),
+                info_hash: None,
+                topic_id: TopicId("3".into()),
+                download_id: DownloadId("3".into()),
+                ..TopicData::default()
+            }]),
             _ => Ok(vec![]),
         }
     }
 
     async fn download_torrent(
         &self,
-        download_id: &DownloadId,
-    ) -> Result<Vec<u8>, SearchProviderError> {
-        match **download_id {
-            1 => Ok(include_bytes!("../../../tests/fixtures/example.torrent").to_vec()),
+        topic: &TopicData,
+    ) -> Result<TorrentSource, SearchProviderError> {
+        match &*topic.download_id {
+            "1" => Ok(TorrentSource::File(
+                include_bytes!("../../../tests/fixtures/example.torrent").to_vec(),
+            )),
+            "3" => Ok(TorrentSource::File(
+                include_bytes!("../../../tests/fixtures/image_cue.torrent").to_vec(),
+            )),
+            "5" => Ok(TorrentSource::Magnet(
+                "magnet:?xt=urn:btih:2ec5340de73d63d6faa162a9dd435acb462d8737".into(),
+            )),
+            "4" => Ok(TorrentSource::File(
+                include_bytes!("../../../tests/fixtures/single_file.torrent").to_vec(),
+            )),
             _ => Err(SearchProviderError(Box::new(Error::from(
                 ErrorKind::NotFound,
             )))),
         }
     }
+
+    async fn get_tracklist(
+        &self,
+        topic: &TopicData,
+    ) -> Result<Option<Vec<String>>, SearchProviderError> {
+        match &*topic.topic_id {
+            "6" => Ok(Some(vec!["Ted Irens - Rising Star".into()])),
+            "7" => Ok(Some(vec![
+                "Ted Irens - Rising Star".into(),
+                "Ted Irens - Sunday Breakfast (Original Mix)".into(),
+            ])),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Rate limits the first torrent download, then behaves as `SearchProviderMock`.
+struct RateLimitedSearchProviderMock {
+    downloads: Mutex<Vec<String>>,
+}
+
+impl RateLimitedSearchProviderMock {
+    fn new() -> Self {
+        Self {
+            downloads: Mutex::new(vec![]),
+        }
+    }
+}
+
+#[async_trait]
+impl SearchProviderTrait for RateLimitedSearchProviderMock {
+    async fn find_all(&self, query: &SearchQuery) -> Result<Vec<TopicData>, SearchProviderError> {
+        SearchProviderMock.find_all(query).await
+    }
+
+    async fn download_torrent(
+        &self,
+        topic: &TopicData,
+    ) -> Result<TorrentSource, SearchProviderError> {
+        let is_first_download = {
+            let mut downloads = self.downloads.lock().unwrap();
+            downloads.push(topic.download_id.to_string());
+            downloads.len() == 1
+        };
+
+        if is_first_download {
+            return Err(SearchProviderError(Box::new(RateLimitedError {
+                retry_after: Duration::from_millis(10),
+            })));
+        }
+
+        SearchProviderMock.download_torrent(topic).await
+    }
+}
+
+/// Fails the searches other than the given query, which is searched by
+/// `SearchProviderMock`.
+struct FailingQueriesSearchProviderMock(&'static str);
+
+#[async_trait]
+impl SearchProviderTrait for FailingQueriesSearchProviderMock {
+    async fn find_all(&self, query: &SearchQuery) -> Result<Vec<TopicData>, SearchProviderError> {
+        if query.text != self.0 {
+            return Err(SearchProviderError(Box::new(Error::from(
+                ErrorKind::ConnectionReset,
+            ))));
+        }
+
+        SearchProviderMock.find_all(query).await
+    }
+
+    async fn download_torrent(
+        &self,
+        topic: &TopicData,
+    ) -> Result<TorrentSource, SearchProviderError> {
+        SearchProviderMock.download_torrent(topic).await
+    }
 }
 
-struct TorrentClientMock;
+struct LocalLibraryMock;
+
+#[async_trait]
+impl LocalLibraryTrait for LocalLibraryMock {
+    async fn find_track(
+        &self,
+        metadata: &AudioMetadata,
+    ) -> Result<Option<String>, LocalLibraryError> {
+        match metadata.title.to_lowercase().as_str() {
+            "another moon night" => Ok(Some(
+                "/music/Ted Irens - Life @ Mirror/03. Another Moon Night.flac".into(),
+            )),
+            _ => Ok(None),
+        }
+    }
+}
+
+struct TorrentClientMock {
+    /// Like in Transmission, metadata of the paused magnet links is never
+    /// fetched.
+    paused: Mutex<HashSet<TorrentId>>,
+    deleted: Mutex<Vec<TorrentId>>,
+}
+
+impl TorrentClientMock {
+    fn new() -> Self {
+        Self {
+            paused: Mutex::new(HashSet::new()),
+            deleted: Mutex::new(Vec::new()),
+        }
+    }
+}
 
 #[async_trait]
 impl TorrentClientTrait for TorrentClientMock {
@@ -236,9 +410,91 @@ impl TorrentClientTrait for TorrentClientMock {
         url: Vec<u8>,
         selected_files_indexes: Vec<i32>,
     ) -> Result<TorrentId, TorrentClientError> {
+        if url == include_bytes!("../../../tests/fixtures/image_cue.torrent") {
+            // Audio image is selected once the cue sheet is downloaded.
+            assert_eq!(vec![0], selected_files_indexes);
+            return Ok(TorrentId(2));
+        }
+
+        if url == include_bytes!("../../../tests/fixtures/single_file.torrent") {
+            assert_eq!(vec![0], selected_files_indexes);
+            return Ok(TorrentId(3));
+        }
+
         Ok(TorrentId(1))
     }
 
+    async fn add_magnet(&self, magnet_link: &str) -> Result<TorrentId, TorrentClientError> {
+        assert_eq!(
+            "magnet:?xt=urn:btih:2ec5340de73d63d6faa162a9dd435acb462d8737",
+            magnet_link
+        );
+        self.paused.lock().unwrap().insert(TorrentId(4));
+
+        Ok(TorrentId(4))
+    }
+
+    async fn start_torrent(&self, torrent_id: &TorrentId) -> Result<(), TorrentClientError> {
+        self.paused.lock().unwrap().remove(torrent_id);
+
+        Ok(())
+    }
+
+    async fn get_files(
+        &self,
+        torrent_id: &TorrentId,
+    ) -> Result<Option<Vec<TorrentFile>>, TorrentClientError> {
+        if self.paused.lock().unwrap().contains(torrent_id) {
+            return Ok(None);
+        }
+
+        let files: &[(&str, u64)] = match **torrent_id {
+            2 => &[
+                ("Ted Irens - Life @ Mirror.cue", 1024),
+                ("Ted Irens - Life @ Mirror.flac", 412345678),
+                ("Folder.jpg", 204800),
+            ],
+            3 => &[("Ted Irens - Rising Star.flac", 31457280)],
+            4 => &[
+                (
+                    "Robert Miles - Dreamland/01. Robert Miles - Children.flac",
+                    41943040,
+                ),
+                (
+                    "Robert Miles - Dreamland/02. Robert Miles - Fable.flac",
+                    36700160,
+                ),
+                ("Robert Miles - Dreamland/Folder.jpg", 102400),
+            ],
+            _ => return Ok(None),
+        };
+
+        Ok(Some(
+            files
+                .iter()
+                .map(|(path, length)| TorrentFile {
+                    path: path.to_string(),
+                    length: *length,
+                    padding: false,
+                })
+                .collect(),
+        ))
+    }
+
+    async fn select_files(
+        &self,
+        torrent_id: &TorrentId,
+        selected_files_indexes: Vec<i32>,
+    ) -> Result<(), TorrentClientError> {
+        match **torrent_id {
+            2 => assert_eq!(vec![0, 1, 2], selected_files_indexes),
+            4 => assert_eq!(vec![0, 2], selected_files_indexes),
+            _ => panic!("Unexpected torrent: {}", torrent_id),
+        }
+
+        Ok(())
+    }
+
     async fn get_torrent(&self, torrent_id: &TorrentId) -> Result<Torrent, TorrentClientError> {
         match **torrent_id {
             1 => Ok(Torrent {
@@ -248,12 +504,32 @@ impl TorrentClientTrait for TorrentClientMock {
                     "path/to/track02.mp3".into(),
                 ],
             }),
+            2 => Ok(Torrent {
+                status: TorrentStatus::Complete,
+                files: vec![
+                    "Ted Irens - Life @ Mirror.cue".into(),
+                    "Ted Irens - Life @ Mirror.flac".into(),
+                ],
+            }),
+            3 => Ok(Torrent {
+                status: TorrentStatus::Complete,
+                files: vec!["Ted Irens - Rising Star.flac".into()],
+            }),
+            4 => Ok(Torrent {
+                status: TorrentStatus::Complete,
+                files: vec![
+                    "Robert Miles - Dreamland/01. Robert Miles - Children.flac".into(),
+                    "Robert Miles - Dreamland/Folder.jpg".into(),
+                ],
+            }),
             _ => todo!(),
         }
     }
 
     async fn delete_torrent(&self, torrent_id: &TorrentId) -> Result<(), TorrentClientError> {
-        todo!()
+        self.deleted.lock().unwrap().push(torrent_id.clone());
+
+        Ok(())
     }
 }
 
@@ -268,6 +544,14 @@ impl RadioManagerClientTrait for RadioManagerMock {
     ) -> Result<RadioManagerTrackId, RadioManagerClientError> {
         match path_to_audio_file {
             "downloads/path/to/01 - Sunday Breakfast.mp3" => Ok(RadioManagerTrackId(1)),
+            "tests/fixtures/02. Rain In The Forest.flac" => Ok(RadioManagerTrackId(2)),
+            "downloads/Ted Irens - Rising Star.flac" => Ok(RadioManagerTrackId(3)),
+            "downloads/Robert Miles - Dreamland/01. Robert Miles - Children.flac" => {
+                Ok(RadioManagerTrackId(4))
+            }
+            "/music/Ted Irens - Life @ Mirror/03. Another Moon Night.flac" => {
+                Ok(RadioManagerTrackId(5))
+            }
             _ => Err(RadioManagerClientError(Box::new(Error::from(
                 ErrorKind::NotFound,
             )))),
@@ -291,17 +575,159 @@ impl RadioManagerClientTrait for RadioManagerMock {
     }
 }
 
+type Split = (String, Duration, Option<Duration>, String);
+
+struct AudioSplitterMock {
+    splits: Mutex<Vec<Split>>,
+}
+
+impl AudioSplitterMock {
+    fn new() -> Self {
+        Self {
+            splits: Mutex::new(vec![]),
+        }
+    }
+}
+
+#[async_trait]
+impl AudioSplitterTrait for AudioSplitterMock {
+    async fn split_track(
+        &self,
+        path_to_image_file: &str,
+        start: Duration,
+        end: Option<Duration>,
+        path_to_output_file: &str,
+    ) -> Result<(), AudioSplitterError> {
+        self.splits.lock().unwrap().push((
+            path_to_image_file.to_string(),
+            start,
+            end,
+            path_to_output_file.to_string(),
+        ));
+
+        Ok(())
+    }
+}
+
+struct AudioProcessorMock {
+    tags: Mutex<Vec<AudioTags>>,
+}
+
+impl AudioProcessorMock {
+    fn new() -> Self {
+        Self {
+            tags: Mutex::new(vec![]),
+        }
+    }
+}
+
+#[async_trait]
+impl AudioProcessorTrait for AudioProcessorMock {
+    async fn process_audio_file(
+        &self,
+        _request_id: &RequestId,
+        path_to_audio_file: &str,
+        tags: &AudioTags,
+    ) -> Result<String, AudioProcessorError> {
+        self.tags.lock().unwrap().push(tags.clone());
+
+        Ok(path_to_audio_file.to_string())
+    }
+
+    async fn remove_processed_files(
+        &self,
+        _request_id: &RequestId,
+    ) -> Result<(), AudioProcessorError> {
+        Ok(())
+    }
+}
+
+struct MetadataProviderMock;
+
+#[async_trait]
+impl MetadataProviderTrait for MetadataProviderMock {
+    async fn find_recording(
+        &self,
+        metadata: &AudioMetadata,
+    ) -> Result<Option<AudioMetadata>, MetadataProviderError> {
+        match (
+            metadata.artist.to_lowercase().as_str(),
+            metadata.title.to_lowercase().as_str(),
+        ) {
+            ("ted irens", "sunday breakfast") => Ok(Some(AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Life @ Mirror".into(),
+            })),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Collaborators of the processor under test, the mocks by default.
+struct ProcessorSetup {
+    state_storage: Arc<dyn StateStorageTrait + Send + Sync>,
+    search_provider: Arc<dyn SearchProviderTrait + Send + Sync>,
+    local_library: Arc<dyn LocalLibraryTrait + Send + Sync>,
+    torrent_client: Arc<dyn TorrentClientTrait + Send + Sync>,
+    radio_manager_client: Arc<dyn RadioManagerClientTrait + Send + Sync>,
+    metadata_provider: Option<Arc<dyn MetadataProviderTrait + Send + Sync>>,
+    audio_splitter: Arc<dyn AudioSplitterTrait + Send + Sync>,
+    audio_processor: Arc<dyn AudioProcessorTrait + Send + Sync>,
+    file_selection: FileSelectionOptions,
+    query_planner: QueryPlanner,
+    download_directory: String,
+}
+
+impl Default for ProcessorSetup {
+    fn default() -> Self {
+        Self {
+            state_storage: Arc::new(StateStorageMock::new()),
+            search_provider: Arc::new(SearchProviderMock),
+            local_library: Arc::new(LocalLibraryMock),
+            torrent_client: Arc::new(TorrentClientMock::new()),
+            radio_manager_client: Arc::new(RadioManagerMock),
+            metadata_provider: Some(Arc::new(MetadataProviderMock)),
+            audio_splitter: Arc::new(AudioSplitterMock::new()),
+            audio_processor: Arc::new(AudioProcessorMock::new()),
+            file_selection: FileSelectionOptions::default(),
+            query_planner: QueryPlanner::default(),
+            download_directory: "downloads".into(),
+        }
+    }
+}
+
+impl ProcessorSetup {
+    fn build(self) -> TrackRequestProcessor {
+        let processor = TrackRequestProcessor::new(
+            self.state_storage,
+            self.search_provider,
+            self.torrent_client,
+            self.radio_manager_client,
+            self.audio_splitter,
+            self.audio_processor,
+            self.download_directory,
+        )
+        .with_local_library(self.local_library)
+        .with_file_selection(self.file_selection)
+        .with_query_planner(self.query_planner);
+
+        match self.metadata_provider {
+            Some(metadata_provider) => processor.with_metadata_provider(metadata_provider),
+            None => processor,
+        }
+    }
+}
+
 #[actix_rt::test]
 async fn test_create_track_request() {
     let state_storage = Arc::new(StateStorageMock::new());
 
-    let processor = TrackRequestProcessor::new(
-        state_storage.clone(),
-        Arc::new(SearchProviderMock),
-        Arc::new(TorrentClientMock),
-        Arc::new(RadioManagerMock),
-        "downloads".to_string(),
-    );
+    let processor = ProcessorSetup {
+        state_storage: state_storage.clone(),
+        ..ProcessorSetup::default()
+    }
+    .build();
     let user_id = 1.into();
     let metadata = AudioMetadata {
         title: "Sunday Breakfast".into(),
@@ -327,7 +753,7 @@ async fn test_create_track_request() {
         .unwrap();
     assert_eq!(stored_context.metadata.title, "Sunday Breakfast");
     assert_eq!(stored_context.metadata.artist, "Ted Irens");
-    assert_eq!(stored_context.metadata.album, "Foo");
+    assert_eq!(stored_context.metadata.album, "Life @ Mirror");
     assert_eq!(stored_context.target_channel_id, channel_id);
 
     let stored_state = state_storage
@@ -336,19 +762,98 @@ async fn test_create_track_request() {
         .unwrap();
     assert_eq!(
         stored_state.get_step(),
-        TrackRequestProcessingStep::GetTopicsIntoQueue
+        TrackRequestProcessingStep::SearchLocalLibrary
     );
 }
 
+#[actix_rt::test]
+async fn test_create_track_request_without_validation() {
+    let state_storage = Arc::new(StateStorageMock::new());
+
+    let processor = ProcessorSetup {
+        state_storage: state_storage.clone(),
+        ..ProcessorSetup::default()
+    }
+    .build();
+    let user_id = 1.into();
+    let metadata = AudioMetadata {
+        title: "sunday breakfast".into(),
+        artist: "ted irens".into(),
+        album: "Foo".into(),
+    };
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                validate_metadata: false,
+            },
+            &RadioManagerChannelId(1),
+        )
+        .await
+        .unwrap();
+
+    let stored_context = state_storage
+        .load_context(&user_id, &request_id)
+        .await
+        .unwrap();
+    assert_eq!(stored_context.metadata, metadata);
+}
+
+#[actix_rt::test]
+async fn test_create_track_request_for_unknown_recording() {
+    let processor = ProcessorSetup::default().build();
+    let metadata = AudioMetadata {
+        title: "Not A Real Song".into(),
+        artist: "Ted Irens".into(),
+        album: "Foo".into(),
+    };
+    let result = processor
+        .create_request(
+            &1.into(),
+            &metadata,
+            &CreateRequestOptions {
+                validate_metadata: true,
+            },
+            &RadioManagerChannelId(1),
+        )
+        .await;
+
+    assert!(matches!(result, Err(CreateRequestError::UnknownRecording)));
+}
+
+#[actix_rt::test]
+async fn test_create_track_request_without_metadata_provider() {
+    let processor = ProcessorSetup {
+        metadata_provider: None,
+        ..ProcessorSetup::default()
+    }
+    .build();
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Foo".into(),
+    };
+    let result = processor
+        .create_request(
+            &1.into(),
+            &metadata,
+            &CreateRequestOptions {
+                validate_metadata: true,
+            },
+            &RadioManagerChannelId(1),
+        )
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(CreateRequestError::MetadataValidationNotConfigured)
+    ));
+}
+
 #[actix_rt::test]
 async fn test_processing_track_request() {
-    let processor = TrackRequestProcessor::new(
-        Arc::from(StateStorageMock::new()),
-        Arc::from(SearchProviderMock),
-        Arc::from(TorrentClientMock),
-        Arc::from(RadioManagerMock),
-        "downloads".into(),
-    );
+    let processor = ProcessorSetup::default().build();
     let user_id = UserId(1);
     let metadata = AudioMetadata {
         title: "Sunday Breakfast".into(),
@@ -369,7 +874,508 @@ async fn test_processing_track_request() {
         .unwrap();
 
     processor
-        .process_request(&user_id, &request_id)
+        .process_request(&user_id, &request_id, &CancellationToken::new())
         .await
         .unwrap();
 }
+
+#[actix_rt::test]
+async fn test_skipping_topic_without_requested_track_in_tracklist() {
+    let processor = ProcessorSetup::default().build();
+    let user_id = UserId(1);
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Bar".into(),
+    };
+    let channel_id = RadioManagerChannelId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                validate_metadata: false,
+            },
+            &channel_id,
+        )
+        .await
+        .unwrap();
+
+    // Torrent file of the first topic is missing, so it must not be downloaded.
+    processor
+        .process_request(&user_id, &request_id, &CancellationToken::new())
+        .await
+        .unwrap();
+}
+
+#[actix_rt::test]
+async fn test_waiting_for_rate_limit() {
+    let search_provider = Arc::new(RateLimitedSearchProviderMock::new());
+    let processor = ProcessorSetup {
+        search_provider: search_provider.clone(),
+        ..ProcessorSetup::default()
+    }
+    .build();
+    let user_id = UserId(1);
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Foo".into(),
+    };
+    let channel_id = RadioManagerChannelId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                validate_metadata: false,
+            },
+            &channel_id,
+        )
+        .await
+        .unwrap();
+
+    processor
+        .process_request(&user_id, &request_id, &CancellationToken::new())
+        .await
+        .unwrap();
+
+    // The rate limited topic is tried again rather than skipped.
+    assert_eq!(
+        vec!["1".to_string(), "1".to_string()],
+        *search_provider.downloads.lock().unwrap()
+    );
+}
+
+#[actix_rt::test]
+async fn test_searching_without_creating_request() {
+    let processor = ProcessorSetup::default().build();
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Bar".into(),
+    };
+
+    let results = processor
+        .search(&metadata, &RadioManagerChannelId(1), 2)
+        .await
+        .unwrap();
+
+    assert_eq!(2, results.len());
+    // Tracklist of the first topic doesn't list the track.
+    assert_eq!(Some(false), results[0].has_requested_track);
+    assert_eq!(None, results[0].selected_files);
+    assert_eq!(Some(true), results[1].has_requested_track);
+    assert!(results[1]
+        .selected_files
+        .as_ref()
+        .is_some_and(|files| files.iter().any(|file| file.contains("Sunday Breakfast"))));
+}
+
+#[actix_rt::test]
+async fn test_stopping_track_request_processing() {
+    let state_storage = Arc::new(StateStorageMock::new());
+    let processor = ProcessorSetup {
+        state_storage: state_storage.clone(),
+        ..ProcessorSetup::default()
+    }
+    .build();
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Sunday Breakfast".into(),
+                artist: "Ted Irens".into(),
+                album: "Foo".into(),
+            },
+            &CreateRequestOptions {
+                validate_metadata: false,
+            },
+            &RadioManagerChannelId(1),
+        )
+        .await
+        .unwrap();
+    let cancellation = CancellationToken::new();
+    cancellation.cancel();
+
+    processor
+        .process_request(&user_id, &request_id, &cancellation)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        TrackRequestProcessingStep::SearchLocalLibrary,
+        state_storage
+            .load_state(&user_id, &request_id)
+            .await
+            .unwrap()
+            .get_step()
+    );
+}
+
+#[actix_rt::test]
+async fn test_searching_despite_failed_queries() {
+    let create_processor = |search_provider| {
+        ProcessorSetup {
+            search_provider: Arc::new(search_provider),
+            query_planner: serde_json::from_str(r#"{"aliases": {"Тед Айренс": ["Ted Irens"]}}"#)
+                .unwrap(),
+            ..ProcessorSetup::default()
+        }
+        .build()
+    };
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Тед Айренс".into(),
+        album: "Foo".into(),
+    };
+
+    let results = create_processor(FailingQueriesSearchProviderMock("Ted Irens - Foo"))
+        .search(&metadata, &RadioManagerChannelId(1), 0)
+        .await
+        .unwrap();
+
+    assert_eq!(2, results.len());
+
+    let result = create_processor(FailingQueriesSearchProviderMock(""))
+        .search(&metadata, &RadioManagerChannelId(1), 0)
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(ProcessRequestError::SearchProviderError(_))
+    ));
+}
+
+#[actix_rt::test]
+async fn test_overriding_track_request() {
+    let processor = ProcessorSetup::default().build();
+    let user_id = UserId(1);
+    let metadata = AudioMetadata {
+        title: "Rising Star (Original Mix)".into(),
+        artist: "Ted Irens".into(),
+        album: "Baz".into(),
+    };
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                validate_metadata: false,
+            },
+            &RadioManagerChannelId(1),
+        )
+        .await
+        .unwrap();
+
+    let result = processor
+        .process_request(&user_id, &request_id, &CancellationToken::new())
+        .await;
+    assert!(matches!(result, Err(ProcessRequestError::TrackNotFound)));
+
+    let result = processor
+        .override_request(
+            &user_id,
+            &request_id,
+            serde_json::from_str(r#"{"topic": {"downloadId": 4}, "fileIndex": 1}"#).unwrap(),
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(OverrideRequestError::InvalidFileIndex(1))
+    ));
+
+    processor
+        .override_request(
+            &user_id,
+            &request_id,
+            serde_json::from_str(r#"{"topic": {"downloadId": 4}, "fileIndex": 0}"#).unwrap(),
+        )
+        .await
+        .unwrap();
+
+    // The pinned file is used although its name doesn't match the title.
+    processor
+        .process_request(&user_id, &request_id, &CancellationToken::new())
+        .await
+        .unwrap();
+}
+
+#[actix_rt::test]
+async fn test_processing_track_request_from_single_image_rip() {
+    let audio_splitter = Arc::new(AudioSplitterMock::new());
+    let audio_processor = Arc::new(AudioProcessorMock::new());
+    let processor = ProcessorSetup {
+        audio_splitter: audio_splitter.clone(),
+        audio_processor: audio_processor.clone(),
+        download_directory: "tests/fixtures".into(),
+        ..ProcessorSetup::default()
+    }
+    .build();
+    let user_id = UserId(1);
+    let metadata = AudioMetadata {
+        title: "Rain In The Forest".into(),
+        artist: "Ted Irens".into(),
+        album: "Life @ Mirror".into(),
+    };
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                validate_metadata: false,
+            },
+            &RadioManagerChannelId(1),
+        )
+        .await
+        .unwrap();
+
+    processor
+        .process_request(&user_id, &request_id, &CancellationToken::new())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        vec![(
+            "tests/fixtures/Ted Irens - Life @ Mirror.flac".to_string(),
+            Duration::from_millis(312_400),
+            Some(Duration::from_millis(624_133)),
+            "tests/fixtures/02. Rain In The Forest.flac".to_string(),
+        )],
+        *audio_splitter.splits.lock().unwrap()
+    );
+    assert_eq!(
+        vec![AudioTags {
+            metadata,
+            track_number: Some(2),
+            year: Some(2012),
+            path_to_cover_art: None,
+        }],
+        *audio_processor.tags.lock().unwrap()
+    );
+}
+
+#[actix_rt::test]
+async fn test_rejecting_single_image_rip_without_requested_track() {
+    let audio_splitter = Arc::new(AudioSplitterMock::new());
+    let torrent_client = Arc::new(TorrentClientMock::new());
+    let processor = ProcessorSetup {
+        audio_splitter: audio_splitter.clone(),
+        torrent_client: torrent_client.clone(),
+        download_directory: "tests/fixtures".into(),
+        ..ProcessorSetup::default()
+    }
+    .build();
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Morning Dew".into(),
+                artist: "Ted Irens".into(),
+                album: "Life @ Mirror".into(),
+            },
+            &CreateRequestOptions {
+                validate_metadata: false,
+            },
+            &RadioManagerChannelId(1),
+        )
+        .await
+        .unwrap();
+
+    let result = processor
+        .process_request(&user_id, &request_id, &CancellationToken::new())
+        .await;
+
+    assert!(matches!(result, Err(ProcessRequestError::TrackNotFound)));
+    assert!(audio_splitter.splits.lock().unwrap().is_empty());
+    assert_eq!(vec![TorrentId(2)], *torrent_client.deleted.lock().unwrap());
+}
+
+#[actix_rt::test]
+async fn test_rejecting_torrent_exceeding_size_limit() {
+    let audio_splitter = Arc::new(AudioSplitterMock::new());
+    let processor = ProcessorSetup {
+        audio_splitter: audio_splitter.clone(),
+        file_selection: FileSelectionOptions {
+            preference: FilePreference::Smallest,
+            max_file_size: Some(100 * 1024 * 1024),
+        },
+        download_directory: "tests/fixtures".into(),
+        ..ProcessorSetup::default()
+    }
+    .build();
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Rain In The Forest".into(),
+                artist: "Ted Irens".into(),
+                album: "Life @ Mirror".into(),
+            },
+            &CreateRequestOptions {
+                validate_metadata: false,
+            },
+            &RadioManagerChannelId(1),
+        )
+        .await
+        .unwrap();
+
+    let result = processor
+        .process_request(&user_id, &request_id, &CancellationToken::new())
+        .await;
+
+    assert!(matches!(result, Err(ProcessRequestError::TrackNotFound)));
+    assert!(audio_splitter.splits.lock().unwrap().is_empty());
+}
+
+#[actix_rt::test]
+async fn test_getting_track_request_details() {
+    let processor = ProcessorSetup::default().build();
+    let user_id = UserId(1);
+    let metadata = AudioMetadata {
+        title: "Sunday Breakfast".into(),
+        artist: "Ted Irens".into(),
+        album: "Foo".into(),
+    };
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                validate_metadata: false,
+            },
+            &RadioManagerChannelId(1),
+        )
+        .await
+        .unwrap();
+
+    let details = processor
+        .get_request_details(&user_id, &request_id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(Some(metadata), details.metadata);
+    assert_eq!(
+        Some(TrackRequestProcessingStep::SearchLocalLibrary),
+        details.step
+    );
+    assert_eq!(None, details.estimated_download_size);
+}
+
+#[actix_rt::test]
+async fn test_processing_track_request_from_single_file_torrent() {
+    let audio_processor = Arc::new(AudioProcessorMock::new());
+    let processor = ProcessorSetup {
+        audio_processor: audio_processor.clone(),
+        ..ProcessorSetup::default()
+    }
+    .build();
+    let user_id = UserId(1);
+    let metadata = AudioMetadata {
+        title: "Rising Star".into(),
+        artist: "Ted Irens".into(),
+        album: "Rising Star".into(),
+    };
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &metadata,
+            &CreateRequestOptions {
+                validate_metadata: false,
+            },
+            &RadioManagerChannelId(1),
+        )
+        .await
+        .unwrap();
+
+    processor
+        .process_request(&user_id, &request_id, &CancellationToken::new())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        vec![AudioTags {
+            metadata,
+            track_number: None,
+            year: Some(2013),
+            path_to_cover_art: None,
+        }],
+        *audio_processor.tags.lock().unwrap()
+    );
+}
+
+#[actix_rt::test]
+async fn test_processing_track_request_from_magnet_link() {
+    let torrent_client = Arc::new(TorrentClientMock::new());
+    let processor = ProcessorSetup {
+        torrent_client: torrent_client.clone(),
+        ..ProcessorSetup::default()
+    }
+    .build();
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Children".into(),
+                artist: "Robert Miles".into(),
+                album: "Dreamland".into(),
+            },
+            &CreateRequestOptions {
+                validate_metadata: false,
+            },
+            &RadioManagerChannelId(1),
+        )
+        .await
+        .unwrap();
+
+    // Processing never finishes if the metadata isn't fetched.
+    actix_rt::time::timeout(
+        Duration::from_secs(60),
+        processor.process_request(&user_id, &request_id, &CancellationToken::new()),
+    )
+    .await
+    .expect("Metadata of the magnet link should be fetched")
+    .unwrap();
+
+    assert!(torrent_client.paused.lock().unwrap().is_empty());
+}
+
+#[actix_rt::test]
+async fn test_processing_track_request_from_local_library() {
+    let audio_processor = Arc::new(AudioProcessorMock::new());
+    let processor = ProcessorSetup {
+        audio_processor: audio_processor.clone(),
+        ..ProcessorSetup::default()
+    }
+    .build();
+    let user_id = UserId(1);
+    let request_id = processor
+        .create_request(
+            &user_id,
+            &AudioMetadata {
+                title: "Another Moon Night".into(),
+                artist: "Ted Irens".into(),
+                album: "Life @ Mirror".into(),
+            },
+            &CreateRequestOptions {
+                validate_metadata: false,
+            },
+            &RadioManagerChannelId(1),
+        )
+        .await
+        .unwrap();
+
+    processor
+        .process_request(&user_id, &request_id, &CancellationToken::new())
+        .await
+        .unwrap();
+
+    let tags = audio_processor.tags.lock().unwrap();
+    assert_eq!(1, tags.len());
+    assert_eq!(Some(3), tags[0].track_number);
+}
@@ -5,22 +5,52 @@ use super::track_request_processor::{
 use crate::services::track_request_processor::{TopicData, TopicId};
 
 #[test]
-fn should_return_search_audio_album_by_default() {
+fn should_return_search_local_library_by_default() {
     let state = TrackRequestProcessingState::default();
 
+    assert_eq!(
+        state.get_step(),
+        TrackRequestProcessingStep::SearchLocalLibrary
+    )
+}
+
+#[test]
+fn should_return_search_audio_album_if_local_library_is_searched() {
+    let state = TrackRequestProcessingState {
+        local_library_searched: true,
+        ..TrackRequestProcessingState::default()
+    };
+
     assert_eq!(
         state.get_step(),
         TrackRequestProcessingStep::GetTopicsIntoQueue
     )
 }
 
+#[test]
+fn should_return_process_audio_file_if_track_is_found_in_local_library() {
+    let state = TrackRequestProcessingState {
+        local_library_searched: true,
+        path_to_downloaded_file: Some("/music/Ted Irens/01. Sunday Breakfast.flac".into()),
+        ..TrackRequestProcessingState::default()
+    };
+
+    assert_eq!(
+        state.get_step(),
+        TrackRequestProcessingStep::ProcessAudioFile
+    )
+}
+
 #[test]
 fn should_return_get_album_url_if_current_topic_id_is_set() {
     let state = TrackRequestProcessingState {
         topics_queue: Some(vec![TopicData {
-            topic_id: TopicId(1),
-            download_id: DownloadId(1),
+            topic_id: TopicId("1".into()),
+            download_id: DownloadId("1".into()),
             title: "Title".into(),
+            provider: "rutracker".into(),
+            info_hash: None,
+            ..TopicData::default()
         }]),
         ..TrackRequestProcessingState::default()
     };
@@ -35,9 +65,12 @@ fn should_return_get_album_url_if_current_topic_id_is_set() {
 fn should_return_download_album_if_current_url_is_set() {
     let state = TrackRequestProcessingState {
         topics_queue: Some(vec![TopicData {
-            topic_id: TopicId(1),
-            download_id: DownloadId(1),
+            topic_id: TopicId("1".into()),
+            download_id: DownloadId("1".into()),
             title: "Title".into(),
+            provider: "rutracker".into(),
+            info_hash: None,
+            ..TopicData::default()
         }]),
         current_torrent_data: Some(vec![]),
         ..TrackRequestProcessingState::default()
@@ -46,13 +79,69 @@ fn should_return_download_album_if_current_url_is_set() {
     assert_eq!(state.get_step(), TrackRequestProcessingStep::Download)
 }
 
+#[test]
+fn should_return_download_album_if_current_magnet_link_is_set() {
+    let state = TrackRequestProcessingState {
+        topics_queue: Some(vec![]),
+        current_magnet_link: Some("magnet:?xt=urn:btih:foo".into()),
+        ..TrackRequestProcessingState::default()
+    };
+
+    assert_eq!(state.get_step(), TrackRequestProcessingStep::Download)
+}
+
+#[test]
+fn should_return_await_metadata_if_magnet_link_is_added() {
+    let state = TrackRequestProcessingState {
+        topics_queue: Some(vec![]),
+        current_magnet_link: Some("magnet:?xt=urn:btih:foo".into()),
+        current_torrent_id: Some(TorrentId(1)),
+        ..TrackRequestProcessingState::default()
+    };
+
+    assert_eq!(state.get_step(), TrackRequestProcessingStep::AwaitMetadata)
+}
+
+#[test]
+fn should_return_check_download_status_if_magnet_link_files_are_selected() {
+    let state = TrackRequestProcessingState {
+        topics_queue: Some(vec![]),
+        current_magnet_link: Some("magnet:?xt=urn:btih:foo".into()),
+        current_torrent_id: Some(TorrentId(1)),
+        estimated_download_size: Some(1024),
+        ..TrackRequestProcessingState::default()
+    };
+
+    assert_eq!(
+        state.get_step(),
+        TrackRequestProcessingStep::CheckDownloadStatus
+    )
+}
+
+#[test]
+fn should_return_await_cue_sheets_if_only_cue_sheets_are_selected() {
+    let state = TrackRequestProcessingState {
+        topics_queue: Some(vec![]),
+        current_torrent_data: Some(vec![]),
+        current_torrent_id: Some(TorrentId(1)),
+        estimated_download_size: Some(1024),
+        awaiting_cue_sheets: true,
+        ..TrackRequestProcessingState::default()
+    };
+
+    assert_eq!(state.get_step(), TrackRequestProcessingStep::AwaitCueSheets)
+}
+
 #[test]
 fn should_return_check_download_status_if_current_download_id_is_set() {
     let state = TrackRequestProcessingState {
         topics_queue: Some(vec![TopicData {
-            topic_id: TopicId(1),
-            download_id: DownloadId(1),
+            topic_id: TopicId("1".into()),
+            download_id: DownloadId("1".into()),
             title: "Title".into(),
+            provider: "rutracker".into(),
+            info_hash: None,
+            ..TopicData::default()
         }]),
         current_torrent_data: Some(vec![]),
         current_torrent_id: Some(TorrentId(1)),
@@ -66,12 +155,15 @@ fn should_return_check_download_status_if_current_download_id_is_set() {
 }
 
 #[test]
-fn should_return_upload_to_radioterio_if_path_to_downloaded_file_is_set() {
+fn should_return_process_audio_file_if_path_to_downloaded_file_is_set() {
     let state = TrackRequestProcessingState {
         topics_queue: Some(vec![TopicData {
-            topic_id: TopicId(1),
-            download_id: DownloadId(1),
+            topic_id: TopicId("1".into()),
+            download_id: DownloadId("1".into()),
             title: "Title".into(),
+            provider: "rutracker".into(),
+            info_hash: None,
+            ..TopicData::default()
         }]),
         current_torrent_data: Some(vec![]),
         current_torrent_id: Some(TorrentId(1)),
@@ -79,6 +171,30 @@ fn should_return_upload_to_radioterio_if_path_to_downloaded_file_is_set() {
         ..TrackRequestProcessingState::default()
     };
 
+    assert_eq!(
+        state.get_step(),
+        TrackRequestProcessingStep::ProcessAudioFile
+    )
+}
+
+#[test]
+fn should_return_upload_to_radioterio_if_path_to_processed_file_is_set() {
+    let state = TrackRequestProcessingState {
+        topics_queue: Some(vec![TopicData {
+            topic_id: TopicId("1".into()),
+            download_id: DownloadId("1".into()),
+            title: "Title".into(),
+            provider: "rutracker".into(),
+            info_hash: None,
+            ..TopicData::default()
+        }]),
+        current_torrent_data: Some(vec![]),
+        current_torrent_id: Some(TorrentId(1)),
+        path_to_downloaded_file: Some("path/to/file".into()),
+        path_to_processed_file: Some("path/to/processed/file".into()),
+        ..TrackRequestProcessingState::default()
+    };
+
     assert_eq!(
         state.get_step(),
         TrackRequestProcessingStep::UploadToRadioManager
@@ -89,9 +205,12 @@ fn should_return_upload_to_radioterio_if_path_to_downloaded_file_is_set() {
 fn should_return_add_track_to_radioterio_channel_if_radioterio_track_id_is_set() {
     let state = TrackRequestProcessingState {
         topics_queue: Some(vec![TopicData {
-            topic_id: TopicId(1),
-            download_id: DownloadId(1),
+            topic_id: TopicId("1".into()),
+            download_id: DownloadId("1".into()),
             title: "Title".into(),
+            provider: "rutracker".into(),
+            info_hash: None,
+            ..TopicData::default()
         }]),
         current_torrent_data: Some(vec![]),
         current_torrent_id: Some(TorrentId(1)),
@@ -110,9 +229,12 @@ fn should_return_add_track_to_radioterio_channel_if_radioterio_track_id_is_set()
 fn should_return_finish_if_radioterio_link_id_is_set() {
     let state = TrackRequestProcessingState {
         topics_queue: Some(vec![TopicData {
-            topic_id: TopicId(1),
-            download_id: DownloadId(1),
+            topic_id: TopicId("1".into()),
+            download_id: DownloadId("1".into()),
             title: "Title".into(),
+            provider: "rutracker".into(),
+            info_hash: None,
+            ..TopicData::default()
         }]),
         current_torrent_data: Some(vec![]),
         current_torrent_id: Some(TorrentId(1)),
@@ -124,3 +246,22 @@ fn should_return_finish_if_radioterio_link_id_is_set() {
 
     assert_eq!(state.get_step(), TrackRequestProcessingStep::Finish)
 }
+
+#[test]
+fn should_deserialize_topics_with_numeric_ids() {
+    let topic: TopicData =
+        serde_json::from_str(r#"{"topic_id":1183770,"download_id":1183770,"title":"Title"}"#)
+            .unwrap();
+
+    assert_eq!(
+        TopicData {
+            topic_id: TopicId("1183770".into()),
+            download_id: DownloadId("1183770".into()),
+            title: "Title".into(),
+            provider: "rutracker".into(),
+            info_hash: None,
+            ..TopicData::default()
+        },
+        topic
+    );
+}
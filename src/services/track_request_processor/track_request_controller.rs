@@ -1,11 +1,14 @@
 use crate::services::track_request_processor::{
-    AudioMetadata, CreateRequestError, CreateRequestOptions, RadioManagerChannelId, RequestId,
-    StateStorageError, StateStorageTrait, TrackRequestProcessingContext,
-    TrackRequestProcessingState,
+    AudioMetadata, CreateRequestError, CreateRequestOptions, OverrideRequestError,
+    RadioManagerChannelId, RequestId, RequestOverride, StateStorageError, StateStorageTrait,
+    TrackRequestProcessingContext, TrackRequestProcessingState,
 };
 use crate::services::TrackRequestProcessor;
 use crate::types::UserId;
-use std::sync::Arc;
+use actix_rt::task::JoinHandle;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info};
 
 #[derive(Debug, thiserror::Error)]
@@ -16,8 +19,15 @@ pub(crate) enum TrackRequestControllerError {
     TrackRequestError(#[from] CreateRequestError),
 }
 
+struct RequestTask {
+    handle: JoinHandle<()>,
+    cancellation: CancellationToken,
+}
+
 pub(crate) struct TrackRequestController {
     track_request_processor: Arc<TrackRequestProcessor>,
+    /// Tasks processing the requests, so they can be stopped.
+    tasks: Mutex<HashMap<RequestId, RequestTask>>,
 }
 
 impl TrackRequestController {
@@ -27,6 +37,7 @@ impl TrackRequestController {
     ) -> Result<Self, TrackRequestControllerError> {
         let controller = Self {
             track_request_processor,
+            tasks: Mutex::new(HashMap::new()),
         };
 
         debug!("Loading tasks...");
@@ -44,18 +55,12 @@ impl TrackRequestController {
         &self,
         user_id: &UserId,
         track_metadata: &AudioMetadata,
+        options: &CreateRequestOptions,
         target_channel_id: &RadioManagerChannelId,
     ) -> Result<RequestId, TrackRequestControllerError> {
         let request_id = self
             .track_request_processor
-            .create_request(
-                user_id,
-                track_metadata,
-                &CreateRequestOptions {
-                    validate_metadata: false,
-                },
-                target_channel_id,
-            )
+            .create_request(user_id, track_metadata, options, target_channel_id)
             .await?;
 
         self.spawn_task(&user_id, &request_id);
@@ -63,20 +68,68 @@ impl TrackRequestController {
         Ok(request_id)
     }
 
+    /// Stops processing the request, if it's still going, to rewrite its
+    /// state and then processes it again from the pinned topic or file.
+    pub(crate) async fn override_request(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        request_override: RequestOverride,
+    ) -> Result<(), OverrideRequestError> {
+        let task = self
+            .tasks
+            .lock()
+            .expect("Unable to lock tasks")
+            .remove(request_id);
+
+        let was_running = task.as_ref().is_some_and(|task| !task.handle.is_finished());
+
+        // Aborting a step halfway could leave e.g. a torrent added but not
+        // saved to the state, so the current step is let to finish.
+        if let Some(task) = task {
+            task.cancellation.cancel();
+            let _ = task.handle.await;
+        }
+
+        let result = self
+            .track_request_processor
+            .override_request(user_id, request_id, request_override)
+            .await;
+
+        // Requests that were being processed go on even if the override fails.
+        if result.is_ok() || was_running {
+            self.spawn_task(user_id, request_id);
+        }
+
+        result
+    }
+
     fn spawn_task(&self, user_id: &UserId, request_id: &RequestId) {
-        actix_rt::spawn({
+        let cancellation = CancellationToken::new();
+        let handle = actix_rt::spawn({
             let user_id = user_id.clone();
             let request_id = request_id.clone();
             let track_request_processor = self.track_request_processor.clone();
+            let cancellation = cancellation.clone();
 
             async move {
                 if let Err(error) = track_request_processor
-                    .process_request(&user_id, &request_id)
+                    .process_request(&user_id, &request_id, &cancellation)
                     .await
                 {
                     error!(?error, "Track request processing failed");
                 }
             }
         });
+
+        let mut tasks = self.tasks.lock().expect("Unable to lock tasks");
+        tasks.retain(|_, task| !task.handle.is_finished());
+        tasks.insert(
+            request_id.clone(),
+            RequestTask {
+                handle,
+                cancellation,
+            },
+        );
     }
 }
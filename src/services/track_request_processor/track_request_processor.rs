@@ -1,14 +1,24 @@
-use crate::services::torrent_parser::{get_files, TorrentParserError};
+use crate::services::cue_sheet::{decode_cue_sheet, parse_cue_sheet, CueSheetError, CueTrack};
+use crate::services::query_planner::QueryPlanner;
+use crate::services::ranking::{rank_topics, RankingPolicies, TopicScore};
+use crate::services::torrent_parser::{parse_torrent, TorrentFile, TorrentParserError};
 use crate::types::UserId;
-use crate::utils::{contains_ignore_case, contains_in_filename_ignore_case};
+use crate::utils::{
+    contains_ignore_case, contains_in_filename_ignore_case, has_extension_ignore_case,
+    parse_release_year, parse_track_number,
+};
 use async_trait::async_trait;
+use futures::future::select;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::io::ErrorKind;
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::pin::pin;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
@@ -43,6 +53,206 @@ impl std::fmt::Display for AudioMetadata {
     }
 }
 
+/// Audio formats a whole release is ripped into when it comes as a single
+/// image accompanied by a cue sheet.
+const CUE_IMAGE_EXTENSIONS: [&str; 4] = ["flac", "ape", "wv", "wav"];
+
+fn is_cue_sheet(filepath: &str) -> bool {
+    has_extension_ignore_case(filepath, "cue")
+}
+
+fn is_cue_image(filepath: &str) -> bool {
+    CUE_IMAGE_EXTENSIONS
+        .iter()
+        .any(|ext| has_extension_ignore_case(filepath, ext))
+}
+
+/// Names of the cover art images commonly shipped along with the tracks.
+const COVER_ART_FILE_NAMES: [&str; 6] = [
+    "folder.jpg",
+    "cover.jpg",
+    "front.jpg",
+    "folder.png",
+    "cover.png",
+    "front.png",
+];
+
+fn is_cover_art(filepath: &str) -> bool {
+    Path::new(filepath)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .is_some_and(|name| COVER_ART_FILE_NAMES.contains(&name.as_str()))
+}
+
+fn is_cue_image_release(files: &[String]) -> bool {
+    files.iter().any(|f| is_cue_sheet(f)) && files.iter().any(|f| is_cue_image(f))
+}
+
+const LOSSLESS_EXTENSIONS: [&str; 5] = ["flac", "ape", "wv", "wav", "aiff"];
+
+const LOSSY_EXTENSIONS: [&str; 6] = ["mp3", "m4a", "aac", "ogg", "opus", "wma"];
+
+fn is_lossless(filepath: &str) -> bool {
+    LOSSLESS_EXTENSIONS
+        .iter()
+        .any(|ext| has_extension_ignore_case(filepath, ext))
+}
+
+pub(crate) fn is_audio_file(filepath: &str) -> bool {
+    is_lossless(filepath)
+        || LOSSY_EXTENSIONS
+            .iter()
+            .any(|ext| has_extension_ignore_case(filepath, ext))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FilePreference {
+    /// Lossless files first, the smaller one among the same kind.
+    Lossless,
+    /// The smallest file regardless of its format.
+    Smallest,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct FileSelectionOptions {
+    pub(crate) preference: FilePreference,
+    /// Files larger than this many bytes are never downloaded.
+    pub(crate) max_file_size: Option<u64>,
+}
+
+impl Default for FileSelectionOptions {
+    fn default() -> Self {
+        Self {
+            preference: FilePreference::Lossless,
+            max_file_size: None,
+        }
+    }
+}
+
+impl FileSelectionOptions {
+    fn fits(&self, file: &TorrentFile) -> bool {
+        match self.max_file_size {
+            Some(max_file_size) => file.length <= max_file_size,
+            None => true,
+        }
+    }
+}
+
+/// Picks the file to download among those matching the requested track.
+/// Audio files are preferred over anything else that happens to match.
+fn select_track_file(
+    files: &[TorrentFile],
+    title: &str,
+    options: &FileSelectionOptions,
+) -> Option<usize> {
+    files
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| {
+            !f.padding && contains_in_filename_ignore_case(&f.path, title) && options.fits(f)
+        })
+        .min_by_key(|(_, f)| {
+            let lossy = match options.preference {
+                FilePreference::Lossless => !is_lossless(&f.path),
+                FilePreference::Smallest => false,
+            };
+            (!is_audio_file(&f.path), lossy, f.length)
+        })
+        .map(|(index, _)| index)
+}
+
+fn get_total_length(files: &[TorrentFile], indexes: &[usize]) -> u64 {
+    indexes.iter().map(|index| files[*index].length).sum()
+}
+
+fn select_cue_sheets(files: &[TorrentFile]) -> Vec<usize> {
+    files
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| is_cue_sheet(&f.path))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+fn is_cue_sheets_only(files: &[TorrentFile], indexes: &[usize]) -> bool {
+    !indexes.is_empty()
+        && indexes
+            .iter()
+            .all(|index| is_cue_sheet(&files[*index].path))
+}
+
+/// Adds the cover art found next to the selected files.
+fn select_cover_art(files: &[TorrentFile], mut selected_files: Vec<usize>) -> Vec<usize> {
+    let selected_dirs = selected_files
+        .iter()
+        .map(|index| Path::new(&files[*index].path).parent())
+        .collect::<HashSet<_>>();
+    selected_files.extend(
+        files
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| {
+                is_cover_art(&f.path) && selected_dirs.contains(&Path::new(&f.path).parent())
+            })
+            .map(|(index, _)| index),
+    );
+
+    selected_files
+}
+
+/// Resolves the audio image a cue sheet refers to. Images are often
+/// re-encoded after the cue sheet was made (e.g. WAV to FLAC), so the file
+/// with the same name but another audio extension is accepted too.
+fn find_cue_image(files: &[String], cue_dir: &Path, image_name: &str) -> Option<String> {
+    let image_stem = Path::new(image_name).file_stem()?.to_string_lossy();
+    let candidates = files
+        .iter()
+        .filter(|f| is_cue_image(f) && Path::new(f).parent() == Some(cue_dir))
+        .collect::<Vec<_>>();
+
+    candidates
+        .iter()
+        .find(|f| Path::new(f).file_name().map(|n| n.to_string_lossy()) == Some(image_name.into()))
+        .or_else(|| {
+            candidates.iter().find(|f| {
+                Path::new(f).file_stem().map(|n| n.to_string_lossy()) == Some(image_stem.clone())
+            })
+        })
+        .map(|f| f.to_string())
+}
+
+/// Track of an audio image as listed by the cue sheet next to it.
+struct CueImageTrack {
+    cue_dir: PathBuf,
+    image_path: String,
+    track: CueTrack,
+}
+
+/// Tags written into the audio file before it's uploaded.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub(crate) struct AudioTags {
+    pub(crate) metadata: AudioMetadata,
+    pub(crate) track_number: Option<u32>,
+    pub(crate) year: Option<u32>,
+    pub(crate) path_to_cover_art: Option<String>,
+}
+
+/// Looks for the cover art image next to the audio file.
+async fn find_cover_art(path_to_audio_file: &str) -> Option<String> {
+    let dir = Path::new(path_to_audio_file).parent()?;
+    let mut dir_reader = tokio::fs::read_dir(dir).await.ok()?;
+
+    while let Ok(Some(entry)) = dir_reader.next_entry().await {
+        let path = entry.path().to_string_lossy().to_string();
+        if is_cover_art(&path) {
+            return Some(path);
+        }
+    }
+
+    None
+}
+
 #[derive(Eq, PartialEq, Clone, Hash, Debug, Serialize, Deserialize)]
 pub(crate) struct RadioManagerChannelId(pub(crate) u64);
 
@@ -78,11 +288,30 @@ impl std::fmt::Display for RadioManagerLinkId {
     }
 }
 
-#[derive(Eq, PartialEq, Clone, Hash, Debug, Serialize, Deserialize)]
-pub(crate) struct TopicId(pub(crate) u64);
+/// Ids used to be RuTracker numbers, other search providers identify their
+/// topics by arbitrary strings.
+fn string_or_number<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(u64),
+    }
+
+    Ok(match StringOrNumber::deserialize(deserializer)? {
+        StringOrNumber::String(value) => value,
+        StringOrNumber::Number(value) => value.to_string(),
+    })
+}
+
+#[derive(Eq, PartialEq, Clone, Hash, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct TopicId(#[serde(deserialize_with = "string_or_number")] pub(crate) String);
 
 impl Deref for TopicId {
-    type Target = u64;
+    type Target = str;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -95,11 +324,11 @@ impl std::fmt::Display for TopicId {
     }
 }
 
-#[derive(Eq, PartialEq, Clone, Hash, Debug, Serialize, Deserialize)]
-pub(crate) struct DownloadId(pub(crate) u64);
+#[derive(Eq, PartialEq, Clone, Hash, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct DownloadId(#[serde(deserialize_with = "string_or_number")] pub(crate) String);
 
 impl Deref for DownloadId {
-    type Target = u64;
+    type Target = str;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -129,11 +358,68 @@ impl std::fmt::Display for TorrentId {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+fn default_provider() -> String {
+    // Topics persisted before search providers became pluggable all came
+    // from RuTracker.
+    "rutracker".to_string()
+}
+
+/// Search query planned from the request metadata. Providers supporting the
+/// structured search use the artist and the album, the others the text.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SearchQuery {
+    pub(crate) text: String,
+    pub(crate) artist: Option<String>,
+    pub(crate) album: Option<String>,
+}
+
+impl std::fmt::Display for SearchQuery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub(crate) struct TopicData {
     pub(crate) topic_id: TopicId,
     pub(crate) download_id: DownloadId,
     pub(crate) title: String,
+    /// Name of the search provider the topic was found by.
+    #[serde(default = "default_provider")]
+    pub(crate) provider: String,
+    #[serde(default)]
+    pub(crate) info_hash: Option<String>,
+    #[serde(default)]
+    pub(crate) seeders: Option<u64>,
+    #[serde(default)]
+    pub(crate) leechers: Option<u64>,
+    /// Size of the torrent in bytes.
+    #[serde(default)]
+    pub(crate) size: Option<u64>,
+    #[serde(default)]
+    pub(crate) category: Option<String>,
+    #[serde(default)]
+    pub(crate) author: Option<String>,
+    /// Unix timestamp of the moment the torrent was published.
+    #[serde(default)]
+    pub(crate) published_at: Option<u64>,
+    /// Whether the release has been checked by the tracker moderators.
+    #[serde(default)]
+    pub(crate) verified: Option<bool>,
+    /// Set once the topic is ranked against the other search results.
+    #[serde(default)]
+    pub(crate) score: Option<TopicScore>,
+    /// Search query the topic was found by.
+    #[serde(default)]
+    pub(crate) query: Option<String>,
+}
+
+/// What a topic resolves to: either the torrent file itself, or a magnet
+/// link the torrent client fetches the metadata by.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) enum TorrentSource {
+    File(Vec<u8>),
+    Magnet(String),
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -171,40 +457,74 @@ impl TrackRequestProcessingContext {
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub(crate) struct TrackRequestProcessingState {
+    #[serde(default)]
+    pub(crate) local_library_searched: bool,
     pub(crate) topics_queue: Option<Vec<TopicData>>,
+    #[serde(default)]
+    pub(crate) current_topic: Option<TopicData>,
     pub(crate) current_torrent_data: Option<Vec<u8>>,
+    #[serde(default)]
+    pub(crate) current_magnet_link: Option<String>,
     pub(crate) current_torrent_id: Option<TorrentId>,
+    #[serde(default)]
+    pub(crate) estimated_download_size: Option<u64>,
+    /// Only the cue sheets are downloaded until the audio image listing the
+    /// requested track is known.
+    #[serde(default)]
+    pub(crate) awaiting_cue_sheets: bool,
     pub(crate) path_to_downloaded_file: Option<String>,
+    #[serde(default)]
+    pub(crate) path_to_processed_file: Option<String>,
     pub(crate) radio_manager_track_id: Option<RadioManagerTrackId>,
     pub(crate) radio_manager_link_id: Option<RadioManagerLinkId>,
+    /// Index of the file to download as the track, chosen by an operator.
+    #[serde(default)]
+    pub(crate) pinned_file_index: Option<usize>,
 }
 
 impl TrackRequestProcessingState {
     pub(crate) fn get_step(&self) -> TrackRequestProcessingStep {
-        if self.topics_queue.is_none() {
+        if self.topics_queue.is_none() && !self.local_library_searched {
+            TrackRequestProcessingStep::SearchLocalLibrary
+        } else if self.path_to_downloaded_file.is_some() {
+            // The track is either downloaded or found in the local library.
+            if self.path_to_processed_file.is_none() && self.radio_manager_track_id.is_none() {
+                TrackRequestProcessingStep::ProcessAudioFile
+            } else if self.radio_manager_track_id.is_none() {
+                TrackRequestProcessingStep::UploadToRadioManager
+            } else if self.radio_manager_link_id.is_none() {
+                TrackRequestProcessingStep::AddToRadioManagerChannel
+            } else {
+                TrackRequestProcessingStep::Finish
+            }
+        } else if self.topics_queue.is_none() {
             TrackRequestProcessingStep::GetTopicsIntoQueue
-        } else if self.current_torrent_data.is_none() {
+        } else if self.current_torrent_data.is_none() && self.current_magnet_link.is_none() {
             TrackRequestProcessingStep::DownloadNextTorrentFile
         } else if self.current_torrent_id.is_none() {
             TrackRequestProcessingStep::Download
-        } else if self.path_to_downloaded_file.is_none() {
-            TrackRequestProcessingStep::CheckDownloadStatus
-        } else if self.radio_manager_track_id.is_none() {
-            TrackRequestProcessingStep::UploadToRadioManager
-        } else if self.radio_manager_link_id.is_none() {
-            TrackRequestProcessingStep::AddToRadioManagerChannel
+        } else if self.current_magnet_link.is_some() && self.estimated_download_size.is_none() {
+            // Files of magnet links are selected, and the download size is
+            // estimated, once the metadata is fetched.
+            TrackRequestProcessingStep::AwaitMetadata
+        } else if self.awaiting_cue_sheets {
+            TrackRequestProcessingStep::AwaitCueSheets
         } else {
-            TrackRequestProcessingStep::Finish
+            TrackRequestProcessingStep::CheckDownloadStatus
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub(crate) enum TrackRequestProcessingStep {
+    SearchLocalLibrary,
     GetTopicsIntoQueue,
     DownloadNextTorrentFile,
     Download,
+    AwaitMetadata,
+    AwaitCueSheets,
     CheckDownloadStatus,
+    ProcessAudioFile,
     UploadToRadioManager,
     AddToRadioManagerChannel,
     Finish,
@@ -213,11 +533,37 @@ pub(crate) enum TrackRequestProcessingStep {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub(crate) enum TrackRequestProcessingStatus {
     Processing,
+    /// Search provider has to be given a rest before the request continues.
+    WaitingForRateLimit,
     NotFound,
     Failed,
     Finished,
 }
 
+/// Progress of a single track request as reported to the user.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TrackRequestDetails {
+    pub(crate) status: Option<TrackRequestProcessingStatus>,
+    pub(crate) metadata: Option<AudioMetadata>,
+    pub(crate) step: Option<TrackRequestProcessingStep>,
+    pub(crate) topic: Option<TopicData>,
+    /// Topics left to try, best first.
+    pub(crate) queue: Option<Vec<TopicData>>,
+    pub(crate) estimated_download_size: Option<u64>,
+}
+
+/// Topic the search step would queue, along with what its torrent has.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SearchResult {
+    pub(crate) topic: TopicData,
+    /// Only known for the fetched torrents, not for magnet links.
+    pub(crate) has_requested_track: Option<bool>,
+    /// Paths of the files that would be downloaded.
+    pub(crate) selected_files: Option<Vec<String>>,
+}
+
 #[derive(Debug, Deserialize)]
 pub(crate) struct RadioManagerChannelTrack {
     pub(crate) album: String,
@@ -290,6 +636,12 @@ impl StateStorageError {
     pub(crate) fn not_found() -> Self {
         StateStorageError(Box::new(std::io::Error::from(ErrorKind::NotFound)))
     }
+
+    pub(crate) fn is_not_found(&self) -> bool {
+        self.0
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|error| error.kind() == ErrorKind::NotFound)
+    }
 }
 
 impl std::fmt::Display for StateStorageError {
@@ -300,11 +652,22 @@ impl std::fmt::Display for StateStorageError {
 
 #[async_trait]
 pub(crate) trait SearchProviderTrait {
-    async fn find_all(&self, query: &str) -> Result<Vec<TopicData>, SearchProviderError>;
+    async fn find_all(&self, query: &SearchQuery) -> Result<Vec<TopicData>, SearchProviderError>;
     async fn download_torrent(
         &self,
-        download_id: &DownloadId,
-    ) -> Result<Vec<u8>, SearchProviderError>;
+        topic: &TopicData,
+    ) -> Result<TorrentSource, SearchProviderError>;
+    /// Track titles of the topic, when they're known without downloading
+    /// the torrent file.
+    async fn get_tracklist(
+        &self,
+        _topic: &TopicData,
+    ) -> Result<Option<Vec<String>>, SearchProviderError> {
+        Ok(None)
+    }
+    async fn check_connection(&self) -> Result<(), SearchProviderError> {
+        Ok(())
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -316,6 +679,39 @@ impl std::fmt::Display for SearchProviderError {
     }
 }
 
+impl SearchProviderError {
+    /// How long to wait before retrying, if the provider has rate limited us.
+    pub(crate) fn retry_after(&self) -> Option<Duration> {
+        self.0
+            .downcast_ref::<RateLimitedError>()
+            .map(|error| error.retry_after)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Rate limit is exceeded, retry in {retry_after:?}")]
+pub(crate) struct RateLimitedError {
+    pub(crate) retry_after: Duration,
+}
+
+#[async_trait]
+pub(crate) trait LocalLibraryTrait {
+    /// Returns the absolute path to the audio file of the track, if any.
+    async fn find_track(
+        &self,
+        metadata: &AudioMetadata,
+    ) -> Result<Option<String>, LocalLibraryError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) struct LocalLibraryError(pub(crate) Box<dyn std::error::Error>);
+
+impl std::fmt::Display for LocalLibraryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 #[async_trait]
 pub(crate) trait TorrentClientTrait {
     async fn add_torrent(
@@ -323,6 +719,22 @@ pub(crate) trait TorrentClientTrait {
         torrent_file_data: Vec<u8>,
         selected_files_indexes: Vec<i32>,
     ) -> Result<TorrentId, TorrentClientError>;
+    /// Adds the magnet link paused, without downloading any files.
+    async fn add_magnet(&self, magnet_link: &str) -> Result<TorrentId, TorrentClientError>;
+    /// Starts the torrent. Metadata of the magnet links is only fetched for
+    /// the started torrents.
+    async fn start_torrent(&self, torrent_id: &TorrentId) -> Result<(), TorrentClientError>;
+    /// Returns `None` until the metadata of the torrent is fetched.
+    async fn get_files(
+        &self,
+        torrent_id: &TorrentId,
+    ) -> Result<Option<Vec<TorrentFile>>, TorrentClientError>;
+    /// Starts downloading the selected files only.
+    async fn select_files(
+        &self,
+        torrent_id: &TorrentId,
+        selected_files_indexes: Vec<i32>,
+    ) -> Result<(), TorrentClientError>;
     async fn get_torrent(&self, torrent_id: &TorrentId) -> Result<Torrent, TorrentClientError>;
     async fn delete_torrent(&self, torrent_id: &TorrentId) -> Result<(), TorrentClientError>;
 }
@@ -364,11 +776,91 @@ impl std::fmt::Display for RadioManagerClientError {
     }
 }
 
+#[async_trait]
+pub(crate) trait MetadataProviderTrait {
+    async fn find_recording(
+        &self,
+        metadata: &AudioMetadata,
+    ) -> Result<Option<AudioMetadata>, MetadataProviderError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) struct MetadataProviderError(pub(crate) Box<dyn std::error::Error>);
+
+impl std::fmt::Display for MetadataProviderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[async_trait]
+pub(crate) trait AudioSplitterTrait {
+    async fn split_track(
+        &self,
+        path_to_image_file: &str,
+        start: Duration,
+        end: Option<Duration>,
+        path_to_output_file: &str,
+    ) -> Result<(), AudioSplitterError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) struct AudioSplitterError(pub(crate) Box<dyn std::error::Error>);
+
+impl std::fmt::Display for AudioSplitterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[async_trait]
+pub(crate) trait AudioProcessorTrait {
+    /// Makes a tagged working copy of the audio file and returns the path to
+    /// it. The downloaded file itself is left intact as it's still seeded.
+    async fn process_audio_file(
+        &self,
+        request_id: &RequestId,
+        path_to_audio_file: &str,
+        tags: &AudioTags,
+    ) -> Result<String, AudioProcessorError>;
+    async fn remove_processed_files(
+        &self,
+        request_id: &RequestId,
+    ) -> Result<(), AudioProcessorError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) struct AudioProcessorError(pub(crate) Box<dyn std::error::Error>);
+
+impl std::fmt::Display for AudioProcessorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Waits between the steps can be cut short, as the state is saved by then.
+async fn sleep_unless_cancelled(duration: Duration, cancellation: &CancellationToken) {
+    select(
+        pin!(actix_rt::time::sleep(duration)),
+        pin!(cancellation.cancelled()),
+    )
+    .await;
+}
+
 pub(crate) struct TrackRequestProcessor {
     state_storage: Arc<dyn StateStorageTrait + Send + Sync + 'static>,
     search_provider: Arc<dyn SearchProviderTrait + Send + Sync + 'static>,
+    /// Tracks found there aren't downloaded, if there is a local library.
+    local_library: Option<Arc<dyn LocalLibraryTrait + Send + Sync + 'static>>,
     torrent_client: Arc<dyn TorrentClientTrait + Send + Sync + 'static>,
     radio_manager_client: Arc<dyn RadioManagerClientTrait + Send + Sync + 'static>,
+    /// Requests can't be validated unless there is a metadata provider.
+    metadata_provider: Option<Arc<dyn MetadataProviderTrait + Send + Sync + 'static>>,
+    audio_splitter: Arc<dyn AudioSplitterTrait + Send + Sync + 'static>,
+    audio_processor: Arc<dyn AudioProcessorTrait + Send + Sync + 'static>,
+    file_selection: FileSelectionOptions,
+    ranking_policies: RankingPolicies,
+    query_planner: QueryPlanner,
     download_directory: String,
 }
 
@@ -376,6 +868,47 @@ pub(crate) struct TrackRequestProcessor {
 pub(crate) enum CreateRequestError {
     #[error(transparent)]
     StateStorageError(#[from] StateStorageError),
+    #[error(transparent)]
+    MetadataProviderError(#[from] MetadataProviderError),
+    #[error("Requested track does not correspond to a known recording")]
+    UnknownRecording,
+    #[error("Metadata validation is not configured")]
+    MetadataValidationNotConfigured,
+}
+
+/// Operator's choice of the release for a request the bot got wrong.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RequestOverride {
+    /// Topic to download instead of the found ones.
+    #[serde(default)]
+    pub(crate) topic: Option<PinnedTopic>,
+    /// File of the torrent to download as the track.
+    #[serde(default)]
+    pub(crate) file_index: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PinnedTopic {
+    #[serde(default = "default_provider")]
+    pub(crate) provider: String,
+    pub(crate) download_id: DownloadId,
+    /// Same as the download id when not given, as on RuTracker.
+    #[serde(default)]
+    pub(crate) topic_id: Option<TopicId>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum OverrideRequestError {
+    #[error(transparent)]
+    ProcessRequestError(#[from] ProcessRequestError),
+    #[error("Track request is not found")]
+    RequestNotFound,
+    #[error("Track request has no torrent to pin the file of")]
+    NoTorrent,
+    #[error("Torrent has no file with index {0}")]
+    InvalidFileIndex(usize),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -385,11 +918,21 @@ pub(crate) enum ProcessRequestError {
     #[error(transparent)]
     SearchProviderError(#[from] SearchProviderError),
     #[error(transparent)]
+    LocalLibraryError(#[from] LocalLibraryError),
+    #[error(transparent)]
     DownloaderError(#[from] TorrentClientError),
     #[error(transparent)]
     RadioManagerError(#[from] RadioManagerClientError),
     #[error(transparent)]
     TorrentParserError(#[from] TorrentParserError),
+    #[error(transparent)]
+    CueSheetError(#[from] CueSheetError),
+    #[error(transparent)]
+    AudioSplitterError(#[from] AudioSplitterError),
+    #[error(transparent)]
+    AudioProcessorError(#[from] AudioProcessorError),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
     #[error("Request track has not been found")]
     TrackNotFound,
 }
@@ -400,22 +943,63 @@ pub(crate) struct CreateRequestOptions {
 }
 
 impl TrackRequestProcessor {
+    /// Optional collaborators and options are set with the `with_*` methods.
     pub(crate) fn new(
         state_storage: Arc<dyn StateStorageTrait + Send + Sync + 'static>,
         search_provider: Arc<dyn SearchProviderTrait + Send + Sync + 'static>,
         torrent_client: Arc<dyn TorrentClientTrait + Send + Sync + 'static>,
         radio_manager_client: Arc<dyn RadioManagerClientTrait + Send + Sync + 'static>,
+        audio_splitter: Arc<dyn AudioSplitterTrait + Send + Sync + 'static>,
+        audio_processor: Arc<dyn AudioProcessorTrait + Send + Sync + 'static>,
         download_directory: String,
     ) -> Self {
         Self {
             state_storage,
             search_provider,
+            local_library: None,
             torrent_client,
             radio_manager_client,
+            metadata_provider: None,
+            audio_splitter,
+            audio_processor,
+            file_selection: FileSelectionOptions::default(),
+            ranking_policies: RankingPolicies::default(),
+            query_planner: QueryPlanner::default(),
             download_directory,
         }
     }
 
+    pub(crate) fn with_local_library(
+        mut self,
+        local_library: Arc<dyn LocalLibraryTrait + Send + Sync + 'static>,
+    ) -> Self {
+        self.local_library = Some(local_library);
+        self
+    }
+
+    pub(crate) fn with_metadata_provider(
+        mut self,
+        metadata_provider: Arc<dyn MetadataProviderTrait + Send + Sync + 'static>,
+    ) -> Self {
+        self.metadata_provider = Some(metadata_provider);
+        self
+    }
+
+    pub(crate) fn with_file_selection(mut self, file_selection: FileSelectionOptions) -> Self {
+        self.file_selection = file_selection;
+        self
+    }
+
+    pub(crate) fn with_ranking_policies(mut self, ranking_policies: RankingPolicies) -> Self {
+        self.ranking_policies = ranking_policies;
+        self
+    }
+
+    pub(crate) fn with_query_planner(mut self, query_planner: QueryPlanner) -> Self {
+        self.query_planner = query_planner;
+        self
+    }
+
     #[tracing::instrument(skip(self))]
     pub(crate) async fn create_request(
         &self,
@@ -429,6 +1013,25 @@ impl TrackRequestProcessor {
             "Creating the new track request - {}", track_metadata
         );
 
+        let track_metadata = if options.validate_metadata {
+            let Some(metadata_provider) = &self.metadata_provider else {
+                return Err(CreateRequestError::MetadataValidationNotConfigured);
+            };
+
+            match metadata_provider.find_recording(track_metadata).await? {
+                Some(recording) => {
+                    debug!("Validated track metadata - {}", recording);
+                    recording
+                }
+                None => {
+                    warn!("Unable to find the recording - {}", track_metadata);
+                    return Err(CreateRequestError::UnknownRecording);
+                }
+            }
+        } else {
+            track_metadata.clone()
+        };
+
         let request_id = RequestId(Uuid::new_v4());
         let ctx = TrackRequestProcessingContext::new(
             track_metadata.clone(),
@@ -453,10 +1056,13 @@ impl TrackRequestProcessor {
     }
 
     #[tracing::instrument(skip(self))]
+    /// Processes the request step by step until it's finished. Once cancelled,
+    /// it stops in between the steps, with the state of the last one saved.
     pub(crate) async fn process_request(
         &self,
         user_id: &UserId,
         request_id: &RequestId,
+        cancellation: &CancellationToken,
     ) -> Result<(), ProcessRequestError> {
         debug!("Starting processing the track request {}", request_id);
 
@@ -471,14 +1077,42 @@ impl TrackRequestProcessor {
             )
             .await?;
 
-        // TODO Check if the file already exists in library.
-
         while !matches!(state.get_step(), TrackRequestProcessingStep::Finish) {
+            if cancellation.is_cancelled() {
+                info!("Track request {} processing is stopped", request_id);
+                return Ok(());
+            }
+
             if let Err(error) = self
                 .handle_next_step(user_id, request_id, &ctx, &mut state)
                 .await
             {
                 match error {
+                    ProcessRequestError::SearchProviderError(ref error) => {
+                        if let Some(retry_after) = error.retry_after() {
+                            info!(
+                                "Track request {} is waiting {:?} for the rate limit",
+                                request_id, retry_after
+                            );
+                            self.wait_for_rate_limit(
+                                user_id,
+                                request_id,
+                                &state,
+                                retry_after,
+                                cancellation,
+                            )
+                            .await?;
+                            continue;
+                        }
+
+                        self.state_storage
+                            .update_status(
+                                user_id,
+                                request_id,
+                                &TrackRequestProcessingStatus::Failed,
+                            )
+                            .await?;
+                    }
                     ProcessRequestError::TrackNotFound => {
                         self.state_storage
                             .update_status(
@@ -504,7 +1138,7 @@ impl TrackRequestProcessor {
             self.state_storage
                 .update_state(user_id, request_id, &state)
                 .await?;
-            actix_rt::time::sleep(Duration::from_secs(1)).await;
+            sleep_unless_cancelled(Duration::from_secs(1), cancellation).await;
         }
 
         info!("Track request {} processing finished", request_id);
@@ -520,6 +1154,39 @@ impl TrackRequestProcessor {
         Ok(())
     }
 
+    /// Keeps the step to retry it once the wait is over.
+    async fn wait_for_rate_limit(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        state: &TrackRequestProcessingState,
+        retry_after: Duration,
+        cancellation: &CancellationToken,
+    ) -> Result<(), ProcessRequestError> {
+        self.state_storage
+            .update_state(user_id, request_id, state)
+            .await?;
+        self.state_storage
+            .update_status(
+                user_id,
+                request_id,
+                &TrackRequestProcessingStatus::WaitingForRateLimit,
+            )
+            .await?;
+
+        sleep_unless_cancelled(retry_after, cancellation).await;
+
+        self.state_storage
+            .update_status(
+                user_id,
+                request_id,
+                &TrackRequestProcessingStatus::Processing,
+            )
+            .await?;
+
+        Ok(())
+    }
+
     pub(crate) async fn get_processing_requests(
         &self,
         user_id: &UserId,
@@ -529,33 +1196,253 @@ impl TrackRequestProcessor {
         Ok(statuses)
     }
 
-    async fn handle_next_step(
+    /// Returns `None` if there is no request with the given id. Context and
+    /// state of the finished requests are gone, so only status is reported.
+    pub(crate) async fn get_request_details(
         &self,
         user_id: &UserId,
         request_id: &RequestId,
-        ctx: &TrackRequestProcessingContext,
-        state: &mut TrackRequestProcessingState,
-    ) -> Result<(), ProcessRequestError> {
-        let step = state.get_step();
+    ) -> Result<Option<TrackRequestDetails>, ProcessRequestError> {
+        let status = self
+            .state_storage
+            .get_all_statuses(user_id)
+            .await?
+            .remove(request_id);
+        let ctx = match self.state_storage.load_context(user_id, request_id).await {
+            Ok(ctx) => Some(ctx),
+            Err(error) if error.is_not_found() => None,
+            Err(error) => return Err(error.into()),
+        };
+        let state = match self.state_storage.load_state(user_id, request_id).await {
+            Ok(state) => Some(state),
+            Err(error) if error.is_not_found() => None,
+            Err(error) => return Err(error.into()),
+        };
 
-        debug!("Running processing step: {:?}", step);
+        if status.is_none() && ctx.is_none() {
+            return Ok(None);
+        }
 
-        match step {
-            TrackRequestProcessingStep::GetTopicsIntoQueue => {
-                self.get_topics_into_queue(user_id, request_id, ctx, state)
-                    .await?;
-            }
-            TrackRequestProcessingStep::DownloadNextTorrentFile => {
-                self.download_next_torrent_file(user_id, ctx, state).await?;
-            }
-            TrackRequestProcessingStep::Download => {
-                self.download(user_id, ctx, state).await?;
+        Ok(Some(TrackRequestDetails {
+            status,
+            metadata: ctx.map(|ctx| ctx.metadata),
+            step: state.as_ref().map(|state| state.get_step()),
+            topic: state.as_ref().and_then(|state| state.current_topic.clone()),
+            queue: state.as_ref().and_then(|state| state.topics_queue.clone()),
+            estimated_download_size: state.and_then(|state| state.estimated_download_size),
+        }))
+    }
+
+    /// Pins the topic and/or the file of its torrent onto the request, so it
+    /// goes on from the `Download` step. The request must not be processed
+    /// meanwhile, see `TrackRequestController::override_request`.
+    pub(crate) async fn override_request(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        request_override: RequestOverride,
+    ) -> Result<(), OverrideRequestError> {
+        let mut state = match self.state_storage.load_state(user_id, request_id).await {
+            Ok(state) => state,
+            Err(error) if error.is_not_found() => {
+                return Err(OverrideRequestError::RequestNotFound)
             }
-            TrackRequestProcessingStep::CheckDownloadStatus => {
+            Err(error) => return Err(ProcessRequestError::from(error).into()),
+        };
+
+        if let Some(pinned_topic) = request_override.topic {
+            let topic = TopicData {
+                topic_id: pinned_topic
+                    .topic_id
+                    .unwrap_or_else(|| TopicId(pinned_topic.download_id.0.clone())),
+                download_id: pinned_topic.download_id,
+                provider: pinned_topic.provider,
+                ..TopicData::default()
+            };
+
+            info!(
+                "Downloading torrent file {} pinned to the request...",
+                topic.download_id
+            );
+
+            match self
+                .search_provider
+                .download_torrent(&topic)
+                .await
+                .map_err(ProcessRequestError::from)?
+            {
+                TorrentSource::File(torrent_data) => {
+                    state.current_torrent_data.replace(torrent_data);
+                    state.current_magnet_link.take();
+                }
+                TorrentSource::Magnet(magnet_link) => {
+                    state.current_magnet_link.replace(magnet_link);
+                    state.current_torrent_data.take();
+                }
+            }
+
+            state.current_topic.replace(topic);
+            // The found topics are what the operator overrides, so there is
+            // nothing to fall back to.
+            state.topics_queue.replace(vec![]);
+        } else if state.current_torrent_data.is_none() && state.current_magnet_link.is_none() {
+            return Err(OverrideRequestError::NoTorrent);
+        }
+
+        // Files of magnet links are only known once the metadata is fetched.
+        if let (Some(index), Some(torrent_data)) =
+            (request_override.file_index, &state.current_torrent_data)
+        {
+            let torrent = parse_torrent(torrent_data).map_err(ProcessRequestError::from)?;
+
+            if index >= torrent.files.len() {
+                return Err(OverrideRequestError::InvalidFileIndex(index));
+            }
+        }
+
+        // Torrent of the previous choice is of no use anymore.
+        if let Some(torrent_id) = state.current_torrent_id.take() {
+            if let Err(error) = self.torrent_client.delete_torrent(&torrent_id).await {
+                warn!(?error, %torrent_id, "Unable to delete the overridden torrent");
+            }
+        }
+
+        state.pinned_file_index = request_override.file_index;
+        state.local_library_searched = true;
+        state.estimated_download_size.take();
+        state.awaiting_cue_sheets = false;
+        state.path_to_downloaded_file.take();
+        state.path_to_processed_file.take();
+        state.radio_manager_track_id.take();
+        state.radio_manager_link_id.take();
+
+        self.state_storage
+            .update_state(user_id, request_id, &state)
+            .await
+            .map_err(ProcessRequestError::from)?;
+        self.state_storage
+            .update_status(
+                user_id,
+                request_id,
+                &TrackRequestProcessingStatus::Processing,
+            )
+            .await
+            .map_err(ProcessRequestError::from)?;
+
+        Ok(())
+    }
+
+    /// Searches for the track the same way a request would, without
+    /// creating one. Torrents of the first `fetch_top` topics are fetched to
+    /// tell which files would be downloaded, counting against the tracker's
+    /// download quota.
+    pub(crate) async fn search(
+        &self,
+        metadata: &AudioMetadata,
+        channel_id: &RadioManagerChannelId,
+        fetch_top: usize,
+    ) -> Result<Vec<SearchResult>, ProcessRequestError> {
+        let topics = self.find_topics(metadata, channel_id).await?;
+        let mut results = vec![];
+
+        for (index, topic) in topics.into_iter().enumerate() {
+            let (has_requested_track, selected_files) = match index < fetch_top {
+                true => self.check_torrent(&topic, metadata).await,
+                false => (None, None),
+            };
+
+            results.push(SearchResult {
+                topic,
+                has_requested_track,
+                selected_files,
+            });
+        }
+
+        Ok(results)
+    }
+
+    async fn check_torrent(
+        &self,
+        topic: &TopicData,
+        metadata: &AudioMetadata,
+    ) -> (Option<bool>, Option<Vec<String>>) {
+        if !self.may_have_requested_track(topic, metadata).await {
+            return (Some(false), None);
+        }
+
+        let torrent = match self.search_provider.download_torrent(topic).await {
+            Ok(TorrentSource::File(torrent_data)) => parse_torrent(&torrent_data),
+            Ok(TorrentSource::Magnet(_)) => return (None, None),
+            Err(error) => {
+                warn!(
+                    ?error,
+                    "Unable to download torrent file {}", topic.download_id
+                );
+                return (None, None);
+            }
+        };
+        let torrent = match torrent {
+            Ok(torrent) => torrent,
+            Err(error) => {
+                warn!(?error, "Unable to parse torrent file {}", topic.download_id);
+                return (None, None);
+            }
+        };
+
+        if !self.has_requested_track(metadata, &torrent.files) {
+            return (Some(false), None);
+        }
+
+        let selected_files = self
+            .select_files(metadata, &torrent.files)
+            .into_iter()
+            .map(|index| torrent.files[index].path.clone())
+            .collect();
+
+        (Some(true), Some(selected_files))
+    }
+
+    async fn handle_next_step(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        ctx: &TrackRequestProcessingContext,
+        state: &mut TrackRequestProcessingState,
+    ) -> Result<(), ProcessRequestError> {
+        let step = state.get_step();
+
+        debug!("Running processing step: {:?}", step);
+
+        match step {
+            TrackRequestProcessingStep::SearchLocalLibrary => {
+                self.search_local_library(user_id, ctx, state).await?;
+            }
+            TrackRequestProcessingStep::GetTopicsIntoQueue => {
+                self.get_topics_into_queue(user_id, request_id, ctx, state)
+                    .await?;
+            }
+            TrackRequestProcessingStep::DownloadNextTorrentFile => {
+                self.download_next_torrent_file(user_id, ctx, state).await?;
+            }
+            TrackRequestProcessingStep::Download => {
+                self.download(user_id, ctx, state).await?;
+            }
+            TrackRequestProcessingStep::AwaitMetadata => {
+                self.await_metadata(user_id, ctx, state).await?;
+            }
+            TrackRequestProcessingStep::AwaitCueSheets => {
+                self.await_cue_sheets(user_id, ctx, state).await?;
+            }
+            TrackRequestProcessingStep::CheckDownloadStatus => {
                 self.check_download_status(user_id, ctx, state).await?;
             }
+            TrackRequestProcessingStep::ProcessAudioFile => {
+                self.process_audio_file(user_id, request_id, ctx, state)
+                    .await?;
+            }
             TrackRequestProcessingStep::UploadToRadioManager => {
-                self.upload_to_radio_manager(user_id, ctx, state).await?;
+                self.upload_to_radio_manager(user_id, request_id, ctx, state)
+                    .await?;
             }
             TrackRequestProcessingStep::AddToRadioManagerChannel => {
                 self.add_to_radio_manager_channel(user_id, ctx, state)
@@ -567,6 +1454,25 @@ impl TrackRequestProcessor {
         Ok(())
     }
 
+    async fn search_local_library(
+        &self,
+        _user_id: &UserId,
+        ctx: &TrackRequestProcessingContext,
+        state: &mut TrackRequestProcessingState,
+    ) -> Result<(), ProcessRequestError> {
+        // Torrents are only searched when the track isn't available locally.
+        if let Some(local_library) = &self.local_library {
+            if let Some(path) = local_library.find_track(&ctx.metadata).await? {
+                info!("Found the track in the local library: {}", path);
+                state.path_to_downloaded_file.replace(path);
+            }
+        }
+
+        state.local_library_searched = true;
+
+        Ok(())
+    }
+
     async fn get_topics_into_queue(
         &self,
         _user_id: &UserId,
@@ -574,32 +1480,74 @@ impl TrackRequestProcessor {
         ctx: &TrackRequestProcessingContext,
         state: &mut TrackRequestProcessingState,
     ) -> Result<(), ProcessRequestError> {
-        let queries = vec![
-            format!("{} - {}", ctx.metadata.artist, ctx.metadata.album),
-            format!("{} дискография", ctx.metadata.artist),
-            format!("{} discography", ctx.metadata.artist),
-            format!("{} дискографія", ctx.metadata.artist),
-        ];
+        let ranked_results = self
+            .find_topics(&ctx.metadata, &ctx.target_channel_id)
+            .await?;
 
-        let mut found_results = vec![];
+        // The queue is ordered best first.
+        state.topics_queue.replace(ranked_results);
 
-        for query in queries {
-            let mut results = self.search_provider.find_all(&query).await?;
+        Ok(())
+    }
+
+    /// Searches for the release by the planned queries, returning the
+    /// topics satisfying the ranking policy of the channel, best first.
+    async fn find_topics(
+        &self,
+        metadata: &AudioMetadata,
+        channel_id: &RadioManagerChannelId,
+    ) -> Result<Vec<TopicData>, ProcessRequestError> {
+        let policy = self.ranking_policies.for_channel(channel_id);
+        let mut found_results = vec![];
+        let mut ranked_results = vec![];
+        let mut last_error = None;
+        let mut succeeded = false;
+
+        for query in self.query_planner.plan(metadata) {
+            let results = match self.search_provider.find_all(&query).await {
+                Ok(results) => results,
+                // Searched again once the wait is over.
+                Err(error) if error.retry_after().is_some() => return Err(error.into()),
+                Err(error) => {
+                    warn!(%error, "Unable to search for \"{}\"", query);
+                    last_error = Some(error);
+                    continue;
+                }
+            };
+            succeeded = true;
 
             info!("Searching for \"{}\": {} result(s)", query, results.len());
 
-            found_results.append(&mut results);
-        }
+            found_results.extend(results.into_iter().map(|topic| TopicData {
+                query: Some(query.text.clone()),
+                ..topic
+            }));
+            ranked_results = rank_topics(found_results.clone(), metadata, policy);
 
-        found_results.dedup_by_key(|topic| *topic.topic_id);
+            if ranked_results
+                .iter()
+                .any(|topic| self.query_planner.is_good_candidate(topic, metadata))
+            {
+                info!(
+                    "Found the release by \"{}\", skipping the other queries",
+                    query
+                );
+                break;
+            }
+        }
 
-        found_results.reverse();
+        if let (false, Some(error)) = (succeeded, last_error) {
+            return Err(error.into());
+        }
 
-        info!("Found {} unique result(s)", found_results.len());
+        info!("Found {} result(s)", found_results.len());
 
-        state.topics_queue.replace(found_results);
+        info!(
+            "{} unique result(s) satisfy the ranking policy",
+            ranked_results.len()
+        );
 
-        Ok(())
+        Ok(ranked_results)
     }
 
     async fn download_next_torrent_file(
@@ -608,69 +1556,331 @@ impl TrackRequestProcessor {
         ctx: &TrackRequestProcessingContext,
         state: &mut TrackRequestProcessingState,
     ) -> Result<(), ProcessRequestError> {
-        let topic = match state.topics_queue.as_mut().and_then(Vec::pop) {
-            Some(topic) => topic,
-            None => {
+        let topic = match state.topics_queue.as_mut() {
+            Some(queue) if !queue.is_empty() => queue.remove(0),
+            _ => {
                 return Err(ProcessRequestError::TrackNotFound);
             }
         };
 
+        if !self.may_have_requested_track(&topic, &ctx.metadata).await {
+            return Ok(());
+        }
+
         info!(
             "Downloading torrent file {} ({})...",
             topic.download_id, topic.title
         );
 
-        let torrent_data = self
-            .search_provider
-            .download_torrent(&topic.download_id)
-            .await?;
-        let files_in_torrent = get_files(&torrent_data)?;
-
-        if files_in_torrent
-            .into_iter()
-            .any(|filepath| contains_in_filename_ignore_case(&filepath, &ctx.metadata.title))
-        {
-            info!("Downloaded torrent file seems to have the requested track...");
+        let torrent_source = match self.search_provider.download_torrent(&topic).await {
+            Ok(torrent_source) => torrent_source,
+            Err(error) => {
+                // The topic is tried again once the rate limit is over.
+                if error.retry_after().is_some() {
+                    if let Some(queue) = state.topics_queue.as_mut() {
+                        queue.insert(0, topic);
+                    }
+                }
+                return Err(error.into());
+            }
+        };
+        let torrent_data = match torrent_source {
+            TorrentSource::File(torrent_data) => torrent_data,
+            TorrentSource::Magnet(magnet_link) => {
+                // Files of the magnet link are only known once the torrent
+                // client fetches its metadata.
+                info!("Topic resolved to a magnet link: {}", magnet_link);
+                state.current_magnet_link.replace(magnet_link);
+                state.current_topic.replace(topic);
+                return Ok(());
+            }
+        };
+        let torrent = parse_torrent(&torrent_data)?;
+
+        if self.has_requested_track(&ctx.metadata, &torrent.files) {
+            // Kept in the topic of the request details too.
+            info!(
+                "Topic {} was found by \"{}\"",
+                topic.topic_id,
+                topic.query.as_deref().unwrap_or_default()
+            );
             state.current_torrent_data.replace(torrent_data);
+            state.current_topic.replace(topic);
         }
 
         Ok(())
     }
 
+    /// Checks the tracklist of the topic, if the provider has it. Topic pages
+    /// are cheaper to fetch than torrent files, and the tracker limits how
+    /// many torrent files can be downloaded a day.
+    async fn may_have_requested_track(&self, topic: &TopicData, metadata: &AudioMetadata) -> bool {
+        match self.search_provider.get_tracklist(topic).await {
+            Ok(Some(tracklist))
+                if !tracklist.is_empty()
+                    && !tracklist
+                        .iter()
+                        .any(|track| contains_ignore_case(track, &metadata.title)) =>
+            {
+                info!(
+                    "Topic {} ({}) doesn't list the requested track",
+                    topic.topic_id, topic.title
+                );
+                false
+            }
+            Ok(_) => true,
+            Err(error) => {
+                warn!(?error, "Unable to get the tracklist of the topic");
+                true
+            }
+        }
+    }
+
+    fn has_requested_track(&self, metadata: &AudioMetadata, files: &[TorrentFile]) -> bool {
+        let paths: Vec<_> = files.iter().map(|f| f.path.clone()).collect();
+
+        if select_track_file(files, &metadata.title, &self.file_selection).is_some() {
+            info!("Torrent seems to have the requested track...");
+            return true;
+        }
+
+        if is_cue_image_release(&paths) {
+            if files
+                .iter()
+                .any(|f| is_cue_image(&f.path) && self.file_selection.fits(f))
+            {
+                info!("Torrent seems to be a single image rip with a cue sheet...");
+                return true;
+            }
+        } else if !paths
+            .iter()
+            .any(|path| contains_in_filename_ignore_case(path, &metadata.title))
+        {
+            return false;
+        }
+
+        info!(
+            max_file_size = self.file_selection.max_file_size,
+            "Requested track in the torrent exceeds the size limit"
+        );
+
+        false
+    }
+
+    /// File pinned by an operator takes precedence over the matching ones.
+    fn select_request_files(
+        &self,
+        metadata: &AudioMetadata,
+        pinned_file_index: Option<usize>,
+        files: &[TorrentFile],
+    ) -> Vec<usize> {
+        match pinned_file_index {
+            Some(index) if index < files.len() => vec![index],
+            Some(index) => {
+                warn!(index, "Pinned file is missing in the torrent");
+                self.select_files(metadata, files)
+            }
+            None => self.select_files(metadata, files),
+        }
+    }
+
+    /// Returns indexes of the files to download: the requested track along
+    /// with its cover art, or only the cue sheets if there is no such track.
+    /// Audio image is picked once the cue sheets are downloaded.
+    fn select_files(&self, metadata: &AudioMetadata, files: &[TorrentFile]) -> Vec<usize> {
+        match select_track_file(files, &metadata.title, &self.file_selection) {
+            Some(index) => {
+                debug!("Selected file to download: {}", files[index].path);
+                select_cover_art(files, vec![index])
+            }
+            None => {
+                debug!("Selecting cue sheets to download...");
+                select_cue_sheets(files)
+            }
+        }
+    }
+
     async fn download(
         &self,
         _user_id: &UserId,
         ctx: &TrackRequestProcessingContext,
         state: &mut TrackRequestProcessingState,
     ) -> Result<(), ProcessRequestError> {
+        if let Some(magnet_link) = &state.current_magnet_link {
+            debug!("Adding magnet link to the torrent client...");
+            let torrent_id = self.torrent_client.add_magnet(magnet_link).await?;
+            // Files are downloaded until the unneeded ones are deselected,
+            // which takes as long as fetching the metadata.
+            self.torrent_client.start_torrent(&torrent_id).await?;
+
+            info!(%torrent_id, "Fetching the torrent metadata...");
+
+            state.current_torrent_id.replace(torrent_id);
+
+            return Ok(());
+        }
+
         let torrent_data = state
             .current_torrent_data
             .clone()
             .take()
             .expect("current_torrent_data should be defined");
 
-        let files_in_torrent = get_files(&torrent_data)?;
-        let selected_files: Vec<_> = files_in_torrent
-            .into_iter()
-            .enumerate()
-            .filter_map(|(index, filepath)| {
-                if contains_in_filename_ignore_case(&filepath, &ctx.metadata.title) {
-                    Some(index as i32)
-                } else {
-                    None
-                }
-            })
-            .collect();
+        let torrent = parse_torrent(&torrent_data)?;
+        let selected_files =
+            self.select_request_files(&ctx.metadata, state.pinned_file_index, &torrent.files);
+        let download_size = get_total_length(&torrent.files, &selected_files);
+        debug!(
+            download_size,
+            total_size = torrent.total_size(),
+            "Estimated download size"
+        );
 
         debug!("Adding torrent to the torrent client...");
         let torrent_id = self
             .torrent_client
-            .add_torrent(torrent_data, selected_files)
+            .add_torrent(
+                torrent_data,
+                selected_files.iter().map(|index| *index as i32).collect(),
+            )
             .await?;
 
         info!(%torrent_id, "Started downloading the torrent contents...");
 
         state.current_torrent_id.replace(torrent_id);
+        state.awaiting_cue_sheets = is_cue_sheets_only(&torrent.files, &selected_files);
+        state.estimated_download_size.replace(download_size);
+
+        Ok(())
+    }
+
+    async fn await_metadata(
+        &self,
+        _user_id: &UserId,
+        ctx: &TrackRequestProcessingContext,
+        state: &mut TrackRequestProcessingState,
+    ) -> Result<(), ProcessRequestError> {
+        let torrent_id = state
+            .current_torrent_id
+            .clone()
+            .expect("current_torrent_id should be defined");
+
+        debug!("Checking whether the torrent metadata is fetched...");
+
+        let files = match self.torrent_client.get_files(&torrent_id).await? {
+            Some(files) => files,
+            None => {
+                // Still fetching? Check again in 5 secs...
+                actix_rt::time::sleep(Duration::from_secs(5)).await;
+
+                return Ok(());
+            }
+        };
+
+        if state.pinned_file_index.is_none() && !self.has_requested_track(&ctx.metadata, &files) {
+            warn!("Torrent of the magnet link does not have the requested audio track");
+
+            self.torrent_client.delete_torrent(&torrent_id).await?;
+
+            state.current_torrent_id.take();
+            state.current_magnet_link.take();
+            state.current_topic.take();
+
+            return Ok(());
+        }
+
+        let selected_files =
+            self.select_request_files(&ctx.metadata, state.pinned_file_index, &files);
+        let download_size = get_total_length(&files, &selected_files);
+        debug!(download_size, "Estimated download size");
+
+        self.torrent_client
+            .select_files(
+                &torrent_id,
+                selected_files.iter().map(|index| *index as i32).collect(),
+            )
+            .await?;
+
+        info!(%torrent_id, "Started downloading the torrent contents...");
+
+        state.awaiting_cue_sheets = is_cue_sheets_only(&files, &selected_files);
+        state.estimated_download_size.replace(download_size);
+
+        Ok(())
+    }
+
+    /// Picks the audio image whose cue sheet lists the requested track once
+    /// the cue sheets are downloaded. Torrent is rejected if there is none.
+    async fn await_cue_sheets(
+        &self,
+        _user_id: &UserId,
+        ctx: &TrackRequestProcessingContext,
+        state: &mut TrackRequestProcessingState,
+    ) -> Result<(), ProcessRequestError> {
+        let torrent_id = state
+            .current_torrent_id
+            .clone()
+            .expect("current_torrent_id should be defined");
+
+        debug!("Checking whether the cue sheets are downloaded...");
+
+        let torrent = self.torrent_client.get_torrent(&torrent_id).await?;
+
+        if !matches!(torrent.status, TorrentStatus::Complete) {
+            // Still downloading? Check again in 5 secs...
+            actix_rt::time::sleep(Duration::from_secs(5)).await;
+
+            return Ok(());
+        }
+
+        let files = self
+            .torrent_client
+            .get_files(&torrent_id)
+            .await?
+            .unwrap_or_default();
+        let paths = files.iter().map(|f| f.path.clone()).collect::<Vec<_>>();
+        let image_index = self
+            .find_cue_track(&ctx.metadata, &paths)
+            .await?
+            .and_then(|cue_track| paths.iter().position(|p| *p == cue_track.image_path));
+
+        match image_index {
+            Some(index) if self.file_selection.fits(&files[index]) => {
+                let mut selected_files = select_cue_sheets(&files);
+                selected_files.push(index);
+                let selected_files = select_cover_art(&files, selected_files);
+                let download_size = get_total_length(&files, &selected_files);
+                debug!(download_size, "Estimated download size");
+
+                self.torrent_client
+                    .select_files(
+                        &torrent_id,
+                        selected_files.iter().map(|index| *index as i32).collect(),
+                    )
+                    .await?;
+
+                info!(%torrent_id, "Started downloading the audio image: {}", paths[index]);
+
+                state.awaiting_cue_sheets = false;
+                state.estimated_download_size.replace(download_size);
+
+                return Ok(());
+            }
+            Some(_) => info!(
+                max_file_size = self.file_selection.max_file_size,
+                "Audio image with the requested track exceeds the size limit"
+            ),
+            None => warn!("Cue sheets of the torrent do not list the requested audio track"),
+        }
+
+        self.torrent_client.delete_torrent(&torrent_id).await?;
+
+        state.current_torrent_id.take();
+        state.current_torrent_data.take();
+        state.current_magnet_link.take();
+        state.current_topic.take();
+        state.estimated_download_size.take();
+        state.awaiting_cue_sheets = false;
 
         Ok(())
     }
@@ -700,46 +1910,205 @@ impl TrackRequestProcessor {
 
         debug!(%torrent_id, "Download complete");
 
-        for filepath in torrent.files {
-            if contains_in_filename_ignore_case(&filepath, &ctx.metadata.title) {
+        // Pinned file is taken whatever its name is. It's looked up in all
+        // files of the torrent, as the same torrent may have other files
+        // wanted by another request.
+        if let Some(index) = state.pinned_file_index {
+            let files = self
+                .torrent_client
+                .get_files(&torrent_id)
+                .await?
+                .unwrap_or_default();
+
+            match files.into_iter().nth(index) {
+                Some(file) => {
+                    info!("Using the pinned file: {}", file.path);
+                    state.path_to_downloaded_file.replace(file.path);
+                    return Ok(());
+                }
+                None => warn!(index, "Pinned file is missing in the torrent"),
+            }
+        }
+
+        for filepath in &torrent.files {
+            if contains_in_filename_ignore_case(filepath, &ctx.metadata.title) {
                 info!("Found matching file: {}", filepath);
-                state.path_to_downloaded_file.replace(filepath);
+                state.path_to_downloaded_file.replace(filepath.clone());
                 return Ok(());
             }
         }
 
+        if let Some(filepath) = self
+            .extract_track_from_cue_image(ctx, &torrent.files)
+            .await?
+        {
+            info!("Extracted matching track from audio image: {}", filepath);
+            state.path_to_downloaded_file.replace(filepath);
+            return Ok(());
+        }
+
         warn!("Downloaded torrent does not have the requested audio track");
 
         state.current_torrent_id.take();
         state.current_torrent_data.take();
+        state.current_magnet_link.take();
+        state.current_topic.take();
+        state.estimated_download_size.take();
 
         Ok(())
     }
 
-    async fn upload_to_radio_manager(
+    /// Looks up the requested track in the downloaded cue sheets. The first
+    /// cue sheet listing the track along with an existing audio image wins.
+    async fn find_cue_track(
         &self,
-        user_id: &UserId,
-        _ctx: &TrackRequestProcessingContext,
+        metadata: &AudioMetadata,
+        files: &[String],
+    ) -> Result<Option<CueImageTrack>, ProcessRequestError> {
+        for cue_path in files.iter().filter(|f| is_cue_sheet(f)) {
+            let contents =
+                tokio::fs::read(format!("{}/{}", self.download_directory, cue_path)).await?;
+            let cue_sheet = parse_cue_sheet(&decode_cue_sheet(&contents))?;
+
+            let track = match cue_sheet
+                .tracks
+                .into_iter()
+                .find(|track| contains_ignore_case(&track.title, &metadata.title))
+            {
+                Some(track) => track,
+                None => continue,
+            };
+
+            let cue_dir = Path::new(cue_path).parent().unwrap_or(Path::new(""));
+            match find_cue_image(files, cue_dir, &track.file) {
+                Some(image_path) => {
+                    return Ok(Some(CueImageTrack {
+                        cue_dir: cue_dir.to_path_buf(),
+                        image_path,
+                        track,
+                    }))
+                }
+                None => warn!(cue_path, "Audio image referenced by cue sheet is missing"),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Splits the requested track out of the audio image its cue sheet
+    /// refers to into a standalone file next to the cue sheet. Returns the
+    /// path to that file within the download directory.
+    async fn extract_track_from_cue_image(
+        &self,
+        ctx: &TrackRequestProcessingContext,
+        files: &[String],
+    ) -> Result<Option<String>, ProcessRequestError> {
+        let CueImageTrack {
+            cue_dir,
+            image_path,
+            track,
+        } = match self.find_cue_track(&ctx.metadata, files).await? {
+            Some(cue_track) => cue_track,
+            None => return Ok(None),
+        };
+        let output_path = cue_dir
+            .join(format!(
+                "{:02}. {}.flac",
+                track.number,
+                track.title.replace(std::path::MAIN_SEPARATOR, "_")
+            ))
+            .to_string_lossy()
+            .to_string();
+
+        info!(
+            image_path,
+            "Splitting track {} out of the audio image...", track.number
+        );
+
+        self.audio_splitter
+            .split_track(
+                &format!("{}/{}", self.download_directory, image_path),
+                track.start,
+                track.end,
+                &format!("{}/{}", self.download_directory, output_path),
+            )
+            .await?;
+
+        Ok(Some(output_path))
+    }
+
+    async fn process_audio_file(
+        &self,
+        _user_id: &UserId,
+        request_id: &RequestId,
+        ctx: &TrackRequestProcessingContext,
         state: &mut TrackRequestProcessingState,
     ) -> Result<(), ProcessRequestError> {
         let path = state
             .path_to_downloaded_file
             .clone()
-            .take()
             .expect("path_to_downloaded_file should be defined");
 
-        let full_path_to_file = format!("{}/{}", self.download_directory, path);
+        // Local library files are given by absolute path.
+        let full_path_to_file = Path::new(&self.download_directory)
+            .join(&path)
+            .to_string_lossy()
+            .to_string();
+
+        let tags = AudioTags {
+            metadata: ctx.metadata.clone(),
+            track_number: parse_track_number(&path),
+            year: state
+                .current_topic
+                .as_ref()
+                .and_then(|topic| parse_release_year(&topic.title)),
+            path_to_cover_art: find_cover_art(&full_path_to_file).await,
+        };
 
-        info!(
+        debug!(
             full_path_to_file,
-            "Uploading audio track to radio manager..."
+            ?tags,
+            "Processing downloaded audio track..."
         );
 
+        let path_to_processed_file = self
+            .audio_processor
+            .process_audio_file(request_id, &full_path_to_file, &tags)
+            .await?;
+
+        state.path_to_processed_file.replace(path_to_processed_file);
+
+        Ok(())
+    }
+
+    async fn upload_to_radio_manager(
+        &self,
+        user_id: &UserId,
+        request_id: &RequestId,
+        _ctx: &TrackRequestProcessingContext,
+        state: &mut TrackRequestProcessingState,
+    ) -> Result<(), ProcessRequestError> {
+        let path_to_file = state
+            .path_to_processed_file
+            .clone()
+            .take()
+            .expect("path_to_processed_file should be defined");
+
+        info!(path_to_file, "Uploading audio track to radio manager...");
+
         let track_id = self
             .radio_manager_client
-            .upload_audio_track(user_id, &full_path_to_file)
+            .upload_audio_track(user_id, &path_to_file)
             .await?;
 
+        if let Err(error) = self
+            .audio_processor
+            .remove_processed_files(request_id)
+            .await
+        {
+            warn!(?error, "Unable to remove processed audio files");
+        }
+
         state.radio_manager_track_id.replace(track_id);
 
         Ok(())
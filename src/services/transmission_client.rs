@@ -1,6 +1,7 @@
 use crate::services::torrent_parser::{get_files_count, TorrentParserError};
 use async_lock::Mutex;
 use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::Proxy;
 use transmission_rpc::types::{
     BasicAuth, Id, RpcResponse, Torrent, TorrentAction, TorrentAddArgs, TorrentAddedOrDuplicate,
     TorrentSetArgs,
@@ -32,14 +33,23 @@ impl TransmissionClient {
         username: Option<String>,
         password: Option<String>,
         download_dir: String,
+        proxy: Option<Proxy>,
     ) -> Self {
         let url = (&url).parse().unwrap();
-        let client = match (username, password) {
-            (Some(user), Some(password)) => {
-                TransClient::with_auth(url, BasicAuth { user, password })
-            }
-            _ => TransClient::new(url),
-        };
+        let mut builder = reqwest::Client::builder();
+
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(proxy);
+        }
+
+        let mut client = TransClient::new_with_client(
+            url,
+            builder.build().expect("Failed to create HTTP Client"),
+        );
+
+        if let (Some(user), Some(password)) = (username, password) {
+            client.set_auth(BasicAuth { user, password });
+        }
 
         Self {
             client: Mutex::new(client),
@@ -58,19 +68,72 @@ impl TransmissionClient {
                     files_wanted: Some(file_indexes.iter().map(|i| *i).collect()),
                     ..TorrentSetArgs::default()
                 },
-                Some(vec![id.clone()]),
+                Some(vec![id]),
             )
             .await?;
 
+        self.start(torrent_id).await
+    }
+
+    pub(crate) async fn start(&self, torrent_id: &i64) -> Result<()> {
+        self.client
+            .lock()
+            .await
+            .torrent_action(TorrentAction::Start, vec![Id::Id(*torrent_id)])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Deselects the files which are not going to be downloaded. Files of the
+    /// magnet links are all selected once the metadata is fetched.
+    pub(crate) async fn deselect_files(
+        &self,
+        torrent_id: &i64,
+        file_indexes: &[i32],
+    ) -> Result<()> {
         self.client
             .lock()
             .await
-            .torrent_action(TorrentAction::Start, vec![id])
+            .torrent_set(
+                TorrentSetArgs {
+                    files_unwanted: Some(file_indexes.to_vec()),
+                    ..TorrentSetArgs::default()
+                },
+                Some(vec![Id::Id(*torrent_id)]),
+            )
             .await?;
 
         Ok(())
     }
 
+    /// Adds the magnet link paused. Its metadata is only fetched once the
+    /// torrent is started.
+    pub(crate) async fn add_magnet(&self, magnet_link: &str) -> Result<i64> {
+        let RpcResponse { arguments, result } = self
+            .client
+            .lock()
+            .await
+            .torrent_add(TorrentAddArgs {
+                filename: Some(magnet_link.to_string()),
+                download_dir: Some(self.download_dir.clone()),
+                paused: Some(true),
+                ..TorrentAddArgs::default()
+            })
+            .await?;
+
+        if result != "success" {
+            return Err(TransmissionClientError::ErroneousResult(result));
+        }
+
+        let torrent = match arguments {
+            TorrentAddedOrDuplicate::TorrentAdded(torrent) => torrent,
+            TorrentAddedOrDuplicate::TorrentDuplicate(torrent) => torrent,
+        };
+
+        Ok(torrent.id.unwrap())
+    }
+
     pub(crate) async fn add(&self, torrent_file_content: Vec<u8>) -> Result<i64> {
         let files_count = get_files_count(&torrent_file_content)?;
         let metainfo = STANDARD.encode(torrent_file_content);
@@ -8,3 +8,73 @@ pub(crate) fn contains_in_filename_ignore_case(filepath: &str, needle: &str) ->
         None => false,
     }
 }
+
+pub(crate) fn has_extension_ignore_case(filepath: &str, extension: &str) -> bool {
+    match std::path::Path::new(filepath).extension() {
+        Some(ext) => ext.to_string_lossy().eq_ignore_ascii_case(extension),
+        None => false,
+    }
+}
+
+/// Extracts the track number from file names like "01. Title.flac" or
+/// "01 - Title.mp3".
+pub(crate) fn parse_track_number(filepath: &str) -> Option<u32> {
+    let filename = filepath.split(std::path::MAIN_SEPARATOR_STR).last()?;
+    let digits = filename
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect::<String>();
+
+    match filename[digits.len()..].chars().next() {
+        Some('.' | ' ' | '-' | '_') if digits.len() <= 3 => digits.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Finds the release year in titles like "Artist - Album - 1996, FLAC".
+pub(crate) fn parse_release_year(title: &str) -> Option<u32> {
+    title
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|part| part.len() == 4)
+        .filter_map(|part| part.parse::<u32>().ok())
+        .find(|year| (1900..2100).contains(year))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_track_number() {
+        assert_eq!(
+            Some(1),
+            parse_track_number("01. Ted Irens - Sunday Breakfast.flac")
+        );
+        assert_eq!(
+            Some(2),
+            parse_track_number("path/to/02 - Rain In The Forest.mp3")
+        );
+        assert_eq!(Some(12), parse_track_number("12_Rider.flac"));
+        assert_eq!(
+            None,
+            parse_track_number("Ted Irens - Sunday Breakfast.flac")
+        );
+        assert_eq!(None, parse_track_number("2012. Year In Review.flac"));
+    }
+
+    #[test]
+    fn test_parsing_release_year() {
+        assert_eq!(
+            Some(1996),
+            parse_release_year("(Trance) Robert Miles - Dreamland - 1996 (Urban, 533 002-2), FLAC")
+        );
+        assert_eq!(
+            Some(2016),
+            parse_release_year("[24/96] Robert Miles - Dreamland - 2016 (1996), FLAC")
+        );
+        assert_eq!(
+            None,
+            parse_release_year("Robert Miles - Dreamland, MP3, 320 kbps")
+        );
+    }
+}
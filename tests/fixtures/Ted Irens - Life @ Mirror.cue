@@ -0,0 +1,18 @@
+REM GENRE "Chillout"
+REM DATE 2012
+PERFORMER "Ted Irens"
+TITLE "Life @ Mirror"
+FILE "Ted Irens - Life @ Mirror.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "Sunday Breakfast"
+    PERFORMER "Ted Irens"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Rain In The Forest"
+    PERFORMER "Ted Irens"
+    INDEX 00 05:10:50
+    INDEX 01 05:12:30
+  TRACK 03 AUDIO
+    TITLE "Another Moon Night"
+    PERFORMER "Ted Irens"
+    INDEX 01 10:24:10